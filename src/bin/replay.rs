@@ -0,0 +1,63 @@
+use mercury_land::{database, error::ServerError, youtube::replay};
+use std::path::{Path, PathBuf};
+use tempfile::NamedTempFile;
+
+const USAGE: &str = "usage: replay <recording.jsonl> [scratch.db]";
+
+/**
+ * replay a recorded live chat and print how the balances changed. Refuses
+ * the live database.
+ */
+#[tokio::main]
+async fn main() -> Result<(), ServerError> {
+    env_logger::init();
+
+    let mut args = std::env::args().skip(1);
+    let Some(recording) = args.next() else {
+        eprintln!("{}", USAGE);
+        std::process::exit(2);
+    };
+
+    // 未指定資料庫時使用暫存檔，結束後自動刪除
+    let scratch = NamedTempFile::new()?;
+    let database = args
+        .next()
+        .map(PathBuf::from)
+        .unwrap_or_else(|| scratch.path().to_path_buf());
+    if is_live(&database) {
+        eprintln!("refuse to replay into the live database");
+        std::process::exit(2);
+    }
+    database::init_at(&database)?;
+
+    let changes = replay::run(&recording).await?;
+
+    println!("======== balance diff ========");
+    for change in &changes {
+        println!(
+            "{:+8} {:>8} -> {:<8} {} ({})",
+            change.delta(),
+            change.before,
+            change.after,
+            change.display,
+            change.youtube
+        );
+    }
+    println!(
+        "{} users changed, {:+} coins in total",
+        changes.len(),
+        changes.iter().map(|change| change.delta()).sum::<i64>()
+    );
+
+    Ok(())
+}
+
+fn is_live(path: &Path) -> bool {
+    match (
+        path.canonicalize(),
+        Path::new("data/sqlite.db").canonicalize(),
+    ) {
+        (Ok(path), Ok(live)) => path == live,
+        _ => false,
+    }
+}
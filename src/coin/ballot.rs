@@ -0,0 +1,472 @@
+use super::moderation;
+use crate::{
+    database::{
+        ballot::{Ballot, BallotMode, BallotOption, BallotStatus, Stake},
+        ledger::{Ledger, Reason},
+        user::User,
+    },
+    error::ServerError,
+};
+use chrono::{DateTime, Utc};
+use rusqlite::Transaction;
+use std::collections::HashMap;
+
+pub enum Outcome {
+    Staked(Stake),
+    NotOpen,
+    InvalidAmount,
+    InsufficientFunds,
+    Frozen,
+}
+
+/**
+ * spend `amount` coins of `voter` on `option`; they come back only if the
+ * ballot refunds losers.
+ */
+pub fn stake(
+    ballot: &Ballot,
+    option: &BallotOption,
+    voter: &mut User,
+    amount: i64,
+    actor: &str,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<Outcome, ServerError> {
+    if ballot.status != BallotStatus::Open
+        || ballot.mode != BallotMode::Coin
+        || option.ballot != ballot.id
+    {
+        return Ok(Outcome::NotOpen);
+    }
+    if amount <= 0 {
+        return Ok(Outcome::InvalidAmount);
+    }
+    if !moderation::can_spend(&voter.youtube, transaction)? {
+        return Ok(Outcome::Frozen);
+    }
+    if voter.coin < amount {
+        return Ok(Outcome::InsufficientFunds);
+    }
+
+    let mut stake = Stake {
+        id: 0,
+        ballot: ballot.id,
+        option: option.id,
+        user: voter.id,
+        amount,
+        created_at: now,
+    };
+    stake.insert(transaction)?;
+
+    voter.updated_at = now;
+    voter.transact(-amount, Reason::Vote, actor, None, transaction)?;
+
+    Ok(Outcome::Staked(stake))
+}
+
+pub fn totals(stakes: &[Stake]) -> HashMap<i64, i64> {
+    let mut totals = HashMap::new();
+    for stake in stakes {
+        *totals.entry(stake.option).or_default() += stake.amount;
+    }
+    totals
+}
+
+/**
+ * give back the stakes on options not in `keep` if the ballot refunds
+ * losers, and for a runoff the losing stakes of the ballots it decides. Each
+ * stake is refunded once.
+ */
+pub fn refund_losers(
+    ballot: &Ballot,
+    keep: &[i64],
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<Vec<Ledger>, ServerError> {
+    if ballot.mode != BallotMode::Coin || !ballot.refund {
+        return Ok(Vec::new());
+    }
+
+    let mut refunds = Vec::new();
+    let mut ballot = ballot.clone();
+    let mut keep = keep.to_vec();
+    loop {
+        for stake in Stake::by_ballot(ballot.id, transaction)? {
+            if keep.contains(&stake.option) {
+                continue;
+            }
+            let Some(mut user) = User::by_id(stake.user, transaction)? else {
+                continue;
+            };
+
+            user.updated_at = now;
+            if let Some(entry) = user.transact_once(
+                stake.amount,
+                Reason::Refund,
+                "system",
+                &format!("stake:{}", stake.id),
+                transaction,
+            )? {
+                refunds.push(entry);
+            }
+        }
+
+        let Some(parent) = ballot.runoff_of else {
+            break;
+        };
+        let Some(parent) = Ballot::by_id(parent, transaction)? else {
+            break;
+        };
+        // 加賽的選項沿用原投票的編號，原投票中留下的是同編號的選項
+        let kept: Vec<u32> = BallotOption::by_ballot(ballot.id, transaction)?
+            .into_iter()
+            .filter(|option| keep.contains(&option.id))
+            .map(|option| option.flag)
+            .collect();
+        keep = BallotOption::by_ballot(parent.id, transaction)?
+            .into_iter()
+            .filter(|option| kept.contains(&option.flag))
+            .map(|option| option.id)
+            .collect();
+        ballot = parent;
+    }
+
+    Ok(refunds)
+}
+
+/**
+ * cancel `ballot` and give back every stake on it and on the ballots it is a
+ * runoff of.
+ */
+pub fn cancel(
+    ballot: &mut Ballot,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<Vec<Ledger>, ServerError> {
+    ballot.status = BallotStatus::Cancelled;
+    ballot.concluded_at = Some(now);
+    ballot.update(transaction)?;
+    if ballot.mode != BallotMode::Coin {
+        return Ok(Vec::new());
+    }
+
+    let mut refunds = Vec::new();
+    let mut current = Some(ballot.clone());
+    while let Some(ballot) = current {
+        for stake in Stake::by_ballot(ballot.id, transaction)? {
+            let Some(mut user) = User::by_id(stake.user, transaction)? else {
+                continue;
+            };
+
+            user.updated_at = now;
+            if let Some(entry) = user.transact_once(
+                stake.amount,
+                Reason::Refund,
+                "system",
+                &format!("stake:{}", stake.id),
+                transaction,
+            )? {
+                refunds.push(entry);
+            }
+        }
+
+        current = match ballot.runoff_of {
+            Some(parent) => Ballot::by_id(parent, transaction)?,
+            None => None,
+        };
+    }
+
+    Ok(refunds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{self, ballot::BallotOption};
+    use rusqlite::Connection;
+
+    fn setup() -> Result<(Connection, Ballot, Vec<BallotOption>, User), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+
+        let mut ballot = Ballot {
+            id: 0,
+            name: String::from("vote"),
+            channel: 1,
+            message: None,
+            deadline: Some(Utc::now()),
+            status: BallotStatus::Open,
+            created_at: Utc::now(),
+            concluded_at: None,
+            winner: None,
+            runoff_of: None,
+            mode: BallotMode::Coin,
+            refund: true,
+            max_options: None,
+            max_nominations: None,
+            emojis: None,
+            pages: None,
+        };
+        ballot.insert(&tran)?;
+
+        let mut options = Vec::new();
+        for flag in 0..2 {
+            let mut option = BallotOption {
+                id: 0,
+                ballot: ballot.id,
+                flag,
+                description: format!("option {}", flag),
+                nominee: 42,
+                votes: None,
+                created_at: Utc::now(),
+            };
+            option.insert(&tran)?;
+            options.push(option);
+        }
+
+        let mut user = User {
+            id: 0,
+            youtube: String::from("viewer"),
+            discord: Some(42),
+            coin: 0,
+            display: String::from("Viewer"),
+            updated_at: Utc::now(),
+        };
+        user.insert(&tran)?;
+        user.transact(100, Reason::Give, "system", None, &tran)?;
+        tran.commit()?;
+
+        Ok((conn, ballot, options, user))
+    }
+
+    #[test]
+    fn stake_spends_coins() -> Result<(), ServerError> {
+        let (mut conn, mut ballot, options, mut user) = setup()?;
+        let tran = conn.transaction()?;
+
+        assert!(matches!(
+            stake(
+                &ballot,
+                &options[0],
+                &mut user,
+                0,
+                "discord:42",
+                Utc::now(),
+                &tran
+            )?,
+            Outcome::InvalidAmount
+        ));
+        assert!(matches!(
+            stake(
+                &ballot,
+                &options[0],
+                &mut user,
+                101,
+                "discord:42",
+                Utc::now(),
+                &tran
+            )?,
+            Outcome::InsufficientFunds
+        ));
+        assert!(matches!(
+            stake(
+                &ballot,
+                &options[0],
+                &mut user,
+                30,
+                "discord:42",
+                Utc::now(),
+                &tran
+            )?,
+            Outcome::Staked(_)
+        ));
+        assert!(matches!(
+            stake(
+                &ballot,
+                &options[1],
+                &mut user,
+                50,
+                "discord:42",
+                Utc::now(),
+                &tran
+            )?,
+            Outcome::Staked(_)
+        ));
+        assert_eq!(User::by_id(user.id, &tran)?.expect("user").coin, 20);
+
+        let totals = totals(&Stake::by_ballot(ballot.id, &tran)?);
+        assert_eq!(totals.get(&options[0].id), Some(&30));
+        assert_eq!(totals.get(&options[1].id), Some(&50));
+
+        ballot.mode = BallotMode::Reaction;
+        assert!(matches!(
+            stake(
+                &ballot,
+                &options[0],
+                &mut user,
+                10,
+                "discord:42",
+                Utc::now(),
+                &tran
+            )?,
+            Outcome::NotOpen
+        ));
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn refund_losers_once() -> Result<(), ServerError> {
+        let (mut conn, mut ballot, options, mut user) = setup()?;
+        let tran = conn.transaction()?;
+        stake(
+            &ballot,
+            &options[0],
+            &mut user,
+            30,
+            "discord:42",
+            Utc::now(),
+            &tran,
+        )?;
+        stake(
+            &ballot,
+            &options[1],
+            &mut user,
+            50,
+            "discord:42",
+            Utc::now(),
+            &tran,
+        )?;
+
+        let refunds = refund_losers(&ballot, &[options[1].id], Utc::now(), &tran)?;
+        assert_eq!(refunds.len(), 1);
+        assert_eq!(refunds[0].amount, 30);
+        assert!(refund_losers(&ballot, &[options[1].id], Utc::now(), &tran)?.is_empty());
+        assert_eq!(User::by_id(user.id, &tran)?.expect("user").coin, 50);
+
+        ballot.refund = false;
+        assert!(refund_losers(&ballot, &[], Utc::now(), &tran)?.is_empty());
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn runoff_refunds_tied_losers() -> Result<(), ServerError> {
+        let (mut conn, mut ballot, options, mut user) = setup()?;
+        let tran = conn.transaction()?;
+        for option in &options {
+            stake(
+                &ballot,
+                option,
+                &mut user,
+                30,
+                "discord:42",
+                Utc::now(),
+                &tran,
+            )?;
+        }
+
+        // both options tie and go to a runoff, nothing is refunded yet
+        let tied: Vec<i64> = options.iter().map(|option| option.id).collect();
+        assert!(refund_losers(&ballot, &tied, Utc::now(), &tran)?.is_empty());
+        ballot.status = BallotStatus::Concluded;
+        ballot.update(&tran)?;
+
+        let mut runoff = Ballot {
+            id: 0,
+            status: BallotStatus::Open,
+            runoff_of: Some(ballot.id),
+            ..ballot.clone()
+        };
+        runoff.insert(&tran)?;
+        let mut runoff_options = Vec::new();
+        for option in &options {
+            let mut option = BallotOption {
+                id: 0,
+                ballot: runoff.id,
+                ..option.clone()
+            };
+            option.insert(&tran)?;
+            runoff_options.push(option);
+        }
+        stake(
+            &runoff,
+            &runoff_options[1],
+            &mut user,
+            10,
+            "discord:42",
+            Utc::now(),
+            &tran,
+        )?;
+
+        // option 0 wins the runoff: the option 1 stakes of both ballots come back
+        let keep = [runoff_options[0].id];
+        let refunds = refund_losers(&runoff, &keep, Utc::now(), &tran)?;
+        let mut amounts: Vec<i64> = refunds.iter().map(|entry| entry.amount).collect();
+        amounts.sort();
+        assert_eq!(amounts, vec![10, 30]);
+        assert!(refund_losers(&runoff, &keep, Utc::now(), &tran)?.is_empty());
+        assert_eq!(User::by_id(user.id, &tran)?.expect("user").coin, 70);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn cancel_refunds_every_stake() -> Result<(), ServerError> {
+        let (mut conn, mut ballot, options, mut user) = setup()?;
+        let tran = conn.transaction()?;
+        ballot.refund = false;
+        ballot.update(&tran)?;
+        for option in &options {
+            stake(
+                &ballot,
+                option,
+                &mut user,
+                30,
+                "discord:42",
+                Utc::now(),
+                &tran,
+            )?;
+        }
+        ballot.status = BallotStatus::Concluded;
+        ballot.update(&tran)?;
+
+        let mut runoff = Ballot {
+            id: 0,
+            status: BallotStatus::Open,
+            runoff_of: Some(ballot.id),
+            ..ballot.clone()
+        };
+        runoff.insert(&tran)?;
+        let mut option = BallotOption {
+            id: 0,
+            ballot: runoff.id,
+            ..options[0].clone()
+        };
+        option.insert(&tran)?;
+        stake(
+            &runoff,
+            &option,
+            &mut user,
+            10,
+            "discord:42",
+            Utc::now(),
+            &tran,
+        )?;
+        assert_eq!(User::by_id(user.id, &tran)?.expect("user").coin, 30);
+
+        // closing the runoff gives back its stakes and those of the original
+        assert_eq!(cancel(&mut runoff, Utc::now(), &tran)?.len(), 3);
+        assert_eq!(runoff.status, BallotStatus::Cancelled);
+        assert_eq!(User::by_id(user.id, &tran)?.expect("user").coin, 100);
+        assert!(cancel(&mut runoff, Utc::now(), &tran)?.is_empty());
+        assert_eq!(User::by_id(user.id, &tran)?.expect("user").coin, 100);
+        tran.finish()?;
+
+        Ok(())
+    }
+}
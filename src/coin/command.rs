@@ -1,41 +1,86 @@
-use super::config::CoinConfig;
+use super::shop::{self, Outcome};
 pub use crate::database::user::User;
 use crate::{
-    database::{config::Config, get_connection},
+    database::{config::Config, get_connection, order::Order},
     discord,
     error::ServerError,
 };
+use async_trait::async_trait;
 use chrono::{DateTime, Utc};
 use serenity::all::CreateMessage;
 
+/**
+ * announcing a placed order, behind a trait so purchases can be tested.
+ */
+#[async_trait]
+pub trait OrderNotifier: Send + Sync {
+    /**
+     * post `text` to `channel` and return the id of the message.
+     */
+    async fn notify(&self, channel: u64, text: String) -> Result<u64, ServerError>;
+}
+
+struct DiscordNotifier;
+
+#[async_trait]
+impl OrderNotifier for DiscordNotifier {
+    async fn notify(&self, channel: u64, text: String) -> Result<u64, ServerError> {
+        let message = discord::Receiver::ChannelId(channel)
+            .message(CreateMessage::new().content(text))
+            .await?;
+        Ok(message.id.get())
+    }
+}
+
 pub struct CoinCommandManager {
-    pub(crate) config: CoinConfig,
+    notifier: Option<Box<dyn OrderNotifier>>,
 }
 
 impl CoinCommandManager {
     pub fn new() -> Self {
-        Self { config: CoinConfig }
+        Self::with_notifier(DiscordNotifier)
+    }
+
+    /**
+     * place orders without announcing them, for the chat replay
+     */
+    pub fn offline() -> Self {
+        Self { notifier: None }
+    }
+
+    pub fn with_notifier(notifier: impl OrderNotifier + 'static) -> Self {
+        Self {
+            notifier: Some(Box::new(notifier)),
+        }
     }
 
-    pub async fn buy_booster(
+    /**
+     * place an order from YouTube chat and return the reply for the buyer.
+     */
+    pub async fn purchase(
         &self,
         user: &String,
-        level: i64,
+        product: &str,
+        amount: f64,
         content: &String,
         now: DateTime<Utc>,
-    ) -> Result<(), ServerError> {
+    ) -> Result<String, ServerError> {
         // 1. 建立一個變數來存放 Discord 發送所需的資訊
-        let mut notification_payload: Option<(u64, String)> = None;
+        let mut notification_payload: Option<(u64, i64, String)> = None;
+        let reply;
 
         // 2. 使用一個獨立的程式碼塊 (Scope) 處理資料庫
         {
             let mut connection = get_connection()?;
             let transaction = connection.transaction()?;
 
-            let channel_coin = if let Some(text) = Config::ChannelCoin.get(&transaction)?
+            // 重播時不公告，不需要設定頻道
+            let channel_coin = if self.notifier.is_none() {
+                None
+            } else if let Some(text) = Config::ChannelCoin.get(&transaction)?
                 && let Ok(channel) = text.parse::<u64>()
             {
-                channel
+                Some(channel)
             } else {
                 return Err(ServerError::Internal(String::from(
                     "Parse ChannelCoin channel id to u64 failed.",
@@ -43,36 +88,34 @@ impl CoinCommandManager {
             };
 
             if let Some(mut record) = User::by_youtube(user, &transaction)? {
-                let cost = self.config.booster_cost(level);
-                if record.coin >= cost {
-                    record.coin -= cost;
-                    record.updated_at = now;
-                    record.update(&transaction)?;
-
-                    let due = now.checked_add_days(chrono::Days::new(3)).unwrap();
-
-                    println!("[-] {} buy a level-{} booster for {}", user, level, content);
-
-                    // 在這裡格式化字串，並暫存到 payload 變數中
-                    /*
-                    # 懲罰加倍 #
-                    > ## 「{}」 x **{}** 倍 ##
-                    - 來自： {} (`{}`)，結餘 **{}** 水星幣。
-                    _此為 YouTube 聊天室指令，須由管理員手動開啟退款單。_
-                    -# 如有疑義，或未抽中想領取半價退款，請在 72 小時內（<t:{}:f> 之前）向管理員申請退款。
-                     */
-                    let message_content = format!(
-                        "# 懲罰加倍 #\n> ## 「{}」 x **{}** 倍 ##\n- 來自： {} (`{}`)，結餘 **{}** 水星幣。\n_此為 YouTube 聊天室指令，須由管理員手動開啟退款單._\n-# 如有疑義，或未抽中想領取半價退款，請在 72 小時內（<t:{}:f> 之前）向管理員申請退款。",
-                        content,
-                        level,
-                        record.display,
-                        record.youtube,
-                        record.coin,
-                        due.timestamp()
-                    );
-
-                    notification_payload = Some((channel_coin, message_content));
+                let outcome = shop::place(
+                    product,
+                    amount,
+                    content,
+                    &mut record,
+                    &format!("youtube:{}", user),
+                    now,
+                    &transaction,
+                )?;
+                reply = Self::reply(&outcome, amount, &record);
+
+                match outcome {
+                    Outcome::Placed(order, product) => {
+                        println!(
+                            "[-] {} buy {} x {} for {}",
+                            user, product.name, amount, content
+                        );
+
+                        // 在這裡格式化字串，並暫存到 payload 變數中
+                        let message_content = shop::notice(&order, &product, &record, true);
+                        if let Some(channel_coin) = channel_coin {
+                            notification_payload = Some((channel_coin, order.id, message_content));
+                        }
+                    }
+                    _ => log::warn!("{} failed to buy {} x {}", user, product, amount),
                 }
+            } else {
+                reply = String::from("找不到您的水星幣記錄，請先在聊天室留言。");
             }
 
             transaction.commit()?;
@@ -80,12 +123,49 @@ impl CoinCommandManager {
         }
 
         // 3. 在資料庫連線釋放後，才執行非同步的 Discord API 呼叫
-        if let Some((channel_id, msg)) = notification_payload {
-            discord::Receiver::ChannelId(channel_id)
-                .message(CreateMessage::new().content(msg))
-                .await?; // 這裡的 await 不再持有資料庫連線，不會報錯
+        if let Some((channel_id, order_id, msg)) = notification_payload
+            && let Some(notifier) = &self.notifier
+        {
+            // 訂單已成立並扣款，公告失敗只記錄，不能讓買家以為購買失敗
+            match notifier.notify(channel_id, msg).await {
+                Ok(message) => {
+                    // 4. 記下通知訊息，方便管理員從訊息找回訂單
+                    let mut connection = get_connection()?;
+                    let transaction = connection.transaction()?;
+                    if let Some(mut order) = Order::by_id(order_id, &transaction)? {
+                        order.message = Some(message);
+                        order.update(&transaction)?;
+                    }
+                    transaction.commit()?;
+                }
+                Err(e) => log::warn!("order {} was placed but not announced: {}", order_id, e),
+            }
         }
 
-        Ok(())
+        Ok(reply)
+    }
+
+    fn reply(outcome: &Outcome, amount: f64, buyer: &User) -> String {
+        match outcome {
+            Outcome::Placed(order, product) => format!(
+                "購買成功！{} x {} {}，花費 {} 水星幣，結餘 {} 水星幣。",
+                product.display, amount, product.unit, order.cost, buyer.coin
+            ),
+            Outcome::NotFound => String::from("找不到該商品，或該商品目前未開放購買。"),
+            Outcome::InvalidAmount(product) => {
+                format!("無效的數量。{}。", shop::amount_range(product))
+            }
+            Outcome::SoldOut(product) => format!("購買失敗，{}已售完。", product.display),
+            Outcome::DailyLimit(product) => format!(
+                "購買失敗，您今天已達{}的購買上限（每日 {} 筆）。",
+                product.display,
+                product.daily_limit.unwrap_or_default()
+            ),
+            Outcome::InsufficientFunds(product) => format!(
+                "購買失敗，您的水星幣不足以購買 {} {} 的{}，結餘 {} 水星幣。",
+                amount, product.unit, product.display, buyer.coin
+            ),
+            Outcome::Frozen => String::from("購買失敗，您的水星幣已被凍結，請聯絡管理員。"),
+        }
     }
 }
@@ -1,14 +1,129 @@
-use std::i64;
+use super::youtube::Support;
+use crate::{database::config::Config, error::ServerError};
+use chrono::TimeDelta;
+use rusqlite::Transaction;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
-pub struct CoinConfig;
+/**
+ * rules for earning coins in YouTube chat, stored as JSON in
+ * `Config::CoinRule`.
+ */
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CoinConfig {
+    pub message: i64,
+    pub sponsor_message: i64,
+    pub first_message: i64,
+    pub sponsor_first_message: i64,
+    pub quota: i64,
+    pub sponsor_quota: i64,
+    pub spam_seconds: i64,
+    pub super_chat: f64,
+    pub super_sticker: f64,
+    pub new_sponsor: i64,
+    pub member_milestone: i64,
+    pub membership_gift: i64,
+    /**
+     * value of one unit of each currency in TWD
+     */
+    pub currency_rates: HashMap<String, f64>,
+}
+
+impl Default for CoinConfig {
+    fn default() -> Self {
+        Self {
+            message: 1,
+            sponsor_message: 2,
+            first_message: 10,
+            sponsor_first_message: 20,
+            quota: 50,
+            sponsor_quota: 100,
+            spam_seconds: 30,
+            super_chat: 1.0,
+            super_sticker: 1.0,
+            new_sponsor: 300,
+            member_milestone: 100,
+            membership_gift: 100,
+            currency_rates: HashMap::from([
+                (String::from("TWD"), 1.0),
+                (String::from("USD"), 32.0),
+                (String::from("HKD"), 4.1),
+                (String::from("JPY"), 0.21),
+                (String::from("CNY"), 4.4),
+                (String::from("EUR"), 35.0),
+                (String::from("MYR"), 7.0),
+                (String::from("SGD"), 24.0),
+            ]),
+        }
+    }
+}
 
 impl CoinConfig {
+    /**
+     * the stored rules, or the defaults when none are set or they no longer
+     * parse.
+     */
+    pub fn load(transaction: &Transaction) -> Result<Self, ServerError> {
+        let config = match Config::CoinRule.get(transaction)? {
+            Some(text) => match Self::parse(&text) {
+                Some(config) => config,
+                None => {
+                    log::warn!("invalid coin rule in config, use default: {}", text);
+                    Self::default()
+                }
+            },
+            None => Self::default(),
+        };
+
+        Ok(config)
+    }
+
+    pub fn parse(text: &str) -> Option<Self> {
+        serde_json::from_str::<Self>(text)
+            .ok()
+            .filter(|config| config.is_valid())
+    }
+
+    fn is_valid(&self) -> bool {
+        [
+            self.message,
+            self.sponsor_message,
+            self.first_message,
+            self.sponsor_first_message,
+            self.quota,
+            self.sponsor_quota,
+            self.spam_seconds,
+        ]
+        .iter()
+        .chain(
+            [
+                self.new_sponsor,
+                self.member_milestone,
+                self.membership_gift,
+            ]
+            .iter(),
+        )
+        .all(|value| *value >= 0)
+            && [self.super_chat, self.super_sticker]
+                .iter()
+                .all(|value| value.is_finite() && *value >= 0.0)
+            && self
+                .currency_rates
+                .values()
+                .all(|rate| rate.is_finite() && *rate > 0.0)
+    }
+
     /**
      * the coin earned for each message sent, unless otherwise
      * specified. Guarded by `daily_quota`.
      */
     pub(super) fn coin_per_message(&self, is_sponsor: bool) -> i64 {
-        if is_sponsor { 2 } else { 1 }
+        if is_sponsor {
+            self.sponsor_message
+        } else {
+            self.message
+        }
     }
 
     /**
@@ -16,29 +131,56 @@ impl CoinConfig {
      * Guarded by `daily_quota`.
      */
     pub(super) fn first_message_coin(&self, is_sponsor: bool) -> i64 {
-        if is_sponsor { 20 } else { 10 }
+        if is_sponsor {
+            self.sponsor_first_message
+        } else {
+            self.first_message
+        }
     }
 
     /**
      * the maximum coin earned for a day.
      */
     pub(super) fn daily_quota(&self, is_sponsor: bool) -> i64 {
-        if is_sponsor { 100 } else { 50 }
+        if is_sponsor {
+            self.sponsor_quota
+        } else {
+            self.quota
+        }
     }
 
-    pub(crate) fn booster_cost(&self, level: i64) -> i64 {
-        match level {
-            2 => 50,
-            3 => 100,
-            4 => 200,
-            5 => 400,
-            6 => 800,
-            7 => 1600,
-            8 => 3200,
-            9 => 6400,
-            _ => i64::MAX, // invalid parameter
+    /**
+     * the coin earned for a paid message or membership event. Not guarded by
+     * `daily_quota`. `None` if the currency has no known rate.
+     */
+    pub(super) fn support_coin(&self, support: &Support) -> Option<i64> {
+        let paid = |amount_micros: u64, currency: &str, per_twd: f64| {
+            let rate = self.currency_rates.get(currency)?;
+            Some((amount_micros as f64 / 1_000_000.0 * rate * per_twd).round() as i64)
+        };
+
+        match support {
+            Support::SuperChat {
+                amount_micros,
+                currency,
+            } => paid(*amount_micros, currency, self.super_chat),
+            Support::SuperSticker {
+                amount_micros,
+                currency,
+            } => paid(*amount_micros, currency, self.super_sticker),
+            Support::NewSponsor => Some(self.new_sponsor),
+            Support::MemberMilestone => Some(self.member_milestone),
+            Support::MembershipGifting { count } => Some(self.membership_gift * count),
         }
     }
+
+    /**
+     * messages sent within this window after the last rewarded one earn
+     * nothing.
+     */
+    pub(super) fn spam_window(&self) -> TimeDelta {
+        TimeDelta::seconds(self.spam_seconds)
+    }
 }
 
 #[cfg(test)]
@@ -47,44 +189,61 @@ mod tests {
 
     #[test]
     fn message_coin_respects_membership() {
-        let config = CoinConfig;
+        let config = CoinConfig::default();
         assert_eq!(config.coin_per_message(false), 1);
         assert_eq!(config.coin_per_message(true), 2);
     }
 
     #[test]
     fn first_message_coin_respects_membership() {
-        let config = CoinConfig;
+        let config = CoinConfig::default();
         assert_eq!(config.first_message_coin(false), 10);
         assert_eq!(config.first_message_coin(true), 20);
     }
 
     #[test]
     fn daily_quota_respects_membership() {
-        let config = CoinConfig;
+        let config = CoinConfig::default();
         assert_eq!(config.daily_quota(false), 50);
         assert_eq!(config.daily_quota(true), 100);
     }
 
     #[test]
-    fn booster_cost_returns_expected_prices() {
-        let config = CoinConfig;
-        let expected = [
-            (2, 50),
-            (3, 100),
-            (4, 200),
-            (5, 400),
-            (6, 800),
-            (7, 1_600),
-            (8, 3_200),
-            (9, 6_400),
-        ];
-
-        for (level, price) in expected {
-            assert_eq!(config.booster_cost(level), price);
-        }
+    fn support_coin_converts_currency() {
+        let config = CoinConfig::default();
+        let super_chat = Support::SuperChat {
+            amount_micros: 5_000_000,
+            currency: String::from("USD"),
+        };
+        assert_eq!(config.support_coin(&super_chat), Some(160));
+
+        let sticker = Support::SuperSticker {
+            amount_micros: 75_000_000,
+            currency: String::from("TWD"),
+        };
+        assert_eq!(config.support_coin(&sticker), Some(75));
+
+        let unknown = Support::SuperChat {
+            amount_micros: 1_000_000,
+            currency: String::from("XXX"),
+        };
+        assert_eq!(config.support_coin(&unknown), None);
+
+        let gift = Support::MembershipGifting { count: 5 };
+        assert_eq!(config.support_coin(&gift), Some(500));
+        assert_eq!(config.support_coin(&Support::NewSponsor), Some(300));
+    }
+
+    #[test]
+    fn parse_fills_defaults_and_rejects_negative() {
+        let config = CoinConfig::parse(r#"{"quota": 80, "spam_seconds": 10}"#).expect("config");
+        assert_eq!(config.daily_quota(false), 80);
+        assert_eq!(config.daily_quota(true), 100);
+        assert_eq!(config.spam_window(), TimeDelta::seconds(10));
 
-        assert_eq!(config.booster_cost(1), i64::MAX);
-        assert_eq!(config.booster_cost(10), i64::MAX);
+        assert!(CoinConfig::parse(r#"{"message": -1}"#).is_none());
+        assert!(CoinConfig::parse(r#"{"mesage": 1}"#).is_none());
+        assert!(CoinConfig::parse("not json").is_none());
+        assert!(CoinConfig::parse(r#"{"currency_rates": {"USD": 0}}"#).is_none());
     }
 }
@@ -1,3 +1,7 @@
+pub mod ballot;
 pub mod command;
-mod config;
+pub mod config;
+pub mod moderation;
+pub mod refund;
+pub mod shop;
 pub mod youtube;
@@ -0,0 +1,168 @@
+use crate::{
+    database::{
+        ban::{Ban, BanPolicy},
+        config::Config,
+        ledger::{Ledger, Reason},
+        user::User,
+    },
+    error::ServerError,
+};
+use chrono::{DateTime, Utc};
+use rusqlite::Transaction;
+
+/**
+ * take back the coins a deleted message earned, once per message. The
+ * balance may go negative.
+ */
+pub fn clawback_message(
+    message_id: &str,
+    moderator: &str,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<Option<Ledger>, ServerError> {
+    let Some(credit) = Ledger::by_source(message_id, transaction)? else {
+        return Ok(None);
+    };
+    if credit.amount <= 0 {
+        return Ok(None);
+    }
+    let Some(mut user) = User::by_id(credit.user, transaction)? else {
+        return Ok(None);
+    };
+
+    user.updated_at = now;
+    user.transact_once(
+        -credit.amount,
+        Reason::Clawback,
+        format!("youtube:{}", moderator),
+        &format!("clawback:{}", message_id),
+        transaction,
+    )
+}
+
+/**
+ * apply `Config::BanPolicy` to a channel banned from the chat.
+ */
+pub fn ban(
+    event_id: &str,
+    youtube: &str,
+    moderator: &str,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<Ban, ServerError> {
+    let policy = Config::BanPolicy
+        .get(transaction)?
+        .and_then(|text| BanPolicy::parse(&text))
+        .unwrap_or(BanPolicy::Excluded);
+    let actor = format!("youtube:{}", moderator);
+
+    let ban = Ban {
+        youtube: youtube.to_string(),
+        policy,
+        actor: actor.clone(),
+        source: event_id.to_string(),
+        created_at: now,
+    };
+    ban.save(transaction)?;
+
+    if policy == BanPolicy::Zeroed
+        && let Some(mut user) = User::by_youtube(youtube, transaction)?
+        && user.coin > 0
+    {
+        user.updated_at = now;
+        let amount = -user.coin;
+        user.transact_once(
+            amount,
+            Reason::Clawback,
+            actor,
+            &format!("ban:{}", event_id),
+            transaction,
+        )?;
+    }
+
+    Ok(ban)
+}
+
+pub fn can_earn(youtube: &str, transaction: &Transaction) -> Result<bool, ServerError> {
+    Ok(Ban::by_youtube(youtube, transaction)?.is_none())
+}
+
+pub fn can_spend(youtube: &str, transaction: &Transaction) -> Result<bool, ServerError> {
+    let ban = Ban::by_youtube(youtube, transaction)?;
+    Ok(ban.is_none_or(|ban| ban.policy != BanPolicy::Frozen))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use rusqlite::Connection;
+
+    fn setup(coin: i64) -> Result<(Connection, User), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        let mut user = User {
+            id: 0,
+            youtube: String::from("troll"),
+            discord: None,
+            coin: 0,
+            display: String::from("Troll"),
+            updated_at: Utc::now(),
+        };
+        user.insert(&tran)?;
+        user.transact(coin, Reason::Give, "system", None, &tran)?;
+        tran.commit()?;
+        Ok((conn, user))
+    }
+
+    #[test]
+    fn clawback_reverses_deleted_message_once() -> Result<(), ServerError> {
+        let (mut conn, mut user) = setup(0)?;
+        let tran = conn.transaction()?;
+        user.transact_once(10, Reason::Chat, "system", "spam", &tran)?;
+
+        let entry = clawback_message("spam", "moderator", Utc::now(), &tran)?.expect("clawback");
+        assert_eq!(entry.amount, -10);
+        assert_eq!(entry.reason, Reason::Clawback);
+        assert_eq!(entry.actor, "youtube:moderator");
+        assert_eq!(entry.source.as_deref(), Some("clawback:spam"));
+        assert!(clawback_message("spam", "moderator", Utc::now(), &tran)?.is_none());
+        assert!(clawback_message("unpaid", "moderator", Utc::now(), &tran)?.is_none());
+
+        let user = User::by_id(user.id, &tran)?.expect("user");
+        assert_eq!(user.coin, 0);
+        assert_eq!(Ledger::by_reason(Reason::Clawback, &tran)?.len(), 1);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn ban_applies_configured_policy() -> Result<(), ServerError> {
+        let (mut conn, user) = setup(300)?;
+        let tran = conn.transaction()?;
+
+        // 預設只停止賺幣
+        let ban = ban("ban-1", "troll", "moderator", Utc::now(), &tran)?;
+        assert_eq!(ban.policy, BanPolicy::Excluded);
+        assert!(!can_earn("troll", &tran)?);
+        assert!(can_spend("troll", &tran)?);
+        assert!(can_earn("viewer", &tran)?);
+
+        Config::BanPolicy.set(String::from("frozen"), &tran)?;
+        super::ban("ban-2", "troll", "moderator", Utc::now(), &tran)?;
+        assert!(!can_spend("troll", &tran)?);
+        assert_eq!(User::by_id(user.id, &tran)?.expect("user").coin, 300);
+
+        Config::BanPolicy.set(String::from("zeroed"), &tran)?;
+        super::ban("ban-3", "troll", "moderator", Utc::now(), &tran)?;
+        assert!(can_spend("troll", &tran)?);
+        assert_eq!(User::by_id(user.id, &tran)?.expect("user").coin, 0);
+        let entry = Ledger::by_source("ban:ban-3", &tran)?.expect("clawback");
+        assert_eq!(entry.amount, -300);
+        tran.finish()?;
+
+        Ok(())
+    }
+}
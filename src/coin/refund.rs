@@ -0,0 +1,232 @@
+use crate::{
+    database::{
+        ledger::{Ledger, Reason},
+        order::{Order, OrderState},
+        user::User,
+    },
+    error::ServerError,
+};
+use chrono::{DateTime, Utc};
+use rusqlite::Transaction;
+
+/**
+ * only a booster not drawn yet can be half refunded
+ */
+pub const HALF_REFUND_PRODUCT: &str = "booster";
+
+#[derive(Debug)]
+pub struct Reverted {
+    pub amount: i64,
+    pub buyer: User,
+}
+
+pub fn half_refundable(order: &Order) -> bool {
+    order.product == HALF_REFUND_PRODUCT
+}
+
+/**
+ * credit `amount` back and mark the order refunded, or rejected when
+ * `amount` is zero.
+ */
+pub fn settle(
+    order: &mut Order,
+    amount: i64,
+    actor: &str,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<User, ServerError> {
+    let mut buyer = User::by_id(order.buyer, transaction)?
+        .ok_or_else(|| ServerError::Internal(format!("Buyer of order #{} not found", order.id)))?;
+
+    if amount > 0 {
+        order.transition(OrderState::Refunded, now, transaction)?;
+        buyer.updated_at = now;
+        buyer.transact(amount, Reason::Refund, actor, Some(order.id), transaction)?;
+    } else {
+        order.transition(OrderState::Rejected, now, transaction)?;
+    }
+
+    Ok(buyer)
+}
+
+/**
+ * undo `settle` and put the order back to refund_requested, even if the
+ * balance goes negative.
+ */
+pub fn revert(
+    order: &mut Order,
+    actor: &str,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<Reverted, ServerError> {
+    let refunded: i64 = Ledger::by_order(order.id, transaction)?
+        .iter()
+        .filter(|e| matches!(e.reason, Reason::Refund | Reason::RefundRevert))
+        .map(|e| e.amount)
+        .sum();
+
+    order.transition(OrderState::RefundRequested, now, transaction)?;
+
+    let mut buyer = User::by_id(order.buyer, transaction)?
+        .ok_or_else(|| ServerError::Internal(format!("Buyer of order #{} not found", order.id)))?;
+    if refunded > 0 {
+        buyer.updated_at = now;
+        buyer.transact(
+            -refunded,
+            Reason::RefundRevert,
+            actor,
+            Some(order.id),
+            transaction,
+        )?;
+    }
+
+    Ok(Reverted {
+        amount: refunded,
+        buyer,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use rusqlite::Connection;
+
+    fn setup() -> Result<(Connection, i64), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+
+        let now = Utc::now();
+        let mut user = User {
+            id: 0,
+            youtube: String::from("buyer"),
+            discord: Some(1),
+            coin: 0,
+            display: String::from("Buyer"),
+            updated_at: now,
+        };
+        user.insert(&tran)?;
+        user.transact(500, Reason::Give, "discord:0", None, &tran)?;
+
+        let mut order = Order {
+            id: 0,
+            product: String::from("booster"),
+            amount: 4.0,
+            cost: 200,
+            buyer: user.id,
+            content: String::from("penalty"),
+            message: None,
+            thread: Some(10),
+            case_number: Some(String::from("ABCDEF")),
+            state: OrderState::RefundRequested,
+            created_at: now,
+            updated_at: now,
+        };
+        order.insert(&tran)?;
+        user.transact(-200, Reason::Purchase, "discord:1", Some(order.id), &tran)?;
+        tran.commit()?;
+
+        Ok((conn, order.id))
+    }
+
+    #[test]
+    fn settle_and_revert_half_refund() -> Result<(), ServerError> {
+        let (mut conn, id) = setup()?;
+
+        let tran = conn.transaction()?;
+        let mut order = Order::by_id(id, &tran)?.expect("order");
+        let cost = order.cost;
+        let buyer = settle(&mut order, cost / 2, "discord:0", Utc::now(), &tran)?;
+        assert_eq!(buyer.coin, 400);
+        assert_eq!(order.state, OrderState::Refunded);
+        tran.commit()?;
+
+        let tran = conn.transaction()?;
+        let mut order = Order::by_id(id, &tran)?.expect("order");
+        let reverted = revert(&mut order, "discord:0", Utc::now(), &tran)?;
+        assert_eq!(reverted.amount, 100);
+        assert_eq!(reverted.buyer.coin, 300);
+        assert_eq!(order.state, OrderState::RefundRequested);
+        let buyer = User::by_youtube("buyer", &tran)?.expect("user");
+        assert_eq!(buyer.coin, 300);
+        tran.commit()?;
+
+        // a second settlement only counts what is still outstanding
+        let tran = conn.transaction()?;
+        let mut order = Order::by_id(id, &tran)?.expect("order");
+        let cost = order.cost;
+        settle(&mut order, cost, "discord:0", Utc::now(), &tran)?;
+        let reverted = revert(&mut order, "discord:0", Utc::now(), &tran)?;
+        assert_eq!(reverted.amount, 200);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn reject_keeps_balance() -> Result<(), ServerError> {
+        let (mut conn, id) = setup()?;
+
+        let tran = conn.transaction()?;
+        let mut order = Order::by_id(id, &tran)?.expect("order");
+        let buyer = settle(&mut order, 0, "discord:0", Utc::now(), &tran)?;
+        assert_eq!(buyer.coin, 300);
+        assert_eq!(order.state, OrderState::Rejected);
+
+        let reverted = revert(&mut order, "discord:0", Utc::now(), &tran)?;
+        assert_eq!(reverted.amount, 0);
+        assert_eq!(reverted.buyer.coin, 300);
+        assert_eq!(order.state, OrderState::RefundRequested);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn settle_requires_refund_request() -> Result<(), ServerError> {
+        let (mut conn, id) = setup()?;
+
+        let tran = conn.transaction()?;
+        let mut order = Order::by_id(id, &tran)?.expect("order");
+        let cost = order.cost;
+        settle(&mut order, cost, "discord:0", Utc::now(), &tran)?;
+        assert!(settle(&mut order, cost, "discord:0", Utc::now(), &tran).is_err());
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn half_refund_only_for_booster() -> Result<(), ServerError> {
+        let (mut conn, id) = setup()?;
+
+        let tran = conn.transaction()?;
+        let mut order = Order::by_id(id, &tran)?.expect("order");
+        assert!(half_refundable(&order));
+        order.product = String::from("overtime");
+        assert!(!half_refundable(&order));
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn revert_reports_spent_refund() -> Result<(), ServerError> {
+        let (mut conn, id) = setup()?;
+
+        let tran = conn.transaction()?;
+        let mut order = Order::by_id(id, &tran)?.expect("order");
+        let cost = order.cost;
+        let mut buyer = settle(&mut order, cost, "discord:0", Utc::now(), &tran)?;
+        // 退款後已花掉
+        buyer.transact(-450, Reason::Purchase, "discord:1", None, &tran)?;
+
+        let reverted = revert(&mut order, "discord:0", Utc::now(), &tran)?;
+        assert_eq!(reverted.amount, 200);
+        assert_eq!(reverted.buyer.coin, -150);
+        tran.finish()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,268 @@
+use super::{moderation, refund};
+use crate::{
+    database::{
+        ledger::Reason,
+        order::{Order, OrderState},
+        product::{Pricing, Product},
+        user::User,
+    },
+    error::ServerError,
+};
+use chrono::{DateTime, FixedOffset, TimeDelta, Utc};
+use rusqlite::Transaction;
+
+pub const REFUND_WINDOW: TimeDelta = TimeDelta::hours(72);
+
+pub enum Outcome {
+    Placed(Order, Product),
+    NotFound,
+    InvalidAmount(Product),
+    SoldOut(Product),
+    DailyLimit(Product),
+    InsufficientFunds(Product),
+    Frozen,
+}
+
+fn start_of_day(now: DateTime<Utc>) -> DateTime<Utc> {
+    let offset = FixedOffset::east_opt(8 * 3600).expect("Can't offset time.");
+    now.with_timezone(&offset)
+        .date_naive()
+        .and_hms_opt(0, 0, 0)
+        .and_then(|t| t.and_local_timezone(offset).single())
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or(now)
+}
+
+/**
+ * buy `amount` of `product` for `buyer` under the catalogue rules. Nothing
+ * is written unless the order is placed.
+ */
+pub fn place(
+    product: &str,
+    amount: f64,
+    content: &str,
+    buyer: &mut User,
+    actor: &str,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<Outcome, ServerError> {
+    if !moderation::can_spend(&buyer.youtube, transaction)? {
+        return Ok(Outcome::Frozen);
+    }
+
+    let mut product = match Product::by_name(product, transaction)? {
+        Some(product) if product.enabled => product,
+        _ => return Ok(Outcome::NotFound),
+    };
+
+    let cost = match product.cost(amount) {
+        Some(cost) => cost,
+        None => return Ok(Outcome::InvalidAmount(product)),
+    };
+
+    if product.stock.is_some_and(|stock| stock <= 0) {
+        return Ok(Outcome::SoldOut(product));
+    }
+
+    if let Some(limit) = product.daily_limit
+        && Order::count_since(buyer.id, &product.name, start_of_day(now), transaction)? >= limit
+    {
+        return Ok(Outcome::DailyLimit(product));
+    }
+
+    if buyer.coin < cost {
+        return Ok(Outcome::InsufficientFunds(product));
+    }
+
+    if let Some(stock) = product.stock {
+        product.stock = Some(stock - 1);
+        product.updated_at = now;
+        product.update(transaction)?;
+    }
+
+    let mut order = Order {
+        id: 0,
+        product: product.name.clone(),
+        amount,
+        cost,
+        buyer: buyer.id,
+        content: content.to_string(),
+        message: None,
+        thread: None,
+        case_number: None,
+        state: OrderState::Placed,
+        created_at: now,
+        updated_at: now,
+    };
+    order.insert(transaction)?;
+
+    buyer.updated_at = now;
+    buyer.transact(-cost, Reason::Purchase, actor, Some(order.id), transaction)?;
+
+    Ok(Outcome::Placed(order, product))
+}
+
+pub fn amount_range(product: &Product) -> String {
+    let range = match (product.min_amount, product.max_amount) {
+        (Some(min), Some(max)) => format!("請輸入 {} ~ {} 的數量", min, max),
+        (Some(min), None) => format!("請輸入至少 {} 的數量", min),
+        (None, Some(max)) => format!("請輸入不超過 {} 的正數", max),
+        (None, None) => String::from("請輸入大於 0 的正數"),
+    };
+    match product.pricing {
+        Pricing::Doubling => format!("{}（僅限整數）", range),
+        Pricing::Linear => range,
+    }
+}
+
+/**
+ * the announcement of a placed order in the coin channel.
+ */
+pub fn notice(order: &Order, product: &Product, buyer: &User, from_youtube: bool) -> String {
+    let due = order.created_at + REFUND_WINDOW;
+    let reason = if refund::half_refundable(order) {
+        "如有疑義，或未抽中想領取半價退款，"
+    } else {
+        "如有疑義"
+    };
+
+    let refund = if from_youtube {
+        format!(
+            "_此為 YouTube 聊天室指令，須由管理員手動開啟退款單。_\n-# {}請在 72 小時內（<t:{}:f> 之前）向管理員申請退款。",
+            reason,
+            due.timestamp()
+        )
+    } else {
+        format!(
+            "-# {}請在 72 小時內（<t:{}:f> 之前）執行退款流程。",
+            reason,
+            due.timestamp()
+        )
+    };
+
+    format!(
+        "\
+# {} #
+> ## 「{}」 x **{}** {} ##
+- 來自： {} (`{}`)，結餘 **{}** 水星幣。
+{}
+",
+        product.display,
+        order.content,
+        order.amount,
+        product.unit,
+        buyer.display,
+        buyer.youtube,
+        buyer.coin,
+        refund
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{self, ledger::Ledger};
+    use rusqlite::Connection;
+
+    fn setup(coin: i64) -> Result<(Connection, User), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+
+        let mut user = User {
+            id: 0,
+            youtube: String::from("buyer"),
+            discord: Some(1),
+            coin,
+            display: String::from("Buyer"),
+            updated_at: Utc::now(),
+        };
+        user.insert(&tran)?;
+        tran.commit()?;
+
+        Ok((conn, user))
+    }
+
+    #[test]
+    fn place_charges_buyer_and_records_order() -> Result<(), ServerError> {
+        let (mut conn, mut user) = setup(1_000)?;
+
+        let tran = conn.transaction()?;
+        let outcome = place(
+            "booster",
+            4.0,
+            "penalty",
+            &mut user,
+            "discord:1",
+            Utc::now(),
+            &tran,
+        )?;
+        let Outcome::Placed(order, product) = outcome else {
+            panic!("order not placed");
+        };
+        assert_eq!(order.cost, 200);
+        assert_eq!(product.name, "booster");
+        assert_eq!(user.coin, 800);
+
+        let entries = Ledger::by_order(order.id, &tran)?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].amount, -200);
+        let text = notice(&order, &product, &user, false);
+        assert!(text.contains("懲罰加倍卡"));
+        assert!(text.contains("未抽中想領取半價退款"));
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn place_rejects_by_catalogue_rules() -> Result<(), ServerError> {
+        let (mut conn, mut user) = setup(100)?;
+        let now = Utc::now();
+
+        let tran = conn.transaction()?;
+        assert!(matches!(
+            place("unknown", 1.0, "", &mut user, "system", now, &tran)?,
+            Outcome::NotFound
+        ));
+        assert!(matches!(
+            place("booster", 1.0, "", &mut user, "system", now, &tran)?,
+            Outcome::InvalidAmount(_)
+        ));
+        assert!(matches!(
+            place("overtime", 1.0, "", &mut user, "system", now, &tran)?,
+            Outcome::InsufficientFunds(_)
+        ));
+
+        let mut overtime = Product::by_name("overtime", &tran)?.expect("overtime");
+        overtime.stock = Some(1);
+        overtime.daily_limit = Some(1);
+        overtime.update(&tran)?;
+        assert!(matches!(
+            place("overtime", 0.05, "", &mut user, "system", now, &tran)?,
+            Outcome::Placed(..)
+        ));
+        assert!(matches!(
+            place("overtime", 0.05, "", &mut user, "system", now, &tran)?,
+            Outcome::SoldOut(_)
+        ));
+
+        overtime.stock = None;
+        overtime.update(&tran)?;
+        assert!(matches!(
+            place("overtime", 0.05, "", &mut user, "system", now, &tran)?,
+            Outcome::DailyLimit(_)
+        ));
+
+        overtime.enabled = false;
+        overtime.update(&tran)?;
+        assert!(matches!(
+            place("overtime", 0.05, "", &mut user, "system", now, &tran)?,
+            Outcome::NotFound
+        ));
+        assert_eq!(user.coin, 50);
+        tran.finish()?;
+
+        Ok(())
+    }
+}
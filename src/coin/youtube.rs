@@ -1,173 +1,366 @@
-use super::config::CoinConfig;
+use super::{config::CoinConfig, moderation};
 pub use crate::database::user::User;
-use crate::{database::get_connection, error::ServerError};
-use chrono::{DateTime, TimeDelta, Utc};
-use std::{cmp::min, collections::HashMap};
+use crate::{
+    database::{
+        get_connection,
+        ledger::{Ledger, Reason},
+        multiplier::Multiplier,
+        quota::Quota,
+    },
+    error::ServerError,
+};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use rusqlite::Transaction;
+use std::cmp::{max, min};
 
-pub struct CoinChatManager {
-    config: CoinConfig,
-    refresh: DateTime<Utc>,
-    quota: HashMap<String, i64>,
-    spam: HashMap<String, DateTime<Utc>>,
+#[derive(Debug, Clone, PartialEq)]
+pub enum Support {
+    SuperChat {
+        amount_micros: u64,
+        currency: String,
+    },
+    SuperSticker {
+        amount_micros: u64,
+        currency: String,
+    },
+    NewSponsor,
+    MemberMilestone,
+    MembershipGifting {
+        count: i64,
+    },
 }
 
+/**
+ * daily earnings and the spam window are kept in `coin_quota`, so they
+ * survive restarts.
+ */
+pub struct CoinChatManager;
+
 impl CoinChatManager {
     pub fn new() -> Self {
-        Self {
-            config: CoinConfig,
-            refresh: Utc::now(),
-            quota: HashMap::new(),
-            spam: HashMap::new(),
-        }
+        Self
     }
 
+    /**
+     * credit a text message; a message id seen twice is only rewarded once.
+     */
     pub fn chat(
-        &mut self,
+        &self,
+        message_id: &str,
+        chat_id: &str,
         author_id: &String,
         author_name: &String,
         is_sponsor: bool,
-        event_type: &String,
         now: DateTime<Utc>,
     ) -> Result<(), ServerError> {
-        let coin = if is_text_message(event_type) && !self.is_spam(author_id, now) {
-            self.reset_quota(now);
-
-            // initial coin based on if user exists
-            let coin = if self.quota.contains_key(author_id) {
-                self.config.coin_per_message(is_sponsor)
-            } else {
-                self.config.first_message_coin(is_sponsor)
-            };
-            // apply quota
-            self.apply_quota(coin, author_id, is_sponsor)
-        } else {
-            0
-        };
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+
+        if Ledger::by_source(message_id, &transaction)?.is_some()
+            || !moderation::can_earn(author_id, &transaction)?
+        {
+            return Ok(());
+        }
+
+        // 規則可隨時從網頁修改，每則訊息重新讀取
+        let rules = Rules::load(chat_id, now, &transaction)?;
+        let coin = rules.earn(author_id, is_sponsor, now, &transaction)?;
 
         if coin != 0 {
             println!("[+] user {} receive ${}", author_id, coin);
 
-            let mut connection = get_connection()?;
-            let transaction = connection.transaction()?;
-
-            let record = User::by_youtube(author_id, &transaction)?;
-            if let Some(mut record) = record {
-                record.coin += coin;
-                record.updated_at = now;
-                record.display = author_name.clone();
-                record.update(&transaction)?;
-            } else {
-                let record = User {
-                    id: 0, // Will be set by autoincrement
-                    youtube: author_id.clone(),
-                    discord: None,
-                    coin,
-                    display: author_name.clone(),
-                    updated_at: now,
-                };
-                record.insert(&transaction)?;
-            }
-
-            transaction.commit()?;
+            let mut record = find_or_create(author_id, author_name, now, &transaction)?;
+            record.transact_once(coin, Reason::Chat, "system", message_id, &transaction)?;
         }
 
+        transaction.commit()?;
+
         Ok(())
     }
 
-    fn is_spam(&mut self, author_id: &String, now: DateTime<Utc>) -> bool {
-        let is_spam = self.spam.contains_key(author_id)
-            && now < self.spam[author_id] + TimeDelta::seconds(30);
+    /**
+     * credit a paid message or membership event, scaled by the multiplier
+     * but not limited by the daily quota.
+     */
+    pub fn support(
+        &self,
+        message_id: &str,
+        chat_id: &str,
+        author_id: &String,
+        author_name: &str,
+        support: &Support,
+        now: DateTime<Utc>,
+    ) -> Result<(), ServerError> {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+
+        if !moderation::can_earn(author_id, &transaction)? {
+            return Ok(());
+        }
+
+        let rules = Rules::load(chat_id, now, &transaction)?;
+        let Some(coin) = rules.config.support_coin(support) else {
+            log::warn!("no currency rate for {:?}, nothing credited", support);
+            return Ok(());
+        };
+        let coin = rules.scale(coin);
 
-        if !is_spam {
-            self.spam.insert(author_id.clone(), now);
+        if coin > 0 {
+            let mut record = find_or_create(author_id, author_name, now, &transaction)?;
+            if record
+                .transact_once(coin, Reason::Support, "system", message_id, &transaction)?
+                .is_some()
+            {
+                println!("[+] user {} receive ${} for {:?}", author_id, coin, support);
+            }
         }
 
-        is_spam
+        transaction.commit()?;
+
+        Ok(())
     }
+}
 
-    fn reset_quota(&mut self, now: DateTime<Utc>) {
-        if now > self.refresh + TimeDelta::days(1) {
-            self.refresh += TimeDelta::days(1);
-            self.quota.clear();
+struct Rules {
+    config: CoinConfig,
+    factor: f64,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            config: CoinConfig::default(),
+            factor: 1.0,
         }
     }
+}
+
+impl Rules {
+    fn load(
+        chat_id: &str,
+        now: DateTime<Utc>,
+        transaction: &Transaction,
+    ) -> Result<Self, ServerError> {
+        Ok(Self {
+            config: CoinConfig::load(transaction)?,
+            factor: Multiplier::factor_at(now, chat_id, transaction)?,
+        })
+    }
+
+    fn earn(
+        &self,
+        author_id: &str,
+        is_sponsor: bool,
+        now: DateTime<Utc>,
+        transaction: &Transaction,
+    ) -> Result<i64, ServerError> {
+        let day = local_day(now);
+
+        let (mut quota, coin) = match Quota::by_youtube(author_id, day, transaction)? {
+            Some(quota) if self.is_spam(&quota, now) => return Ok(0),
+            Some(quota) => (quota, self.config.coin_per_message(is_sponsor)),
+            // first message of the day
+            None => (
+                Quota {
+                    youtube: author_id.to_string(),
+                    day,
+                    earned: 0,
+                    last_message: now,
+                },
+                self.config.first_message_coin(is_sponsor),
+            ),
+        };
+
+        let coin = self.apply_quota(self.scale(coin), &quota, is_sponsor);
+        quota.earned += coin;
+        quota.last_message = now;
+        quota.save(transaction)?;
+
+        Ok(coin)
+    }
+
+    fn is_spam(&self, quota: &Quota, now: DateTime<Utc>) -> bool {
+        now < quota.last_message + self.config.spam_window()
+    }
 
-    fn apply_quota(&mut self, coin: i64, author: &String, is_sponsor: bool) -> i64 {
-        let remaining = self
-            .quota
-            .entry(author.clone())
-            .or_insert(self.config.daily_quota(is_sponsor));
+    fn scale(&self, coin: i64) -> i64 {
+        (coin as f64 * self.factor).round() as i64
+    }
 
-        let coin = min(coin, *remaining);
-        *remaining -= coin;
-        coin
+    fn apply_quota(&self, coin: i64, quota: &Quota, is_sponsor: bool) -> i64 {
+        let remaining = self.scale(self.config.daily_quota(is_sponsor)) - quota.earned;
+        max(min(coin, remaining), 0)
     }
 }
 
-fn is_text_message(event_type: &String) -> bool {
-    event_type == "textMessageEvent"
+fn find_or_create(
+    author_id: &String,
+    author_name: &str,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<User, ServerError> {
+    let record = match User::by_youtube(author_id, transaction)? {
+        Some(mut record) => {
+            record.updated_at = now;
+            record.display = author_name.to_string();
+            record
+        }
+        None => {
+            let mut record = User {
+                id: 0, // Will be set by autoincrement
+                youtube: author_id.clone(),
+                discord: None,
+                coin: 0,
+                display: author_name.to_string(),
+                updated_at: now,
+            };
+            record.insert(transaction)?;
+            record
+        }
+    };
+
+    Ok(record)
+}
+
+fn local_day(now: DateTime<Utc>) -> NaiveDate {
+    let offset = FixedOffset::east_opt(8 * 3600).expect("Can't offset time.");
+    now.with_timezone(&offset).date_naive()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::TimeDelta;
+    use crate::database;
+    use chrono::{TimeDelta, TimeZone};
+    use rusqlite::Connection;
+
+    fn setup_conn() -> Result<Connection, ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+        Ok(conn)
+    }
+
+    fn noon() -> DateTime<Utc> {
+        // 2025-01-01 12:00 UTC+8
+        Utc.with_ymd_and_hms(2025, 1, 1, 4, 0, 0)
+            .single()
+            .expect("valid timestamp")
+    }
 
     #[test]
-    fn spam_detection_blocks_frequent_messages() {
-        let mut manager = CoinChatManager::new();
-        let author = String::from("author");
-        let now = Utc::now();
+    fn spam_detection_blocks_frequent_messages() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+        let rules = Rules::default();
+        let now = noon();
 
-        assert!(!manager.is_spam(&author, now));
-        assert!(manager.is_spam(&author, now + TimeDelta::seconds(10)));
-        assert!(!manager.is_spam(&author, now + TimeDelta::seconds(31)));
+        assert_eq!(rules.earn("author", false, now, &tran)?, 10);
+        assert_eq!(
+            rules.earn("author", false, now + TimeDelta::seconds(10), &tran)?,
+            0
+        );
+        assert_eq!(
+            rules.earn("author", false, now + TimeDelta::seconds(31), &tran)?,
+            1
+        );
+        tran.finish()?;
+
+        Ok(())
     }
 
     #[test]
-    fn reset_quota_clears_after_day_rollover() {
-        let mut manager = CoinChatManager::new();
-        let author = String::from("author");
+    fn quota_rolls_over_at_local_midnight() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+        let rules = Rules::default();
 
-        manager.quota.insert(author.clone(), 5);
-        manager.refresh -= TimeDelta::days(2);
+        // 23:59 and 00:01 UTC+8 fall on different days
+        let before = Utc
+            .with_ymd_and_hms(2025, 1, 1, 15, 59, 0)
+            .single()
+            .expect("valid timestamp");
+        let after = before + TimeDelta::minutes(2);
+        assert_ne!(local_day(before), local_day(after));
 
-        let now = manager.refresh + TimeDelta::days(2);
-        manager.reset_quota(now);
+        assert_eq!(rules.earn("author", false, before, &tran)?, 10);
+        assert_eq!(rules.earn("author", false, after, &tran)?, 10);
+        tran.finish()?;
 
-        assert!(manager.quota.is_empty());
-        assert!(now - manager.refresh <= TimeDelta::days(1));
+        Ok(())
+    }
+
+    #[test]
+    fn quota_survives_a_new_manager() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let now = noon();
+
+        let tran = conn.transaction()?;
+        Rules::default().earn("author", false, now, &tran)?;
+        tran.commit()?;
+
+        // a restart must not grant a second first-message bonus
+        let tran = conn.transaction()?;
+        let coin = Rules::default().earn("author", false, now + TimeDelta::minutes(1), &tran)?;
+        assert_eq!(coin, 1);
+        tran.finish()?;
+
+        Ok(())
     }
 
     #[test]
     fn apply_quota_limits_daily_allowance() {
-        let mut manager = CoinChatManager::new();
-        let author = String::from("author");
+        let rules = Rules::default();
+        let mut quota = Quota {
+            youtube: String::from("author"),
+            day: local_day(noon()),
+            earned: 0,
+            last_message: noon(),
+        };
 
-        let awarded = manager.apply_quota(40, &author, false);
+        let awarded = rules.apply_quota(40, &quota, false);
         assert_eq!(awarded, 40);
+        quota.earned += awarded;
 
-        let second_award = manager.apply_quota(40, &author, false);
+        let second_award = rules.apply_quota(40, &quota, false);
         assert_eq!(second_award, 10);
+        quota.earned += second_award;
 
-        let third_award = manager.apply_quota(10, &author, false);
+        let third_award = rules.apply_quota(10, &quota, false);
         assert_eq!(third_award, 0);
     }
 
     #[test]
     fn sponsor_quota_is_larger() {
-        let mut manager = CoinChatManager::new();
-        let author = String::from("member");
+        let rules = Rules::default();
+        let mut quota = Quota {
+            youtube: String::from("member"),
+            day: local_day(noon()),
+            earned: 0,
+            last_message: noon(),
+        };
 
-        let first = manager.apply_quota(120, &author, true);
+        let first = rules.apply_quota(120, &quota, true);
         assert_eq!(first, 100);
-        let second = manager.apply_quota(10, &author, true);
+        quota.earned += first;
+        let second = rules.apply_quota(10, &quota, true);
         assert_eq!(second, 0);
     }
 
     #[test]
-    fn is_text_message_matches_expected_type() {
-        assert!(is_text_message(&"textMessageEvent".to_string()));
-        assert!(!is_text_message(&"superChatEvent".to_string()));
+    fn multiplier_scales_coin_and_quota() {
+        let mut rules = Rules::default();
+        let quota = Quota {
+            youtube: String::from("author"),
+            day: local_day(noon()),
+            earned: 0,
+            last_message: noon(),
+        };
+        rules.factor = 2.0;
+
+        assert_eq!(rules.scale(rules.config.first_message_coin(false)), 20);
+        let awarded = rules.apply_quota(120, &quota, false);
+        assert_eq!(awarded, 100);
     }
 }
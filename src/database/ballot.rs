@@ -0,0 +1,763 @@
+use crate::error::ServerError;
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction, types::Type};
+use sea_query::{Expr, IdenStatic, OnConflict, Order, Query, SqliteQueryBuilder, enum_def};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BallotStatus {
+    Open = 0,
+    Concluded = 1,
+    Cancelled = 2,
+}
+
+impl TryFrom<i32> for BallotStatus {
+    type Error = ServerError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(BallotStatus::Open),
+            1 => Ok(BallotStatus::Concluded),
+            2 => Ok(BallotStatus::Cancelled),
+            _ => Err(ServerError::Internal(format!(
+                "Invalid ballot status: {}",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BallotMode {
+    Reaction = 0,
+    Linked = 1,
+    Coin = 2,
+    Menu = 3,
+}
+
+impl TryFrom<i32> for BallotMode {
+    type Error = ServerError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(BallotMode::Reaction),
+            1 => Ok(BallotMode::Linked),
+            2 => Ok(BallotMode::Coin),
+            3 => Ok(BallotMode::Menu),
+            _ => Err(ServerError::Internal(format!(
+                "Invalid ballot mode: {}",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum TieRule {
+    Runoff,
+    Admin,
+    Random,
+}
+
+impl TieRule {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim() {
+            "runoff" => Some(TieRule::Runoff),
+            "admin" => Some(TieRule::Admin),
+            "random" => Some(TieRule::Random),
+            _ => None,
+        }
+    }
+}
+
+/**
+ * a vote held in a Discord channel; the message only shows it.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def(table_name = "ballot")]
+pub struct Ballot {
+    pub id: i64,
+    pub name: String,
+    pub channel: u64,
+    pub message: Option<u64>,
+    pub deadline: Option<DateTime<Utc>>,
+    pub status: BallotStatus,
+    pub created_at: DateTime<Utc>,
+    pub concluded_at: Option<DateTime<Utc>>,
+    pub winner: Option<i64>,
+    pub runoff_of: Option<i64>,
+    pub mode: BallotMode,
+    pub refund: bool,
+    pub max_options: Option<u32>,
+    pub max_nominations: Option<u32>,
+    /**
+     * separated by spaces, `None` for flags
+     */
+    pub emojis: Option<String>,
+    /**
+     * ids of the messages continuing a long ballot, separated by spaces
+     */
+    pub pages: Option<String>,
+}
+
+impl TryFrom<&Row<'_>> for Ballot {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        let status: i32 = value.get(BallotIden::Status.as_str())?;
+        let status = BallotStatus::try_from(status).map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Integer, err.to_string().into())
+        })?;
+        let mode: i32 = value.get(BallotIden::Mode.as_str())?;
+        let mode = BallotMode::try_from(mode).map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Integer, err.to_string().into())
+        })?;
+
+        Ok(Self {
+            id: value.get(BallotIden::Id.as_str())?,
+            name: value.get(BallotIden::Name.as_str())?,
+            channel: value.get(BallotIden::Channel.as_str())?,
+            message: value.get(BallotIden::Message.as_str())?,
+            deadline: value.get(BallotIden::Deadline.as_str())?,
+            status,
+            created_at: value.get(BallotIden::CreatedAt.as_str())?,
+            concluded_at: value.get(BallotIden::ConcludedAt.as_str())?,
+            winner: value.get(BallotIden::Winner.as_str())?,
+            runoff_of: value.get(BallotIden::RunoffOf.as_str())?,
+            mode,
+            refund: value.get(BallotIden::Refund.as_str())?,
+            max_options: value.get(BallotIden::MaxOptions.as_str())?,
+            max_nominations: value.get(BallotIden::MaxNominations.as_str())?,
+            emojis: value.get(BallotIden::Emojis.as_str())?,
+            pages: value.get(BallotIden::Pages.as_str())?,
+        })
+    }
+}
+
+impl Ballot {
+    const COLUMNS: [BallotIden; 16] = [
+        BallotIden::Id,
+        BallotIden::Name,
+        BallotIden::Channel,
+        BallotIden::Message,
+        BallotIden::Deadline,
+        BallotIden::Status,
+        BallotIden::CreatedAt,
+        BallotIden::ConcludedAt,
+        BallotIden::Winner,
+        BallotIden::RunoffOf,
+        BallotIden::Mode,
+        BallotIden::Refund,
+        BallotIden::MaxOptions,
+        BallotIden::MaxNominations,
+        BallotIden::Emojis,
+        BallotIden::Pages,
+    ];
+
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(BallotIden::Table)
+            .columns([
+                BallotIden::Name,
+                BallotIden::Channel,
+                BallotIden::Message,
+                BallotIden::Deadline,
+                BallotIden::Status,
+                BallotIden::CreatedAt,
+                BallotIden::ConcludedAt,
+                BallotIden::Winner,
+                BallotIden::RunoffOf,
+                BallotIden::Mode,
+                BallotIden::Refund,
+                BallotIden::MaxOptions,
+                BallotIden::MaxNominations,
+                BallotIden::Emojis,
+                BallotIden::Pages,
+            ])
+            .values([
+                self.name.clone().into(),
+                self.channel.into(),
+                self.message.into(),
+                self.deadline.into(),
+                (self.status as i32).into(),
+                self.created_at.into(),
+                self.concluded_at.into(),
+                self.winner.into(),
+                self.runoff_of.into(),
+                (self.mode as i32).into(),
+                self.refund.into(),
+                self.max_options.into(),
+                self.max_nominations.into(),
+                self.emojis.clone().into(),
+                self.pages.clone().into(),
+            ])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        self.id = transaction.last_insert_rowid();
+
+        Ok(())
+    }
+
+    pub fn by_id(id: i64, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(BallotIden::Table)
+            .and_where(Expr::col(BallotIden::Id).eq(id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let ballot = statement
+            .query_and_then(&*values.as_params(), |row| Ballot::try_from(row))?
+            .next();
+
+        Ok(ballot.transpose()?)
+    }
+
+    pub fn open(transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(BallotIden::Table)
+            .and_where(Expr::col(BallotIden::Status).eq(BallotStatus::Open as i32))
+            .order_by(BallotIden::Id, Order::Asc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let ballots = statement
+            .query_and_then(&*values.as_params(), |row| Ballot::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ballots)
+    }
+
+    pub fn due(now: DateTime<Utc>, transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(BallotIden::Table)
+            .and_where(Expr::col(BallotIden::Status).eq(BallotStatus::Open as i32))
+            .and_where(Expr::col(BallotIden::Deadline).lte(now))
+            .order_by(BallotIden::Id, Order::Asc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let ballots = statement
+            .query_and_then(&*values.as_params(), |row| Ballot::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(ballots)
+    }
+
+    pub fn last_concluded(
+        name: Option<&str>,
+        transaction: &Transaction,
+    ) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(BallotIden::Table)
+            .and_where(Expr::col(BallotIden::Status).eq(BallotStatus::Concluded as i32))
+            .and_where_option(name.map(|name| Expr::col(BallotIden::Name).eq(name)))
+            .order_by(BallotIden::ConcludedAt, Order::Desc)
+            .order_by(BallotIden::Id, Order::Desc)
+            .limit(1)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let ballot = statement
+            .query_and_then(&*values.as_params(), |row| Ballot::try_from(row))?
+            .next();
+
+        Ok(ballot.transpose()?)
+    }
+
+    pub fn update(&self, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::update()
+            .table(BallotIden::Table)
+            .values([
+                (BallotIden::Message, self.message.into()),
+                (BallotIden::Pages, self.pages.clone().into()),
+                (BallotIden::Deadline, self.deadline.into()),
+                (BallotIden::Status, (self.status as i32).into()),
+                (BallotIden::ConcludedAt, self.concluded_at.into()),
+                (BallotIden::Winner, self.winner.into()),
+            ])
+            .and_where(Expr::col(BallotIden::Id).eq(self.id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let affected = transaction.execute(&query, &*values.as_params())?;
+        Ok(affected)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[enum_def(table_name = "ballot_option")]
+pub struct BallotOption {
+    pub id: i64,
+    pub ballot: i64,
+    pub flag: u32,
+    pub description: String,
+    pub nominee: u64,
+    pub votes: Option<i64>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for BallotOption {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.get(BallotOptionIden::Id.as_str())?,
+            ballot: value.get(BallotOptionIden::Ballot.as_str())?,
+            flag: value.get(BallotOptionIden::Flag.as_str())?,
+            description: value.get(BallotOptionIden::Description.as_str())?,
+            nominee: value.get(BallotOptionIden::Nominee.as_str())?,
+            votes: value.get(BallotOptionIden::Votes.as_str())?,
+            created_at: value.get(BallotOptionIden::CreatedAt.as_str())?,
+        })
+    }
+}
+
+impl BallotOption {
+    const COLUMNS: [BallotOptionIden; 7] = [
+        BallotOptionIden::Id,
+        BallotOptionIden::Ballot,
+        BallotOptionIden::Flag,
+        BallotOptionIden::Description,
+        BallotOptionIden::Nominee,
+        BallotOptionIden::Votes,
+        BallotOptionIden::CreatedAt,
+    ];
+
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(BallotOptionIden::Table)
+            .columns([
+                BallotOptionIden::Ballot,
+                BallotOptionIden::Flag,
+                BallotOptionIden::Description,
+                BallotOptionIden::Nominee,
+                BallotOptionIden::Votes,
+                BallotOptionIden::CreatedAt,
+            ])
+            .values([
+                self.ballot.into(),
+                self.flag.into(),
+                self.description.clone().into(),
+                self.nominee.into(),
+                self.votes.into(),
+                self.created_at.into(),
+            ])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        self.id = transaction.last_insert_rowid();
+
+        Ok(())
+    }
+
+    pub fn by_ballot(ballot: i64, transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(BallotOptionIden::Table)
+            .and_where(Expr::col(BallotOptionIden::Ballot).eq(ballot))
+            .order_by(BallotOptionIden::Id, Order::Asc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let options = statement
+            .query_and_then(&*values.as_params(), |row| BallotOption::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(options)
+    }
+
+    pub fn update(&self, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::update()
+            .table(BallotOptionIden::Table)
+            .values([
+                (
+                    BallotOptionIden::Description,
+                    self.description.clone().into(),
+                ),
+                (BallotOptionIden::Votes, self.votes.into()),
+            ])
+            .and_where(Expr::col(BallotOptionIden::Id).eq(self.id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let affected = transaction.execute(&query, &*values.as_params())?;
+        Ok(affected)
+    }
+
+    pub fn delete(&self, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::delete()
+            .from_table(BallotOptionIden::Table)
+            .and_where(Expr::col(BallotOptionIden::Id).eq(self.id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let affected = transaction.execute(&query, &*values.as_params())?;
+        Ok(affected)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[enum_def(table_name = "ballot_stake")]
+pub struct Stake {
+    pub id: i64,
+    pub ballot: i64,
+    pub option: i64,
+    pub user: i64,
+    pub amount: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for Stake {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.get(StakeIden::Id.as_str())?,
+            ballot: value.get(StakeIden::Ballot.as_str())?,
+            option: value.get(StakeIden::Option.as_str())?,
+            user: value.get(StakeIden::User.as_str())?,
+            amount: value.get(StakeIden::Amount.as_str())?,
+            created_at: value.get(StakeIden::CreatedAt.as_str())?,
+        })
+    }
+}
+
+impl Stake {
+    const COLUMNS: [StakeIden; 6] = [
+        StakeIden::Id,
+        StakeIden::Ballot,
+        StakeIden::Option,
+        StakeIden::User,
+        StakeIden::Amount,
+        StakeIden::CreatedAt,
+    ];
+
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(StakeIden::Table)
+            .columns([
+                StakeIden::Ballot,
+                StakeIden::Option,
+                StakeIden::User,
+                StakeIden::Amount,
+                StakeIden::CreatedAt,
+            ])
+            .values([
+                self.ballot.into(),
+                self.option.into(),
+                self.user.into(),
+                self.amount.into(),
+                self.created_at.into(),
+            ])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        self.id = transaction.last_insert_rowid();
+
+        Ok(())
+    }
+
+    pub fn by_ballot(ballot: i64, transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(StakeIden::Table)
+            .and_where(Expr::col(StakeIden::Ballot).eq(ballot))
+            .order_by(StakeIden::Id, Order::Asc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let stakes = statement
+            .query_and_then(&*values.as_params(), |row| Stake::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(stakes)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[enum_def(table_name = "ballot_vote")]
+pub struct BallotVote {
+    pub id: i64,
+    pub ballot: i64,
+    pub option: i64,
+    pub voter: u64,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for BallotVote {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.get(BallotVoteIden::Id.as_str())?,
+            ballot: value.get(BallotVoteIden::Ballot.as_str())?,
+            option: value.get(BallotVoteIden::Option.as_str())?,
+            voter: value.get(BallotVoteIden::Voter.as_str())?,
+            created_at: value.get(BallotVoteIden::CreatedAt.as_str())?,
+        })
+    }
+}
+
+impl BallotVote {
+    const COLUMNS: [BallotVoteIden; 5] = [
+        BallotVoteIden::Id,
+        BallotVoteIden::Ballot,
+        BallotVoteIden::Option,
+        BallotVoteIden::Voter,
+        BallotVoteIden::CreatedAt,
+    ];
+
+    /**
+     * replaces the voter's earlier choice on the ballot
+     */
+    pub fn cast(&self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(BallotVoteIden::Table)
+            .columns([
+                BallotVoteIden::Ballot,
+                BallotVoteIden::Option,
+                BallotVoteIden::Voter,
+                BallotVoteIden::CreatedAt,
+            ])
+            .values([
+                self.ballot.into(),
+                self.option.into(),
+                self.voter.into(),
+                self.created_at.into(),
+            ])?
+            .on_conflict(
+                OnConflict::columns([BallotVoteIden::Ballot, BallotVoteIden::Voter])
+                    .update_columns([BallotVoteIden::Option, BallotVoteIden::CreatedAt])
+                    .to_owned(),
+            )
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        Ok(())
+    }
+
+    pub fn by_ballot(ballot: i64, transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(BallotVoteIden::Table)
+            .and_where(Expr::col(BallotVoteIden::Ballot).eq(ballot))
+            .order_by(BallotVoteIden::Id, Order::Asc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let votes = statement
+            .query_and_then(&*values.as_params(), |row| BallotVote::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(votes)
+    }
+
+    pub fn delete_by_option(option: i64, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::delete()
+            .from_table(BallotVoteIden::Table)
+            .and_where(Expr::col(BallotVoteIden::Option).eq(option))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let affected = transaction.execute(&query, &*values.as_params())?;
+        Ok(affected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use rusqlite::Connection;
+
+    #[test]
+    fn tie_rule_parses_config() {
+        assert_eq!(TieRule::parse(" runoff "), Some(TieRule::Runoff));
+        assert_eq!(TieRule::parse("admin"), Some(TieRule::Admin));
+        assert_eq!(TieRule::parse("random"), Some(TieRule::Random));
+        assert_eq!(TieRule::parse("coin"), None);
+    }
+
+    #[test]
+    fn ballot_keeps_options_and_result() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+
+        assert!(Ballot::open(&tran)?.is_empty());
+        let mut ballot = Ballot {
+            id: 0,
+            name: String::from("game"),
+            channel: 1234,
+            message: None,
+            deadline: Some(Utc::now()),
+            status: BallotStatus::Open,
+            created_at: Utc::now(),
+            concluded_at: None,
+            winner: None,
+            runoff_of: None,
+            mode: BallotMode::Coin,
+            refund: true,
+            max_options: Some(5),
+            max_nominations: None,
+            emojis: Some(String::from("🍎 🍌")),
+            pages: None,
+        };
+        ballot.insert(&tran)?;
+        let mut penalty = Ballot {
+            name: String::from("penalty"),
+            mode: BallotMode::Reaction,
+            deadline: None,
+            ..ballot.clone()
+        };
+        penalty.insert(&tran)?;
+        ballot.message = Some(5678);
+        ballot.pages = Some(String::from("5679 5680"));
+        ballot.update(&tran)?;
+
+        let mut first = BallotOption {
+            id: 0,
+            ballot: ballot.id,
+            flag: 1,
+            description: String::from("Minecraft"),
+            nominee: 42,
+            votes: None,
+            created_at: Utc::now(),
+        };
+        first.insert(&tran)?;
+        let mut second = BallotOption {
+            flag: 0,
+            description: String::from("Terraria"),
+            ..first.clone()
+        };
+        second.insert(&tran)?;
+        // 同一個 flag 不能重複使用
+        assert!(first.clone().insert(&tran).is_err());
+
+        let open = Ballot::open(&tran)?;
+        assert_eq!(
+            open.iter()
+                .map(|ballot| ballot.name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["game", "penalty"]
+        );
+        let current = &open[0];
+        assert_eq!(Ballot::due(Utc::now(), &tran)?.len(), 1);
+        assert!(Ballot::due(Utc::now() - chrono::TimeDelta::days(1), &tran)?.is_empty());
+        assert_eq!(current.message, Some(5678));
+        assert_eq!(current.mode, BallotMode::Coin);
+        assert!(current.refund);
+        assert_eq!(current.max_options, Some(5));
+        assert_eq!(current.max_nominations, None);
+        assert_eq!(current.emojis.as_deref(), Some("🍎 🍌"));
+        assert_eq!(current.pages.as_deref(), Some("5679 5680"));
+        let options = BallotOption::by_ballot(ballot.id, &tran)?;
+        assert_eq!(options, vec![first.clone(), second.clone()]);
+
+        assert_eq!(second.delete(&tran)?, 1);
+        first.votes = Some(3);
+        first.update(&tran)?;
+        ballot.winner = Some(first.id);
+        ballot.status = BallotStatus::Concluded;
+        ballot.concluded_at = Some(Utc::now());
+        ballot.update(&tran)?;
+
+        assert_eq!(Ballot::open(&tran)?.len(), 1);
+        assert!(Ballot::due(Utc::now(), &tran)?.is_empty());
+        let concluded = Ballot::by_id(ballot.id, &tran)?.expect("ballot");
+        assert_eq!(concluded.status, BallotStatus::Concluded);
+        assert_eq!(
+            Ballot::last_concluded(None, &tran)?.map(|ballot| ballot.id),
+            Some(ballot.id)
+        );
+        assert_eq!(
+            Ballot::last_concluded(Some("game"), &tran)?.map(|ballot| ballot.id),
+            Some(ballot.id)
+        );
+        assert!(Ballot::last_concluded(Some("penalty"), &tran)?.is_none());
+        let options = BallotOption::by_ballot(ballot.id, &tran)?;
+        assert_eq!(options.len(), 1);
+        assert_eq!(options[0].votes, Some(3));
+        assert_eq!(concluded.winner, Some(first.id));
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn ballot_vote_keeps_latest_choice() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+
+        let mut ballot = Ballot {
+            id: 0,
+            name: String::from("game"),
+            channel: 1234,
+            message: None,
+            deadline: None,
+            status: BallotStatus::Open,
+            created_at: Utc::now(),
+            concluded_at: None,
+            winner: None,
+            runoff_of: None,
+            mode: BallotMode::Menu,
+            refund: false,
+            max_options: None,
+            max_nominations: None,
+            emojis: None,
+            pages: None,
+        };
+        ballot.insert(&tran)?;
+        let mut options = Vec::new();
+        for flag in 0..3 {
+            let mut option = BallotOption {
+                id: 0,
+                ballot: ballot.id,
+                flag,
+                description: format!("option {}", flag),
+                nominee: 42,
+                votes: None,
+                created_at: Utc::now(),
+            };
+            option.insert(&tran)?;
+            options.push(option.id);
+        }
+
+        let vote = |option: i64, voter: u64| BallotVote {
+            id: 0,
+            ballot: ballot.id,
+            option,
+            voter,
+            created_at: Utc::now(),
+        };
+        vote(options[0], 42).cast(&tran)?;
+        vote(options[1], 7).cast(&tran)?;
+        // 改投只保留最後的選擇
+        vote(options[2], 42).cast(&tran)?;
+
+        let votes = BallotVote::by_ballot(ballot.id, &tran)?;
+        assert_eq!(
+            votes
+                .iter()
+                .map(|vote| (vote.voter, vote.option))
+                .collect::<Vec<_>>(),
+            vec![(42, options[2]), (7, options[1])]
+        );
+
+        assert_eq!(BallotVote::delete_by_option(options[2], &tran)?, 1);
+        assert_eq!(BallotVote::by_ballot(ballot.id, &tran)?.len(), 1);
+        tran.finish()?;
+
+        Ok(())
+    }
+}
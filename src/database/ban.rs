@@ -0,0 +1,211 @@
+use crate::error::ServerError;
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction, types::Type};
+use sea_query::{Expr, IdenStatic, OnConflict, Order, Query, SqliteQueryBuilder, enum_def};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum BanPolicy {
+    /**
+     * no more coins from chat, the balance stays usable
+     */
+    Excluded = 0,
+    /**
+     * no more coins, and the balance can not be spent
+     */
+    Frozen = 1,
+    /**
+     * the balance is taken back and no more coins are earned
+     */
+    Zeroed = 2,
+}
+
+impl BanPolicy {
+    pub fn parse(text: &str) -> Option<Self> {
+        match text.trim() {
+            "excluded" => Some(BanPolicy::Excluded),
+            "frozen" => Some(BanPolicy::Frozen),
+            "zeroed" => Some(BanPolicy::Zeroed),
+            _ => None,
+        }
+    }
+}
+
+impl TryFrom<i32> for BanPolicy {
+    type Error = ServerError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(BanPolicy::Excluded),
+            1 => Ok(BanPolicy::Frozen),
+            2 => Ok(BanPolicy::Zeroed),
+            _ => Err(ServerError::Internal(format!(
+                "Invalid ban policy: {}",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def(table_name = "coin_ban")]
+pub struct Ban {
+    pub youtube: String,
+    pub policy: BanPolicy,
+    pub actor: String,
+    pub source: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for Ban {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        let policy: i32 = value.get(BanIden::Policy.as_str())?;
+        let policy = BanPolicy::try_from(policy).map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Integer, err.to_string().into())
+        })?;
+
+        Ok(Self {
+            youtube: value.get(BanIden::Youtube.as_str())?,
+            policy,
+            actor: value.get(BanIden::Actor.as_str())?,
+            source: value.get(BanIden::Source.as_str())?,
+            created_at: value.get(BanIden::CreatedAt.as_str())?,
+        })
+    }
+}
+
+impl Ban {
+    const COLUMNS: [BanIden; 5] = [
+        BanIden::Youtube,
+        BanIden::Policy,
+        BanIden::Actor,
+        BanIden::Source,
+        BanIden::CreatedAt,
+    ];
+
+    /**
+     * replaces the ban the channel already has
+     */
+    pub fn save(&self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(BanIden::Table)
+            .columns(Self::COLUMNS)
+            .values([
+                self.youtube.clone().into(),
+                (self.policy as i32).into(),
+                self.actor.clone().into(),
+                self.source.clone().into(),
+                self.created_at.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(BanIden::Youtube)
+                    .update_columns([
+                        BanIden::Policy,
+                        BanIden::Actor,
+                        BanIden::Source,
+                        BanIden::CreatedAt,
+                    ])
+                    .to_owned(),
+            )
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        Ok(())
+    }
+
+    pub fn by_youtube(
+        youtube: &str,
+        transaction: &Transaction,
+    ) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(BanIden::Table)
+            .and_where(Expr::col(BanIden::Youtube).eq(youtube))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let ban = statement
+            .query_and_then(&*values.as_params(), |row| Ban::try_from(row))?
+            .next();
+
+        Ok(ban.transpose()?)
+    }
+
+    pub fn all(transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(BanIden::Table)
+            .order_by(BanIden::CreatedAt, Order::Desc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let bans = statement
+            .query_and_then(&*values.as_params(), |row| Ban::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(bans)
+    }
+
+    /**
+     * coins already taken back are not returned
+     */
+    pub fn delete(&self, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::delete()
+            .from_table(BanIden::Table)
+            .and_where(Expr::col(BanIden::Youtube).eq(self.youtube.clone()))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let affected = transaction.execute(&query, &*values.as_params())?;
+        Ok(affected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use rusqlite::Connection;
+
+    #[test]
+    fn save_replaces_and_delete_lifts() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+
+        let mut ban = Ban {
+            youtube: String::from("troll"),
+            policy: BanPolicy::Excluded,
+            actor: String::from("youtube:moderator"),
+            source: String::from("ban-1"),
+            created_at: Utc::now(),
+        };
+        ban.save(&tran)?;
+        ban.policy = BanPolicy::Frozen;
+        ban.source = String::from("ban-2");
+        ban.save(&tran)?;
+
+        let fetched = Ban::by_youtube("troll", &tran)?.expect("ban");
+        assert_eq!(fetched.policy, BanPolicy::Frozen);
+        assert_eq!(fetched.source, "ban-2");
+        assert_eq!(Ban::all(&tran)?.len(), 1);
+
+        assert_eq!(fetched.delete(&tran)?, 1);
+        assert!(Ban::by_youtube("troll", &tran)?.is_none());
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn policy_parses_config_text() {
+        assert_eq!(BanPolicy::parse("frozen"), Some(BanPolicy::Frozen));
+        assert_eq!(BanPolicy::parse(" zeroed\n"), Some(BanPolicy::Zeroed));
+        assert_eq!(BanPolicy::parse("excluded"), Some(BanPolicy::Excluded));
+        assert_eq!(BanPolicy::parse("banned"), None);
+    }
+}
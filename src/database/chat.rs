@@ -0,0 +1,425 @@
+use crate::{database::stream::StreamSessionIden, error::ServerError};
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction};
+use sea_query::{
+    Cond, Expr, IdenStatic, LikeExpr, OnConflict, Order, Query, SqliteQueryBuilder, enum_def,
+};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+/**
+ * where reading a live chat stopped, so a restarted listener resumes there.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def(table_name = "chat_cursor")]
+pub struct Cursor {
+    pub chat_id: String,
+    pub page_token: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for Cursor {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            chat_id: value.get(CursorIden::ChatId.as_str())?,
+            page_token: value.get(CursorIden::PageToken.as_str())?,
+            updated_at: value.get(CursorIden::UpdatedAt.as_str())?,
+        })
+    }
+}
+
+impl Cursor {
+    pub fn by_chat_id(
+        chat_id: &str,
+        transaction: &Transaction,
+    ) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns([
+                CursorIden::ChatId,
+                CursorIden::PageToken,
+                CursorIden::UpdatedAt,
+            ])
+            .from(CursorIden::Table)
+            .and_where(Expr::col(CursorIden::ChatId).eq(chat_id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let cursor = statement
+            .query_and_then(&*values.as_params(), |row| Cursor::try_from(row))?
+            .next();
+
+        Ok(cursor.transpose()?)
+    }
+
+    pub fn save(&self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(CursorIden::Table)
+            .columns([
+                CursorIden::ChatId,
+                CursorIden::PageToken,
+                CursorIden::UpdatedAt,
+            ])
+            .values([
+                self.chat_id.clone().into(),
+                self.page_token.clone().into(),
+                self.updated_at.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(CursorIden::ChatId)
+                    .update_columns([CursorIden::PageToken, CursorIden::UpdatedAt])
+                    .to_owned(),
+            )
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def(table_name = "chat_processed")]
+pub struct Processed {
+    pub message_id: String,
+    pub chat_id: String,
+    pub processed_at: DateTime<Utc>,
+    pub author: Option<String>,
+}
+
+impl Processed {
+    /**
+     * `false` if the message was already processed
+     */
+    pub fn claim(&self, transaction: &Transaction) -> Result<bool, ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(ProcessedIden::Table)
+            .columns([
+                ProcessedIden::MessageId,
+                ProcessedIden::ChatId,
+                ProcessedIden::ProcessedAt,
+                ProcessedIden::Author,
+            ])
+            .values([
+                self.message_id.clone().into(),
+                self.chat_id.clone().into(),
+                self.processed_at.into(),
+                self.author.clone().into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(ProcessedIden::MessageId)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let affected = transaction.execute(&query, &*values.as_params())?;
+        Ok(affected == 1)
+    }
+
+    /**
+     * forget the messages of streams that ended before `before`.
+     */
+    pub fn prune(before: DateTime<Utc>, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::delete()
+            .from_table(ProcessedIden::Table)
+            .and_where(
+                Expr::col(ProcessedIden::ChatId).in_subquery(
+                    Query::select()
+                        .column(StreamSessionIden::ChatId)
+                        .from(StreamSessionIden::Table)
+                        .and_where(Expr::col(StreamSessionIden::EndAt).lt(before))
+                        .to_owned(),
+                ),
+            )
+            .build_rusqlite(SqliteQueryBuilder);
+
+        Ok(transaction.execute(&query, &*values.as_params())?)
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[enum_def(table_name = "chat_archive")]
+pub struct Archived {
+    pub message_id: String,
+    pub chat_id: String,
+    pub author: Option<String>,
+    pub author_name: String,
+    pub kind: String,
+    pub text: String,
+    pub published_at: DateTime<Utc>,
+}
+
+/**
+ * empty fields match everything.
+ */
+#[derive(Debug, Default, Clone)]
+pub struct Search {
+    pub text: Option<String>,
+    pub author: Option<String>,
+    pub kind: Option<String>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+impl TryFrom<&Row<'_>> for Archived {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            message_id: value.get(ArchivedIden::MessageId.as_str())?,
+            chat_id: value.get(ArchivedIden::ChatId.as_str())?,
+            author: value.get(ArchivedIden::Author.as_str())?,
+            author_name: value.get(ArchivedIden::AuthorName.as_str())?,
+            kind: value.get(ArchivedIden::Kind.as_str())?,
+            text: value.get(ArchivedIden::Text.as_str())?,
+            published_at: value.get(ArchivedIden::PublishedAt.as_str())?,
+        })
+    }
+}
+
+impl Archived {
+    const COLUMNS: [ArchivedIden; 7] = [
+        ArchivedIden::MessageId,
+        ArchivedIden::ChatId,
+        ArchivedIden::Author,
+        ArchivedIden::AuthorName,
+        ArchivedIden::Kind,
+        ArchivedIden::Text,
+        ArchivedIden::PublishedAt,
+    ];
+
+    pub fn save(&self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(ArchivedIden::Table)
+            .columns(Self::COLUMNS)
+            .values([
+                self.message_id.clone().into(),
+                self.chat_id.clone().into(),
+                self.author.clone().into(),
+                self.author_name.clone().into(),
+                self.kind.clone().into(),
+                self.text.clone().into(),
+                self.published_at.into(),
+            ])?
+            .on_conflict(
+                OnConflict::column(ArchivedIden::MessageId)
+                    .do_nothing()
+                    .to_owned(),
+            )
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        Ok(())
+    }
+
+    pub fn search(
+        chat_id: &str,
+        search: &Search,
+        transaction: &Transaction,
+    ) -> Result<Vec<Self>, ServerError> {
+        let mut select = Query::select();
+        select
+            .columns(Self::COLUMNS)
+            .from(ArchivedIden::Table)
+            .and_where(Expr::col(ArchivedIden::ChatId).eq(chat_id))
+            .order_by(ArchivedIden::PublishedAt, Order::Asc)
+            .order_by(ArchivedIden::MessageId, Order::Asc);
+
+        if let Some(text) = search.text.as_deref() {
+            select.and_where(Expr::col(ArchivedIden::Text).like(contains(text)));
+        }
+        if let Some(author) = search.author.as_deref() {
+            select.cond_where(
+                Cond::any()
+                    .add(Expr::col(ArchivedIden::Author).eq(author))
+                    .add(Expr::col(ArchivedIden::AuthorName).like(contains(author))),
+            );
+        }
+        if let Some(kind) = search.kind.as_deref() {
+            select.and_where(Expr::col(ArchivedIden::Kind).eq(kind));
+        }
+        if let Some(limit) = search.limit {
+            select.limit(limit);
+        }
+        if let Some(offset) = search.offset {
+            select.offset(offset);
+        }
+
+        let (query, values) = select.build_rusqlite(SqliteQueryBuilder);
+        let mut statement = transaction.prepare(&query)?;
+        let messages = statement
+            .query_and_then(&*values.as_params(), |row| Archived::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(messages)
+    }
+}
+
+fn contains(text: &str) -> LikeExpr {
+    let escaped = text
+        .replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_");
+    LikeExpr::new(format!("%{}%", escaped)).escape('\\')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{self, stream::StreamSession};
+    use chrono::TimeDelta;
+    use rusqlite::Connection;
+
+    fn setup_conn() -> Result<Connection, ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn cursor_save_overwrites() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+
+        let mut cursor = Cursor {
+            chat_id: String::from("chat"),
+            page_token: String::from("a"),
+            updated_at: Utc::now(),
+        };
+        cursor.save(&tran)?;
+        cursor.page_token = String::from("b");
+        cursor.save(&tran)?;
+
+        let fetched = Cursor::by_chat_id("chat", &tran)?.expect("cursor");
+        assert_eq!(fetched.page_token, "b");
+        assert!(Cursor::by_chat_id("other", &tran)?.is_none());
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn message_is_claimed_once() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+
+        let processed = Processed {
+            message_id: String::from("message"),
+            chat_id: String::from("chat"),
+            processed_at: Utc::now(),
+            author: Some(String::from("author")),
+        };
+        assert!(processed.claim(&tran)?);
+        assert!(!processed.claim(&tran)?);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn prune_keeps_recent_streams() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+
+        let now = Utc::now();
+        for (broadcast, end_at) in [
+            ("old", Some(now - TimeDelta::days(10))),
+            ("recent", Some(now - TimeDelta::hours(1))),
+            ("live", None),
+        ] {
+            let mut session = StreamSession {
+                id: 0,
+                broadcast: broadcast.to_string(),
+                chat_id: broadcast.to_string(),
+                channel: String::from("channel"),
+                title: String::from("title"),
+                start_at: Some(now - TimeDelta::days(11)),
+                end_at,
+                peak_viewers: 0,
+                message_count: 0,
+                chatter_count: 0,
+                coin_issued: 0,
+                updated_at: now,
+            };
+            session.insert(&tran)?;
+            Processed {
+                message_id: format!("{broadcast}-message"),
+                chat_id: broadcast.to_string(),
+                processed_at: now - TimeDelta::days(11),
+                author: None,
+            }
+            .claim(&tran)?;
+        }
+
+        assert_eq!(Processed::prune(now - TimeDelta::days(7), &tran)?, 1);
+        let remaining: i64 =
+            tran.query_row("SELECT COUNT(*) FROM chat_processed", [], |row| row.get(0))?;
+        assert_eq!(remaining, 2);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn archive_search_filters() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+
+        let message = |id: &str, author: &str, kind: &str, text: &str, second: i64| Archived {
+            message_id: id.to_string(),
+            chat_id: String::from("chat"),
+            author: Some(author.to_string()),
+            author_name: author.to_uppercase(),
+            kind: kind.to_string(),
+            text: text.to_string(),
+            published_at: DateTime::from_timestamp(second, 0).expect("timestamp"),
+        };
+        message("m2", "bob", "textMessageEvent", "100% 好笑", 2).save(&tran)?;
+        message("m1", "alice", "textMessageEvent", "晚安", 1).save(&tran)?;
+        message("m3", "alice", "superChatEvent", "NT$75.00", 3).save(&tran)?;
+        // saving again keeps the first copy
+        message("m1", "alice", "textMessageEvent", "edited", 1).save(&tran)?;
+
+        let all = Archived::search("chat", &Search::default(), &tran)?;
+        let ids: Vec<&str> = all.iter().map(|m| m.message_id.as_str()).collect();
+        assert_eq!(ids, vec!["m1", "m2", "m3"]);
+        assert_eq!(all[0].text, "晚安");
+
+        let search = |search: Search| -> Result<usize, ServerError> {
+            Ok(Archived::search("chat", &search, &tran)?.len())
+        };
+        let text = |text: &str| Search {
+            text: Some(text.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(search(text("好笑"))?, 1);
+        assert_eq!(search(text("%"))?, 1);
+        assert_eq!(search(text("_"))?, 0);
+        let author = Search {
+            author: Some(String::from("ALI")),
+            ..Default::default()
+        };
+        assert_eq!(search(author)?, 2);
+        let kind = Search {
+            author: Some(String::from("alice")),
+            kind: Some(String::from("superChatEvent")),
+            ..Default::default()
+        };
+        assert_eq!(search(kind)?, 1);
+        let page = Search {
+            limit: Some(1),
+            offset: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(Archived::search("chat", &page, &tran)?[0].message_id, "m2");
+        assert!(Archived::search("other", &Search::default(), &tran)?.is_empty());
+        tran.finish()?;
+
+        Ok(())
+    }
+}
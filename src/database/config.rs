@@ -1,7 +1,9 @@
 use crate::error::ServerError;
-use rusqlite::Transaction;
-use sea_query::{Expr, Iden, Query, SqliteQueryBuilder};
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction};
+use sea_query::{Expr, Iden, Order, Query, SqliteQueryBuilder};
 use sea_query_rusqlite::RusqliteBinder;
+use serde::Serialize;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Config {
@@ -10,6 +12,42 @@ pub enum Config {
     ChannelVote = 2,
     MessageVote = 3,
     YoutubeChannelId = 4,
+    /**
+     * JSON of `coin::config::CoinConfig`
+     */
+    CoinRule = 5,
+    /**
+     * JSON object from handler name to enabled
+     */
+    ChatHandler = 6,
+    ChannelStream = 7,
+    VideoTag = 8,
+    BanPolicy = 9,
+    VoteTieRule = 10,
+}
+
+impl TryFrom<i32> for Config {
+    type Error = ServerError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Config::ChannelPenalty),
+            1 => Ok(Config::ChannelCoin),
+            2 => Ok(Config::ChannelVote),
+            3 => Ok(Config::MessageVote),
+            4 => Ok(Config::YoutubeChannelId),
+            5 => Ok(Config::CoinRule),
+            6 => Ok(Config::ChatHandler),
+            7 => Ok(Config::ChannelStream),
+            8 => Ok(Config::VideoTag),
+            9 => Ok(Config::BanPolicy),
+            10 => Ok(Config::VoteTieRule),
+            _ => Err(ServerError::Internal(format!(
+                "Invalid config id: {}",
+                value
+            ))),
+        }
+    }
 }
 
 #[derive(Debug, Iden)]
@@ -20,6 +58,35 @@ enum ConfigIden {
     Text,
 }
 
+#[derive(Debug, Iden)]
+#[iden = "config_history"]
+enum ConfigHistoryIden {
+    Table,
+    Id,
+    Config,
+    Text,
+    CreatedAt,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct ConfigVersion {
+    pub id: i64,
+    pub text: String,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for ConfigVersion {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.get(0)?,
+            text: value.get(1)?,
+            created_at: value.get(2)?,
+        })
+    }
+}
+
 impl Config {
     pub fn get(&self, transaction: &Transaction) -> Result<Option<String>, ServerError> {
         let (query, values) = Query::select()
@@ -39,6 +106,10 @@ impl Config {
     }
 
     pub fn set(&self, text: String, transaction: &Transaction) -> Result<(), ServerError> {
+        if self.get(transaction)?.as_ref() == Some(&text) {
+            return Ok(());
+        }
+
         // First try to update
         let (update_query, update_values) = Query::update()
             .table(ConfigIden::Table)
@@ -53,14 +124,46 @@ impl Config {
             let (insert_query, insert_values) = Query::insert()
                 .into_table(ConfigIden::Table)
                 .columns([ConfigIden::Id, ConfigIden::Text])
-                .values([(*self as i64).into(), text.into()])?
+                .values([(*self as i64).into(), text.clone().into()])?
                 .build_rusqlite(SqliteQueryBuilder);
 
             transaction.execute(&insert_query, &*insert_values.as_params())?;
         }
 
+        let (history_query, history_values) = Query::insert()
+            .into_table(ConfigHistoryIden::Table)
+            .columns([
+                ConfigHistoryIden::Config,
+                ConfigHistoryIden::Text,
+                ConfigHistoryIden::CreatedAt,
+            ])
+            .values([(*self as i64).into(), text.into(), Utc::now().into()])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&history_query, &*history_values.as_params())?;
+
         Ok(())
     }
+
+    pub fn history(&self, transaction: &Transaction) -> Result<Vec<ConfigVersion>, ServerError> {
+        let (query, values) = Query::select()
+            .columns([
+                ConfigHistoryIden::Id,
+                ConfigHistoryIden::Text,
+                ConfigHistoryIden::CreatedAt,
+            ])
+            .from(ConfigHistoryIden::Table)
+            .and_where(Expr::col(ConfigHistoryIden::Config).eq(*self as i64))
+            .order_by(ConfigHistoryIden::Id, Order::Desc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let versions = statement
+            .query_and_then(&*values.as_params(), |row| ConfigVersion::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(versions)
+    }
 }
 
 #[cfg(test)]
@@ -105,4 +208,33 @@ mod tests {
         tran.finish()?;
         Ok(())
     }
+
+    #[test]
+    fn set_keeps_history_of_changes() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+        let config = Config::CoinRule;
+
+        config.set(String::from("a"), &tran)?;
+        config.set(String::from("b"), &tran)?;
+        config.set(String::from("b"), &tran)?;
+
+        let history = config.history(&tran)?;
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].text, "b");
+        assert_eq!(history[1].text, "a");
+        assert!(Config::ChannelCoin.history(&tran)?.is_empty());
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn id_round_trips() {
+        for id in 0..=10 {
+            let config = Config::try_from(id).expect("config");
+            assert_eq!(config as i32, id);
+        }
+        assert!(Config::try_from(11).is_err());
+    }
 }
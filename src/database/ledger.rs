@@ -0,0 +1,276 @@
+use crate::error::ServerError;
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction, types::Type};
+use sea_query::{Expr, IdenStatic, Order, Query, SqliteQueryBuilder, enum_def};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Reason {
+    Chat,
+    Purchase,
+    Give,
+    Refund,
+    RefundRevert,
+    Support,
+    Clawback,
+    Vote,
+}
+
+impl Reason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Reason::Chat => "chat",
+            Reason::Purchase => "purchase",
+            Reason::Give => "give",
+            Reason::Refund => "refund",
+            Reason::RefundRevert => "refund_revert",
+            Reason::Support => "support",
+            Reason::Clawback => "clawback",
+            Reason::Vote => "vote",
+        }
+    }
+}
+
+impl TryFrom<&str> for Reason {
+    type Error = ServerError;
+
+    fn try_from(value: &str) -> Result<Self, Self::Error> {
+        match value {
+            "chat" => Ok(Reason::Chat),
+            "purchase" => Ok(Reason::Purchase),
+            "give" => Ok(Reason::Give),
+            "refund" => Ok(Reason::Refund),
+            "refund_revert" => Ok(Reason::RefundRevert),
+            "support" => Ok(Reason::Support),
+            "clawback" => Ok(Reason::Clawback),
+            "vote" => Ok(Reason::Vote),
+            _ => Err(ServerError::Internal(format!(
+                "Invalid ledger reason: '{}'",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def(table_name = "coin_ledger")]
+pub struct Ledger {
+    pub id: i64,
+    pub user: i64,
+    pub amount: i64,
+    pub reason: Reason,
+    pub actor: String,
+    pub order: Option<i64>,
+    pub balance: i64,
+    pub created_at: DateTime<Utc>,
+    /**
+     * the YouTube event credited, each at most once
+     */
+    pub source: Option<String>,
+}
+
+impl TryFrom<&Row<'_>> for Ledger {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        let reason: String = value.get(LedgerIden::Reason.as_str())?;
+        let reason = Reason::try_from(reason.as_str()).map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Text, err.to_string().into())
+        })?;
+
+        Ok(Self {
+            id: value.get(LedgerIden::Id.as_str())?,
+            user: value.get(LedgerIden::User.as_str())?,
+            amount: value.get(LedgerIden::Amount.as_str())?,
+            reason,
+            actor: value.get(LedgerIden::Actor.as_str())?,
+            order: value.get(LedgerIden::Order.as_str())?,
+            balance: value.get(LedgerIden::Balance.as_str())?,
+            created_at: value.get(LedgerIden::CreatedAt.as_str())?,
+            source: value.get(LedgerIden::Source.as_str())?,
+        })
+    }
+}
+
+impl Ledger {
+    const COLUMNS: [LedgerIden; 9] = [
+        LedgerIden::Id,
+        LedgerIden::User,
+        LedgerIden::Amount,
+        LedgerIden::Reason,
+        LedgerIden::Actor,
+        LedgerIden::Order,
+        LedgerIden::Balance,
+        LedgerIden::CreatedAt,
+        LedgerIden::Source,
+    ];
+
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(LedgerIden::Table)
+            .columns([
+                LedgerIden::User,
+                LedgerIden::Amount,
+                LedgerIden::Reason,
+                LedgerIden::Actor,
+                LedgerIden::Order,
+                LedgerIden::Balance,
+                LedgerIden::CreatedAt,
+                LedgerIden::Source,
+            ])
+            .values([
+                self.user.into(),
+                self.amount.into(),
+                self.reason.as_str().into(),
+                self.actor.clone().into(),
+                self.order.into(),
+                self.balance.into(),
+                self.created_at.into(),
+                self.source.clone().into(),
+            ])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        self.id = transaction.last_insert_rowid();
+
+        Ok(())
+    }
+
+    pub fn by_user(user: i64, transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(LedgerIden::Table)
+            .and_where(Expr::col(LedgerIden::User).eq(user))
+            .order_by(LedgerIden::Id, Order::Desc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let entries = statement
+            .query_and_then(&*values.as_params(), |row| Ledger::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    pub fn by_order(order: i64, transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(LedgerIden::Table)
+            .and_where(Expr::col(LedgerIden::Order).eq(order))
+            .order_by(LedgerIden::Id, Order::Asc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let entries = statement
+            .query_and_then(&*values.as_params(), |row| Ledger::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    pub fn by_reason(reason: Reason, transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(LedgerIden::Table)
+            .and_where(Expr::col(LedgerIden::Reason).eq(reason.as_str()))
+            .order_by(LedgerIden::Id, Order::Desc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let entries = statement
+            .query_and_then(&*values.as_params(), |row| Ledger::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(entries)
+    }
+
+    pub fn by_source(source: &str, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(LedgerIden::Table)
+            .and_where(Expr::col(LedgerIden::Source).eq(source))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let entry = statement
+            .query_and_then(&*values.as_params(), |row| Ledger::try_from(row))?
+            .next();
+
+        Ok(entry.transpose()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{self, user::User};
+    use rusqlite::Connection;
+
+    fn setup_conn() -> Result<Connection, ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn transact_records_balance() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+
+        let tran = conn.transaction()?;
+        let mut user = User {
+            id: 0,
+            youtube: String::from("test_id"),
+            discord: None,
+            coin: 0,
+            display: String::from("test_user"),
+            updated_at: Utc::now(),
+        };
+        user.insert(&tran)?;
+        user.transact(30, Reason::Chat, "system", None, &tran)?;
+        user.transact(-20, Reason::Purchase, "discord:1", Some(7), &tran)?;
+        tran.commit()?;
+
+        let tran = conn.transaction()?;
+        let fetched = User::by_youtube("test_id", &tran)?.expect("user");
+        assert_eq!(fetched.coin, 10);
+
+        let entries = Ledger::by_user(user.id, &tran)?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].amount, -20);
+        assert_eq!(entries[0].reason, Reason::Purchase);
+        assert_eq!(entries[0].actor, "discord:1");
+        assert_eq!(entries[0].order, Some(7));
+        assert_eq!(entries[0].balance, 10);
+        assert_eq!(entries[1].amount, 30);
+        assert_eq!(entries[1].balance, 30);
+
+        let entries = Ledger::by_order(7, &tran)?;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].amount, -20);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn reason_round_trips() {
+        for reason in [
+            Reason::Chat,
+            Reason::Purchase,
+            Reason::Give,
+            Reason::Refund,
+            Reason::RefundRevert,
+            Reason::Support,
+            Reason::Clawback,
+            Reason::Vote,
+        ] {
+            assert_eq!(Reason::try_from(reason.as_str()).ok(), Some(reason));
+        }
+        assert!(Reason::try_from("unknown").is_err());
+    }
+}
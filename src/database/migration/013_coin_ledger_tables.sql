@@ -0,0 +1,14 @@
+CREATE TABLE `coin_ledger` (
+    `id` INTEGER PRIMARY KEY NOT NULL,
+    `user` INTEGER NOT NULL,
+    `amount` INTEGER NOT NULL,
+    `reason` TEXT NOT NULL,
+    `actor` TEXT NOT NULL,
+    `order` INTEGER,
+    `balance` INTEGER NOT NULL,
+    `created_at` DATETIME NOT NULL
+);
+
+CREATE INDEX `coin_ledger_i0` ON `coin_ledger` (`user`, `created_at`);
+
+CREATE INDEX `coin_ledger_i1` ON `coin_ledger` (`order`);
@@ -0,0 +1,20 @@
+CREATE TABLE `order` (
+    `id` INTEGER PRIMARY KEY NOT NULL,
+    `product` TEXT NOT NULL,
+    `amount` REAL NOT NULL,
+    `cost` INTEGER NOT NULL,
+    `buyer` INTEGER NOT NULL,
+    `content` TEXT NOT NULL,
+    `message` BIGINT,
+    `thread` BIGINT,
+    `case_number` TEXT,
+    `state` INTEGER NOT NULL,
+    `created_at` DATETIME NOT NULL,
+    `updated_at` DATETIME NOT NULL
+);
+
+CREATE INDEX `order_i0` ON `order` (`buyer`);
+
+CREATE INDEX `order_i1` ON `order` (`state`);
+
+CREATE UNIQUE INDEX `order_i2` ON `order` (`thread`);
@@ -0,0 +1,21 @@
+CREATE TABLE `product` (
+    `id` INTEGER PRIMARY KEY NOT NULL,
+    `name` TEXT NOT NULL,
+    `display` TEXT NOT NULL,
+    `unit` TEXT NOT NULL,
+    `pricing` INTEGER NOT NULL,
+    `price` INTEGER NOT NULL,
+    `min_amount` REAL,
+    `max_amount` REAL,
+    `stock` INTEGER,
+    `daily_limit` INTEGER,
+    `enabled` BOOLEAN NOT NULL,
+    `updated_at` DATETIME NOT NULL
+);
+
+CREATE UNIQUE INDEX `product_i0` ON `product` (`name`);
+
+INSERT INTO `product` (`name`, `display`, `unit`, `pricing`, `price`, `min_amount`, `max_amount`, `stock`, `daily_limit`, `enabled`, `updated_at`)
+VALUES
+    ('booster', '懲罰加倍卡', '倍', 1, 50, 2, 9, NULL, NULL, 1, CURRENT_TIMESTAMP),
+    ('overtime', '加班台時數卡', '小時', 0, 1000, NULL, NULL, NULL, NULL, 1, CURRENT_TIMESTAMP);
@@ -0,0 +1,19 @@
+CREATE TABLE `config_history` (
+    `id` INTEGER PRIMARY KEY NOT NULL,
+    `config` INTEGER NOT NULL,
+    `text` TEXT NOT NULL,
+    `created_at` DATETIME NOT NULL
+);
+
+CREATE INDEX `config_history_i0` ON `config_history` (`config`);
+
+CREATE TABLE `coin_multiplier` (
+    `id` INTEGER PRIMARY KEY NOT NULL,
+    `name` TEXT NOT NULL,
+    `factor` REAL NOT NULL,
+    `start_at` DATETIME NOT NULL,
+    `end_at` DATETIME NOT NULL,
+    `created_at` DATETIME NOT NULL
+);
+
+CREATE INDEX `coin_multiplier_i0` ON `coin_multiplier` (`start_at`, `end_at`);
@@ -0,0 +1,7 @@
+CREATE TABLE `coin_quota` (
+    `youtube` TEXT NOT NULL,
+    `day` TEXT NOT NULL,
+    `earned` INTEGER NOT NULL,
+    `last_message` DATETIME NOT NULL,
+    PRIMARY KEY (`youtube`, `day`)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE `coin_ledger` ADD COLUMN `source` TEXT;
+
+CREATE UNIQUE INDEX `coin_ledger_i2` ON `coin_ledger` (`source`);
@@ -0,0 +1,11 @@
+CREATE TABLE `chat_cursor` (
+    `chat_id` TEXT PRIMARY KEY NOT NULL,
+    `page_token` TEXT NOT NULL,
+    `updated_at` DATETIME NOT NULL
+);
+
+CREATE TABLE `chat_processed` (
+    `message_id` TEXT PRIMARY KEY NOT NULL,
+    `chat_id` TEXT NOT NULL,
+    `processed_at` DATETIME NOT NULL
+);
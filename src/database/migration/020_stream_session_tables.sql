@@ -0,0 +1,18 @@
+ALTER TABLE `chat_processed` ADD COLUMN `author` TEXT;
+
+CREATE INDEX `chat_processed_i1` ON `chat_processed` (`chat_id`);
+
+CREATE TABLE `stream_session` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `broadcast` TEXT NOT NULL UNIQUE,
+    `chat_id` TEXT NOT NULL,
+    `channel` TEXT NOT NULL,
+    `title` TEXT NOT NULL,
+    `start_at` DATETIME,
+    `end_at` DATETIME,
+    `peak_viewers` INTEGER NOT NULL DEFAULT 0,
+    `message_count` INTEGER NOT NULL DEFAULT 0,
+    `chatter_count` INTEGER NOT NULL DEFAULT 0,
+    `coin_issued` INTEGER NOT NULL DEFAULT 0,
+    `updated_at` DATETIME NOT NULL
+);
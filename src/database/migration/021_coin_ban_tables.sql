@@ -0,0 +1,7 @@
+CREATE TABLE `coin_ban` (
+    `youtube` TEXT PRIMARY KEY NOT NULL,
+    `policy` INTEGER NOT NULL,
+    `actor` TEXT NOT NULL,
+    `source` TEXT NOT NULL,
+    `created_at` DATETIME NOT NULL
+);
@@ -0,0 +1,11 @@
+CREATE TABLE `chat_archive` (
+    `message_id` TEXT PRIMARY KEY NOT NULL,
+    `chat_id` TEXT NOT NULL,
+    `author` TEXT,
+    `author_name` TEXT NOT NULL,
+    `kind` TEXT NOT NULL,
+    `text` TEXT NOT NULL,
+    `published_at` DATETIME NOT NULL
+);
+
+CREATE INDEX `chat_archive_i1` ON `chat_archive` (`chat_id`, `published_at`);
@@ -0,0 +1,20 @@
+CREATE TABLE `ballot` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `channel` INTEGER NOT NULL,
+    `message` INTEGER,
+    `deadline` DATETIME,
+    `status` INTEGER NOT NULL DEFAULT 0,
+    `created_at` DATETIME NOT NULL,
+    `concluded_at` DATETIME
+);
+
+CREATE TABLE `ballot_option` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `ballot` INTEGER NOT NULL REFERENCES `ballot` (`id`),
+    `flag` INTEGER NOT NULL,
+    `description` TEXT NOT NULL,
+    `nominee` INTEGER NOT NULL,
+    `votes` INTEGER,
+    `created_at` DATETIME NOT NULL,
+    UNIQUE (`ballot`, `flag`)
+);
@@ -0,0 +1,4 @@
+ALTER TABLE `ballot` ADD COLUMN `winner` INTEGER REFERENCES `ballot_option` (`id`);
+ALTER TABLE `ballot` ADD COLUMN `runoff_of` INTEGER REFERENCES `ballot` (`id`);
+
+CREATE INDEX `ballot_i1` ON `ballot` (`status`, `deadline`);
@@ -0,0 +1,13 @@
+ALTER TABLE `ballot` ADD COLUMN `mode` INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE `ballot` ADD COLUMN `refund` BOOLEAN NOT NULL DEFAULT 0;
+
+CREATE TABLE `ballot_stake` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `ballot` INTEGER NOT NULL REFERENCES `ballot` (`id`),
+    `option` INTEGER NOT NULL REFERENCES `ballot_option` (`id`),
+    `user` INTEGER NOT NULL REFERENCES `user` (`id`),
+    `amount` INTEGER NOT NULL,
+    `created_at` DATETIME NOT NULL
+);
+
+CREATE INDEX `ballot_stake_i1` ON `ballot_stake` (`ballot`);
@@ -0,0 +1,3 @@
+ALTER TABLE `ballot` ADD COLUMN `name` TEXT NOT NULL DEFAULT 'vote';
+ALTER TABLE `ballot` ADD COLUMN `max_options` INTEGER;
+ALTER TABLE `ballot` ADD COLUMN `max_nominations` INTEGER;
@@ -0,0 +1,11 @@
+ALTER TABLE `ballot` ADD COLUMN `emojis` TEXT;
+ALTER TABLE `ballot` ADD COLUMN `pages` TEXT;
+
+CREATE TABLE `ballot_vote` (
+    `id` INTEGER PRIMARY KEY AUTOINCREMENT NOT NULL,
+    `ballot` INTEGER NOT NULL REFERENCES `ballot` (`id`),
+    `option` INTEGER NOT NULL REFERENCES `ballot_option` (`id`),
+    `voter` INTEGER NOT NULL,
+    `created_at` DATETIME NOT NULL,
+    UNIQUE (`ballot`, `voter`)
+);
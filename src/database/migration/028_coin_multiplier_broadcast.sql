@@ -0,0 +1 @@
+ALTER TABLE `coin_multiplier` ADD COLUMN `broadcast` TEXT;
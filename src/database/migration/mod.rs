@@ -1,6 +1,6 @@
 use crate::error::ServerError;
 
-const VERSION: u32 = 12;
+const VERSION: u32 = 28;
 
 pub fn run_migration(transaction: &rusqlite::Transaction) -> Result<(), ServerError> {
     let mut version =
@@ -37,6 +37,22 @@ pub fn run_migration(transaction: &rusqlite::Transaction) -> Result<(), ServerEr
     migrate!(10, "010_config_tables.sql");
     migrate!(11, "011_anonymous_table.sql");
     migrate!(12, "012_drop_anonymous_content.sql");
+    migrate!(13, "013_coin_ledger_tables.sql");
+    migrate!(14, "014_order_tables.sql");
+    migrate!(15, "015_product_tables.sql");
+    migrate!(16, "016_coin_rule_tables.sql");
+    migrate!(17, "017_coin_quota_tables.sql");
+    migrate!(18, "018_coin_ledger_source.sql");
+    migrate!(19, "019_chat_progress_tables.sql");
+    migrate!(20, "020_stream_session_tables.sql");
+    migrate!(21, "021_coin_ban_tables.sql");
+    migrate!(22, "022_chat_archive_tables.sql");
+    migrate!(23, "023_ballot_tables.sql");
+    migrate!(24, "024_ballot_result.sql");
+    migrate!(25, "025_ballot_mode.sql");
+    migrate!(26, "026_ballot_name.sql");
+    migrate!(27, "027_ballot_menu.sql");
+    migrate!(28, "028_coin_multiplier_broadcast.sql");
 
     if version != VERSION {
         Err(format!(
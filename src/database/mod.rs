@@ -1,8 +1,17 @@
 pub(crate) mod anonymous;
+pub(crate) mod ballot;
+pub(crate) mod ban;
+pub(crate) mod chat;
 pub(crate) mod config;
 pub(crate) mod image;
-mod migration;
+pub(crate) mod ledger;
+pub(crate) mod migration;
+pub(crate) mod multiplier;
+pub(crate) mod order;
 pub(crate) mod penalty;
+pub(crate) mod product;
+pub(crate) mod quota;
+pub(crate) mod stream;
 pub(crate) mod user;
 pub(crate) mod video;
 
@@ -13,6 +22,7 @@ use rusqlite::{Connection, backup::Backup};
 use std::collections::HashMap;
 use std::fs;
 use std::panic::Location;
+use std::path::Path;
 use std::sync::Mutex;
 use std::sync::OnceLock;
 use std::sync::atomic::{AtomicU64, Ordering};
@@ -98,8 +108,16 @@ pub fn init() -> Result<(), ServerError> {
 
     const DATABASE: &str = "data/sqlite.db";
 
+    init_at(DATABASE)
+}
+
+/**
+ * open and migrate the database at `path`, for tools that must not touch
+ * `data/sqlite.db`.
+ */
+pub fn init_at(path: impl AsRef<Path>) -> Result<(), ServerError> {
     // 初始化連接池管理器
-    let manager = SqliteConnectionManager::file(DATABASE).with_init(|c| {
+    let manager = SqliteConnectionManager::file(path).with_init(|c| {
         // 使用 pragma_update 代替 execute，這樣就不會因為有回傳值而報錯
         c.pragma_update(None, "busy_timeout", &"5000")?;
         c.pragma_update(None, "journal_mode", &"WAL")?;
@@ -137,6 +155,24 @@ pub fn backup_database() -> Result<Vec<u8>, ServerError> {
     fs::read(temp_file.path()).map_err(ServerError::from)
 }
 
+/**
+ * open the pool on a throwaway database for tests that go through
+ * `get_connection`. The pool can only be set once per process, so these tests
+ * share it and take turns by holding the returned guard.
+ */
+#[cfg(test)]
+pub(crate) async fn scratch() -> tokio::sync::MutexGuard<'static, ()> {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    static TURN: tokio::sync::Mutex<()> = tokio::sync::Mutex::const_new(());
+
+    INIT.call_once(|| {
+        let directory = tempfile::tempdir().expect("scratch directory").keep();
+        init_at(directory.join("scratch.db")).expect("scratch database");
+    });
+
+    TURN.lock().await
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
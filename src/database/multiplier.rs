@@ -0,0 +1,268 @@
+use crate::{database::stream::StreamSessionIden, error::ServerError};
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction};
+use sea_query::{Cond, Expr, IdenStatic, Order, Query, SqliteQueryBuilder, enum_def};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+/**
+ * scales chat coins between `start_at` and `end_at`, for one stream if
+ * `broadcast` is set.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def(table_name = "coin_multiplier")]
+pub struct Multiplier {
+    pub id: i64,
+    pub name: String,
+    pub factor: f64,
+    pub start_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+    pub broadcast: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for Multiplier {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.get(MultiplierIden::Id.as_str())?,
+            name: value.get(MultiplierIden::Name.as_str())?,
+            factor: value.get(MultiplierIden::Factor.as_str())?,
+            start_at: value.get(MultiplierIden::StartAt.as_str())?,
+            end_at: value.get(MultiplierIden::EndAt.as_str())?,
+            broadcast: value.get(MultiplierIden::Broadcast.as_str())?,
+            created_at: value.get(MultiplierIden::CreatedAt.as_str())?,
+        })
+    }
+}
+
+impl Multiplier {
+    const COLUMNS: [MultiplierIden; 7] = [
+        MultiplierIden::Id,
+        MultiplierIden::Name,
+        MultiplierIden::Factor,
+        MultiplierIden::StartAt,
+        MultiplierIden::EndAt,
+        MultiplierIden::Broadcast,
+        MultiplierIden::CreatedAt,
+    ];
+
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(MultiplierIden::Table)
+            .columns([
+                MultiplierIden::Name,
+                MultiplierIden::Factor,
+                MultiplierIden::StartAt,
+                MultiplierIden::EndAt,
+                MultiplierIden::Broadcast,
+                MultiplierIden::CreatedAt,
+            ])
+            .values([
+                self.name.clone().into(),
+                self.factor.into(),
+                self.start_at.into(),
+                self.end_at.into(),
+                self.broadcast.clone().into(),
+                self.created_at.into(),
+            ])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        self.id = transaction.last_insert_rowid();
+
+        Ok(())
+    }
+
+    pub fn all(transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(MultiplierIden::Table)
+            .order_by(MultiplierIden::StartAt, Order::Desc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let multipliers = statement
+            .query_and_then(&*values.as_params(), |row| Multiplier::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(multipliers)
+    }
+
+    pub fn by_id(id: i64, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(MultiplierIden::Table)
+            .and_where(Expr::col(MultiplierIden::Id).eq(id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let multiplier = statement
+            .query_and_then(&*values.as_params(), |row| Multiplier::try_from(row))?
+            .next();
+
+        Ok(multiplier.transpose()?)
+    }
+
+    /**
+     * the highest factor scheduled at `now` for the chat `chat_id`, or 1.
+     */
+    pub fn factor_at(
+        now: DateTime<Utc>,
+        chat_id: &str,
+        transaction: &Transaction,
+    ) -> Result<f64, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(MultiplierIden::Table)
+            .and_where(Expr::col(MultiplierIden::StartAt).lte(now))
+            .and_where(Expr::col(MultiplierIden::EndAt).gt(now))
+            .cond_where(
+                Cond::any()
+                    .add(Expr::col(MultiplierIden::Broadcast).is_null())
+                    .add(
+                        Expr::col(MultiplierIden::Broadcast).in_subquery(
+                            Query::select()
+                                .column(StreamSessionIden::Broadcast)
+                                .from(StreamSessionIden::Table)
+                                .and_where(Expr::col(StreamSessionIden::ChatId).eq(chat_id))
+                                .to_owned(),
+                        ),
+                    ),
+            )
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let factor = statement
+            .query_and_then(&*values.as_params(), |row| Multiplier::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .map(|m| m.factor)
+            .reduce(f64::max);
+
+        Ok(factor.unwrap_or(1.0))
+    }
+
+    pub fn delete(&self, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::delete()
+            .from_table(MultiplierIden::Table)
+            .and_where(Expr::col(MultiplierIden::Id).eq(self.id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let affected = transaction.execute(&query, &*values.as_params())?;
+        Ok(affected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{self, stream::StreamSession};
+    use chrono::TimeDelta;
+    use rusqlite::Connection;
+
+    fn setup_conn() -> Result<Connection, ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn factor_follows_schedule() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let now = Utc::now();
+
+        let tran = conn.transaction()?;
+        assert_eq!(Multiplier::factor_at(now, "chat", &tran)?, 1.0);
+
+        let mut birthday = Multiplier {
+            id: 0,
+            name: String::from("birthday"),
+            factor: 2.0,
+            start_at: now,
+            end_at: now + TimeDelta::hours(4),
+            broadcast: None,
+            created_at: now,
+        };
+        birthday.insert(&tran)?;
+        let mut bonus = Multiplier {
+            id: 0,
+            name: String::from("bonus"),
+            factor: 1.5,
+            start_at: now + TimeDelta::hours(1),
+            end_at: now + TimeDelta::hours(6),
+            broadcast: None,
+            created_at: now,
+        };
+        bonus.insert(&tran)?;
+
+        assert_eq!(
+            Multiplier::factor_at(now - TimeDelta::hours(1), "chat", &tran)?,
+            1.0
+        );
+        assert_eq!(Multiplier::factor_at(now, "chat", &tran)?, 2.0);
+        assert_eq!(
+            Multiplier::factor_at(now + TimeDelta::hours(2), "chat", &tran)?,
+            2.0
+        );
+        assert_eq!(
+            Multiplier::factor_at(now + TimeDelta::hours(5), "chat", &tran)?,
+            1.5
+        );
+        assert_eq!(
+            Multiplier::factor_at(now + TimeDelta::hours(6), "chat", &tran)?,
+            1.0
+        );
+
+        assert_eq!(birthday.delete(&tran)?, 1);
+        assert!(Multiplier::by_id(birthday.id, &tran)?.is_none());
+        assert_eq!(Multiplier::all(&tran)?.len(), 1);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn stream_multiplier_only_applies_to_its_chat() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let now = Utc::now();
+        let tran = conn.transaction()?;
+
+        let mut session = StreamSession {
+            id: 0,
+            broadcast: String::from("birthday-stream"),
+            chat_id: String::from("birthday-chat"),
+            channel: String::from("channel"),
+            title: String::from("生日會"),
+            start_at: Some(now),
+            end_at: None,
+            peak_viewers: 0,
+            message_count: 0,
+            chatter_count: 0,
+            coin_issued: 0,
+            updated_at: now,
+        };
+        session.insert(&tran)?;
+        Multiplier {
+            id: 0,
+            name: String::from("birthday"),
+            factor: 2.0,
+            start_at: now,
+            end_at: now + TimeDelta::hours(4),
+            broadcast: Some(session.broadcast.clone()),
+            created_at: now,
+        }
+        .insert(&tran)?;
+
+        assert_eq!(Multiplier::factor_at(now, "birthday-chat", &tran)?, 2.0);
+        // a collab streamed at the same time earns as usual
+        assert_eq!(Multiplier::factor_at(now, "other-chat", &tran)?, 1.0);
+        tran.finish()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,424 @@
+use crate::error::ServerError;
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction, types::Type};
+use sea_query::{Expr, IdenStatic, Order as SortOrder, Query, SqliteQueryBuilder, enum_def};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+/**
+ * placed → refund_requested → refunded / rejected, and placed / rejected →
+ * fulfilled / expired. Reopening a refund thread goes back to
+ * refund_requested.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OrderState {
+    Placed = 0,
+    RefundRequested = 1,
+    Refunded = 2,
+    Rejected = 3,
+    Fulfilled = 4,
+    Expired = 5,
+}
+
+impl OrderState {
+    pub fn can_transition(&self, to: OrderState) -> bool {
+        use OrderState::*;
+        matches!(
+            (self, to),
+            (Placed, RefundRequested)
+                | (Placed, Fulfilled)
+                | (Placed, Expired)
+                | (RefundRequested, Refunded)
+                | (RefundRequested, Rejected)
+                | (Refunded, RefundRequested)
+                | (Rejected, RefundRequested)
+                | (Rejected, Fulfilled)
+                | (Rejected, Expired)
+        )
+    }
+}
+
+impl TryFrom<i32> for OrderState {
+    type Error = ServerError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(OrderState::Placed),
+            1 => Ok(OrderState::RefundRequested),
+            2 => Ok(OrderState::Refunded),
+            3 => Ok(OrderState::Rejected),
+            4 => Ok(OrderState::Fulfilled),
+            5 => Ok(OrderState::Expired),
+            _ => Err(ServerError::Internal(format!(
+                "Invalid order state: {}",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def]
+pub struct Order {
+    pub id: i64,
+    pub product: String,
+    pub amount: f64,
+    pub cost: i64,
+    pub buyer: i64,
+    pub content: String,
+    pub message: Option<u64>,
+    pub thread: Option<u64>,
+    pub case_number: Option<String>,
+    pub state: OrderState,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for Order {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        let state: i32 = value.get(OrderIden::State.as_str())?;
+        let state = OrderState::try_from(state).map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Integer, err.to_string().into())
+        })?;
+
+        Ok(Self {
+            id: value.get(OrderIden::Id.as_str())?,
+            product: value.get(OrderIden::Product.as_str())?,
+            amount: value.get(OrderIden::Amount.as_str())?,
+            cost: value.get(OrderIden::Cost.as_str())?,
+            buyer: value.get(OrderIden::Buyer.as_str())?,
+            content: value.get(OrderIden::Content.as_str())?,
+            message: value.get(OrderIden::Message.as_str())?,
+            thread: value.get(OrderIden::Thread.as_str())?,
+            case_number: value.get(OrderIden::CaseNumber.as_str())?,
+            state,
+            created_at: value.get(OrderIden::CreatedAt.as_str())?,
+            updated_at: value.get(OrderIden::UpdatedAt.as_str())?,
+        })
+    }
+}
+
+impl Order {
+    const COLUMNS: [OrderIden; 12] = [
+        OrderIden::Id,
+        OrderIden::Product,
+        OrderIden::Amount,
+        OrderIden::Cost,
+        OrderIden::Buyer,
+        OrderIden::Content,
+        OrderIden::Message,
+        OrderIden::Thread,
+        OrderIden::CaseNumber,
+        OrderIden::State,
+        OrderIden::CreatedAt,
+        OrderIden::UpdatedAt,
+    ];
+
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(OrderIden::Table)
+            .columns([
+                OrderIden::Product,
+                OrderIden::Amount,
+                OrderIden::Cost,
+                OrderIden::Buyer,
+                OrderIden::Content,
+                OrderIden::Message,
+                OrderIden::Thread,
+                OrderIden::CaseNumber,
+                OrderIden::State,
+                OrderIden::CreatedAt,
+                OrderIden::UpdatedAt,
+            ])
+            .values([
+                self.product.clone().into(),
+                self.amount.into(),
+                self.cost.into(),
+                self.buyer.into(),
+                self.content.clone().into(),
+                self.message.into(),
+                self.thread.into(),
+                self.case_number.clone().into(),
+                (self.state as i32).into(),
+                self.created_at.into(),
+                self.updated_at.into(),
+            ])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        self.id = transaction.last_insert_rowid();
+
+        Ok(())
+    }
+
+    pub fn all(transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(OrderIden::Table)
+            .order_by(OrderIden::Id, SortOrder::Desc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let orders = statement
+            .query_and_then(&*values.as_params(), |row| Order::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(orders)
+    }
+
+    pub fn by_id(id: i64, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(OrderIden::Table)
+            .and_where(Expr::col(OrderIden::Id).eq(id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let order = statement
+            .query_and_then(&*values.as_params(), |row| Order::try_from(row))?
+            .next();
+
+        Ok(order.transpose()?)
+    }
+
+    pub fn by_state(
+        state: OrderState,
+        transaction: &Transaction,
+    ) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(OrderIden::Table)
+            .and_where(Expr::col(OrderIden::State).eq(state as i32))
+            .order_by(OrderIden::Id, SortOrder::Asc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let orders = statement
+            .query_and_then(&*values.as_params(), |row| Order::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(orders)
+    }
+
+    /**
+     * refunded orders are not counted
+     */
+    pub fn count_since(
+        buyer: i64,
+        product: &str,
+        since: DateTime<Utc>,
+        transaction: &Transaction,
+    ) -> Result<i64, ServerError> {
+        let (query, values) = Query::select()
+            .expr(Expr::col(OrderIden::Id).count())
+            .from(OrderIden::Table)
+            .and_where(Expr::col(OrderIden::Buyer).eq(buyer))
+            .and_where(Expr::col(OrderIden::Product).eq(product))
+            .and_where(Expr::col(OrderIden::CreatedAt).gte(since))
+            .and_where(Expr::col(OrderIden::State).ne(OrderState::Refunded as i32))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let count = transaction.query_row(&query, &*values.as_params(), |row| row.get(0))?;
+        Ok(count)
+    }
+
+    pub fn by_thread(thread: u64, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(OrderIden::Table)
+            .and_where(Expr::col(OrderIden::Thread).eq(thread))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let order = statement
+            .query_and_then(&*values.as_params(), |row| Order::try_from(row))?
+            .next();
+
+        Ok(order.transpose()?)
+    }
+
+    pub fn by_case_number(
+        case_number: &str,
+        transaction: &Transaction,
+    ) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(OrderIden::Table)
+            .and_where(Expr::col(OrderIden::CaseNumber).eq(case_number))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let order = statement
+            .query_and_then(&*values.as_params(), |row| Order::try_from(row))?
+            .next();
+
+        Ok(order.transpose()?)
+    }
+
+    pub fn update(&self, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::update()
+            .table(OrderIden::Table)
+            .values([
+                (OrderIden::Content, self.content.clone().into()),
+                (OrderIden::Message, self.message.into()),
+                (OrderIden::Thread, self.thread.into()),
+                (OrderIden::CaseNumber, self.case_number.clone().into()),
+                (OrderIden::State, (self.state as i32).into()),
+                (OrderIden::UpdatedAt, self.updated_at.into()),
+            ])
+            .and_where(Expr::col(OrderIden::Id).eq(self.id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let affected = transaction.execute(&query, &*values.as_params())?;
+        Ok(affected)
+    }
+
+    pub fn transition(
+        &mut self,
+        to: OrderState,
+        now: DateTime<Utc>,
+        transaction: &Transaction,
+    ) -> Result<(), ServerError> {
+        if !self.state.can_transition(to) {
+            return Err(ServerError::Internal(format!(
+                "Order #{} cannot move from {:?} to {:?}",
+                self.id, self.state, to
+            )));
+        }
+
+        self.state = to;
+        self.updated_at = now;
+        self.update(transaction)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use rusqlite::Connection;
+
+    fn setup_conn() -> Result<Connection, ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+        Ok(conn)
+    }
+
+    fn sample() -> Order {
+        let now = Utc::now();
+        Order {
+            id: 0,
+            product: String::from("booster"),
+            amount: 3.0,
+            cost: 100,
+            buyer: 1,
+            content: "Some penalty".into(),
+            message: None,
+            thread: None,
+            case_number: None,
+            state: OrderState::Placed,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    #[test]
+    fn insert_and_find() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let mut order = sample();
+
+        let tran = conn.transaction()?;
+        order.insert(&tran)?;
+        tran.commit()?;
+
+        let tran = conn.transaction()?;
+        let mut fetched = Order::by_id(order.id, &tran)?.expect("order");
+        assert_eq!(fetched.product, order.product);
+        assert_eq!(fetched.amount, order.amount);
+        assert_eq!(fetched.cost, order.cost);
+        assert_eq!(fetched.state, OrderState::Placed);
+
+        fetched.message = Some(42);
+        fetched.thread = Some(43);
+        fetched.case_number = Some("ABCDEF".into());
+        fetched.transition(OrderState::RefundRequested, Utc::now(), &tran)?;
+        tran.commit()?;
+
+        let tran = conn.transaction()?;
+        let fetched = Order::by_thread(43, &tran)?.expect("order");
+        assert_eq!(fetched.id, order.id);
+        let fetched = Order::by_case_number("ABCDEF", &tran)?.expect("order");
+        assert_eq!(fetched.id, order.id);
+        assert_eq!(fetched.message, Some(42));
+        assert_eq!(fetched.case_number.as_deref(), Some("ABCDEF"));
+        assert_eq!(fetched.state, OrderState::RefundRequested);
+        assert_eq!(Order::all(&tran)?.len(), 1);
+        assert_eq!(
+            Order::by_state(OrderState::RefundRequested, &tran)?.len(),
+            1
+        );
+        assert!(Order::by_state(OrderState::Placed, &tran)?.is_empty());
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn transition_follows_lifecycle() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let mut order = sample();
+
+        let tran = conn.transaction()?;
+        order.insert(&tran)?;
+        assert!(
+            order
+                .transition(OrderState::Refunded, Utc::now(), &tran)
+                .is_err()
+        );
+        order.transition(OrderState::RefundRequested, Utc::now(), &tran)?;
+        order.transition(OrderState::Rejected, Utc::now(), &tran)?;
+        order.transition(OrderState::Fulfilled, Utc::now(), &tran)?;
+        assert!(
+            order
+                .transition(OrderState::Expired, Utc::now(), &tran)
+                .is_err()
+        );
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn count_since_skips_refunded_and_older() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let now = Utc::now();
+
+        let tran = conn.transaction()?;
+        let mut old = sample();
+        old.created_at = now - chrono::TimeDelta::days(1);
+        old.insert(&tran)?;
+        let mut refunded = sample();
+        refunded.state = OrderState::Refunded;
+        refunded.insert(&tran)?;
+        sample().insert(&tran)?;
+        let mut other = sample();
+        other.product = String::from("overtime");
+        other.insert(&tran)?;
+
+        let since = now - chrono::TimeDelta::hours(1);
+        assert_eq!(Order::count_since(1, "booster", since, &tran)?, 1);
+        assert_eq!(Order::count_since(1, "overtime", since, &tran)?, 1);
+        assert_eq!(Order::count_since(2, "booster", since, &tran)?, 0);
+        tran.finish()?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,317 @@
+use crate::error::ServerError;
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction, types::Type};
+use sea_query::{Expr, IdenStatic, Order, Query, SqliteQueryBuilder, enum_def};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Pricing {
+    /**
+     * `price` per unit, rounded up
+     */
+    Linear = 0,
+    /**
+     * `price` at `min_amount`, doubled for every unit above it
+     */
+    Doubling = 1,
+}
+
+impl TryFrom<i32> for Pricing {
+    type Error = ServerError;
+
+    fn try_from(value: i32) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Pricing::Linear),
+            1 => Ok(Pricing::Doubling),
+            _ => Err(ServerError::Internal(format!(
+                "Invalid product pricing: {}",
+                value
+            ))),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def]
+pub struct Product {
+    pub id: i64,
+    pub name: String,
+    pub display: String,
+    pub unit: String,
+    pub pricing: Pricing,
+    pub price: i64,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub stock: Option<i64>,
+    pub daily_limit: Option<i64>,
+    pub enabled: bool,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for Product {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        let pricing: i32 = value.get(ProductIden::Pricing.as_str())?;
+        let pricing = Pricing::try_from(pricing).map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Integer, err.to_string().into())
+        })?;
+
+        Ok(Self {
+            id: value.get(ProductIden::Id.as_str())?,
+            name: value.get(ProductIden::Name.as_str())?,
+            display: value.get(ProductIden::Display.as_str())?,
+            unit: value.get(ProductIden::Unit.as_str())?,
+            pricing,
+            price: value.get(ProductIden::Price.as_str())?,
+            min_amount: value.get(ProductIden::MinAmount.as_str())?,
+            max_amount: value.get(ProductIden::MaxAmount.as_str())?,
+            stock: value.get(ProductIden::Stock.as_str())?,
+            daily_limit: value.get(ProductIden::DailyLimit.as_str())?,
+            enabled: value.get(ProductIden::Enabled.as_str())?,
+            updated_at: value.get(ProductIden::UpdatedAt.as_str())?,
+        })
+    }
+}
+
+impl Product {
+    const COLUMNS: [ProductIden; 12] = [
+        ProductIden::Id,
+        ProductIden::Name,
+        ProductIden::Display,
+        ProductIden::Unit,
+        ProductIden::Pricing,
+        ProductIden::Price,
+        ProductIden::MinAmount,
+        ProductIden::MaxAmount,
+        ProductIden::Stock,
+        ProductIden::DailyLimit,
+        ProductIden::Enabled,
+        ProductIden::UpdatedAt,
+    ];
+
+    /**
+     * `None` if the amount is not allowed
+     */
+    pub fn cost(&self, amount: f64) -> Option<i64> {
+        if !amount.is_finite() || amount <= 0.0 {
+            return None;
+        }
+        if self.min_amount.is_some_and(|min| amount < min)
+            || self.max_amount.is_some_and(|max| amount > max)
+        {
+            return None;
+        }
+
+        match self.pricing {
+            Pricing::Linear => Some((self.price as f64 * amount).ceil() as i64),
+            Pricing::Doubling => {
+                let base = self.min_amount.unwrap_or(1.0);
+                if amount.fract() != 0.0 || base.fract() != 0.0 {
+                    return None;
+                }
+                let steps = u32::try_from((amount - base) as i64).ok()?;
+                self.price.checked_mul(2i64.checked_pow(steps)?)
+            }
+        }
+    }
+
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(ProductIden::Table)
+            .columns([
+                ProductIden::Name,
+                ProductIden::Display,
+                ProductIden::Unit,
+                ProductIden::Pricing,
+                ProductIden::Price,
+                ProductIden::MinAmount,
+                ProductIden::MaxAmount,
+                ProductIden::Stock,
+                ProductIden::DailyLimit,
+                ProductIden::Enabled,
+                ProductIden::UpdatedAt,
+            ])
+            .values([
+                self.name.clone().into(),
+                self.display.clone().into(),
+                self.unit.clone().into(),
+                (self.pricing as i32).into(),
+                self.price.into(),
+                self.min_amount.into(),
+                self.max_amount.into(),
+                self.stock.into(),
+                self.daily_limit.into(),
+                self.enabled.into(),
+                self.updated_at.into(),
+            ])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        self.id = transaction.last_insert_rowid();
+
+        Ok(())
+    }
+
+    pub fn all(transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(ProductIden::Table)
+            .order_by(ProductIden::Id, Order::Asc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let products = statement
+            .query_and_then(&*values.as_params(), |row| Product::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(products)
+    }
+
+    pub fn by_id(id: i64, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(ProductIden::Table)
+            .and_where(Expr::col(ProductIden::Id).eq(id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let mut rows = statement.query(&*values.as_params())?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(Product::try_from(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn by_name(name: &str, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(ProductIden::Table)
+            .and_where(Expr::col(ProductIden::Name).eq(name))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let mut rows = statement.query(&*values.as_params())?;
+
+        match rows.next()? {
+            Some(row) => Ok(Some(Product::try_from(row)?)),
+            None => Ok(None),
+        }
+    }
+
+    pub fn update(&self, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::update()
+            .table(ProductIden::Table)
+            .values([
+                (ProductIden::Name, self.name.clone().into()),
+                (ProductIden::Display, self.display.clone().into()),
+                (ProductIden::Unit, self.unit.clone().into()),
+                (ProductIden::Pricing, (self.pricing as i32).into()),
+                (ProductIden::Price, self.price.into()),
+                (ProductIden::MinAmount, self.min_amount.into()),
+                (ProductIden::MaxAmount, self.max_amount.into()),
+                (ProductIden::Stock, self.stock.into()),
+                (ProductIden::DailyLimit, self.daily_limit.into()),
+                (ProductIden::Enabled, self.enabled.into()),
+                (ProductIden::UpdatedAt, self.updated_at.into()),
+            ])
+            .and_where(Expr::col(ProductIden::Id).eq(self.id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let updated = transaction.execute(&query, &*values.as_params())?;
+
+        Ok(updated)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use rusqlite::Connection;
+
+    fn setup_conn() -> Result<Connection, ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+        Ok(conn)
+    }
+
+    #[test]
+    fn seeded_products_keep_old_prices() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+
+        let booster = Product::by_name("booster", &tran)?.expect("booster");
+        let expected = [
+            (2, 50),
+            (3, 100),
+            (4, 200),
+            (5, 400),
+            (6, 800),
+            (7, 1_600),
+            (8, 3_200),
+            (9, 6_400),
+        ];
+        for (level, price) in expected {
+            assert_eq!(booster.cost(level as f64), Some(price));
+        }
+        assert_eq!(booster.cost(1.0), None);
+        assert_eq!(booster.cost(10.0), None);
+        assert_eq!(booster.cost(2.5), None);
+
+        let overtime = Product::by_name("overtime", &tran)?.expect("overtime");
+        assert_eq!(overtime.cost(1.0), Some(1_000));
+        assert_eq!(overtime.cost(0.5), Some(500));
+        assert_eq!(overtime.cost(0.0001), Some(1));
+        assert_eq!(overtime.cost(0.0), None);
+        assert_eq!(overtime.cost(-1.0), None);
+
+        assert_eq!(Product::all(&tran)?.len(), 2);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_and_update_product() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+
+        let mut product = Product {
+            id: 0,
+            name: String::from("song"),
+            display: String::from("點歌卡"),
+            unit: String::from("首"),
+            pricing: Pricing::Linear,
+            price: 300,
+            min_amount: Some(1.0),
+            max_amount: Some(3.0),
+            stock: Some(5),
+            daily_limit: Some(1),
+            enabled: true,
+            updated_at: Utc::now(),
+        };
+        product.insert(&tran)?;
+
+        product.enabled = false;
+        product.stock = None;
+        assert_eq!(product.update(&tran)?, 1);
+
+        let fetched = Product::by_id(product.id, &tran)?.expect("product");
+        assert_eq!(fetched.name, "song");
+        assert_eq!(fetched.pricing, Pricing::Linear);
+        assert!(!fetched.enabled);
+        assert_eq!(fetched.stock, None);
+        assert_eq!(fetched.daily_limit, Some(1));
+        assert_eq!(fetched.cost(2.0), Some(600));
+        tran.finish()?;
+
+        Ok(())
+    }
+}
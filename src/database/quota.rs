@@ -0,0 +1,122 @@
+use crate::error::ServerError;
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::{Row, Transaction, types::Type};
+use sea_query::{Expr, IdenStatic, OnConflict, Query, SqliteQueryBuilder, enum_def};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+/**
+ * coins a YouTube user earned from chat on one UTC+8 day.
+ */
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def(table_name = "coin_quota")]
+pub struct Quota {
+    pub youtube: String,
+    pub day: NaiveDate,
+    pub earned: i64,
+    pub last_message: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for Quota {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        let day: String = value.get(QuotaIden::Day.as_str())?;
+        let day = NaiveDate::parse_from_str(&day, "%Y-%m-%d").map_err(|err| {
+            rusqlite::Error::FromSqlConversionFailure(0, Type::Text, Box::new(err))
+        })?;
+
+        Ok(Self {
+            youtube: value.get(QuotaIden::Youtube.as_str())?,
+            day,
+            earned: value.get(QuotaIden::Earned.as_str())?,
+            last_message: value.get(QuotaIden::LastMessage.as_str())?,
+        })
+    }
+}
+
+impl Quota {
+    pub fn by_youtube(
+        youtube: &str,
+        day: NaiveDate,
+        transaction: &Transaction,
+    ) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns([
+                QuotaIden::Youtube,
+                QuotaIden::Day,
+                QuotaIden::Earned,
+                QuotaIden::LastMessage,
+            ])
+            .from(QuotaIden::Table)
+            .and_where(Expr::col(QuotaIden::Youtube).eq(youtube))
+            .and_where(Expr::col(QuotaIden::Day).eq(day.format("%Y-%m-%d").to_string()))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let quota = statement
+            .query_and_then(&*values.as_params(), |row| Quota::try_from(row))?
+            .next();
+
+        Ok(quota.transpose()?)
+    }
+
+    pub fn save(&self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(QuotaIden::Table)
+            .columns([
+                QuotaIden::Youtube,
+                QuotaIden::Day,
+                QuotaIden::Earned,
+                QuotaIden::LastMessage,
+            ])
+            .values([
+                self.youtube.clone().into(),
+                self.day.format("%Y-%m-%d").to_string().into(),
+                self.earned.into(),
+                self.last_message.into(),
+            ])?
+            .on_conflict(
+                OnConflict::columns([QuotaIden::Youtube, QuotaIden::Day])
+                    .update_columns([QuotaIden::Earned, QuotaIden::LastMessage])
+                    .to_owned(),
+            )
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database;
+    use rusqlite::Connection;
+
+    #[test]
+    fn save_overwrites_same_day() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+
+        let day = NaiveDate::from_ymd_opt(2025, 1, 1).expect("date");
+        let mut quota = Quota {
+            youtube: String::from("author"),
+            day,
+            earned: 10,
+            last_message: Utc::now(),
+        };
+        quota.save(&tran)?;
+        quota.earned = 15;
+        quota.save(&tran)?;
+
+        let fetched = Quota::by_youtube("author", day, &tran)?.expect("quota");
+        assert_eq!(fetched.earned, 15);
+        assert!(Quota::by_youtube("author", day.succ_opt().expect("date"), &tran)?.is_none());
+        tran.finish()?;
+
+        Ok(())
+    }
+}
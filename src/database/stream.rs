@@ -0,0 +1,315 @@
+use super::{chat::ProcessedIden, ledger::LedgerIden};
+use crate::error::ServerError;
+use chrono::{DateTime, Utc};
+use rusqlite::{Row, Transaction};
+use sea_query::{Expr, IdenStatic, Order, Query, SqliteQueryBuilder, enum_def};
+use sea_query_rusqlite::RusqliteBinder;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[enum_def(table_name = "stream_session")]
+pub struct StreamSession {
+    pub id: i64,
+    pub broadcast: String,
+    pub chat_id: String,
+    pub channel: String,
+    pub title: String,
+    pub start_at: Option<DateTime<Utc>>,
+    pub end_at: Option<DateTime<Utc>>,
+    pub peak_viewers: i64,
+    pub message_count: i64,
+    pub chatter_count: i64,
+    pub coin_issued: i64,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl TryFrom<&Row<'_>> for StreamSession {
+    type Error = rusqlite::Error;
+
+    fn try_from(value: &Row<'_>) -> Result<Self, Self::Error> {
+        Ok(Self {
+            id: value.get(StreamSessionIden::Id.as_str())?,
+            broadcast: value.get(StreamSessionIden::Broadcast.as_str())?,
+            chat_id: value.get(StreamSessionIden::ChatId.as_str())?,
+            channel: value.get(StreamSessionIden::Channel.as_str())?,
+            title: value.get(StreamSessionIden::Title.as_str())?,
+            start_at: value.get(StreamSessionIden::StartAt.as_str())?,
+            end_at: value.get(StreamSessionIden::EndAt.as_str())?,
+            peak_viewers: value.get(StreamSessionIden::PeakViewers.as_str())?,
+            message_count: value.get(StreamSessionIden::MessageCount.as_str())?,
+            chatter_count: value.get(StreamSessionIden::ChatterCount.as_str())?,
+            coin_issued: value.get(StreamSessionIden::CoinIssued.as_str())?,
+            updated_at: value.get(StreamSessionIden::UpdatedAt.as_str())?,
+        })
+    }
+}
+
+impl StreamSession {
+    const COLUMNS: [StreamSessionIden; 12] = [
+        StreamSessionIden::Id,
+        StreamSessionIden::Broadcast,
+        StreamSessionIden::ChatId,
+        StreamSessionIden::Channel,
+        StreamSessionIden::Title,
+        StreamSessionIden::StartAt,
+        StreamSessionIden::EndAt,
+        StreamSessionIden::PeakViewers,
+        StreamSessionIden::MessageCount,
+        StreamSessionIden::ChatterCount,
+        StreamSessionIden::CoinIssued,
+        StreamSessionIden::UpdatedAt,
+    ];
+
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::insert()
+            .into_table(StreamSessionIden::Table)
+            .columns([
+                StreamSessionIden::Broadcast,
+                StreamSessionIden::ChatId,
+                StreamSessionIden::Channel,
+                StreamSessionIden::Title,
+                StreamSessionIden::StartAt,
+                StreamSessionIden::EndAt,
+                StreamSessionIden::PeakViewers,
+                StreamSessionIden::MessageCount,
+                StreamSessionIden::ChatterCount,
+                StreamSessionIden::CoinIssued,
+                StreamSessionIden::UpdatedAt,
+            ])
+            .values([
+                self.broadcast.clone().into(),
+                self.chat_id.clone().into(),
+                self.channel.clone().into(),
+                self.title.clone().into(),
+                self.start_at.into(),
+                self.end_at.into(),
+                self.peak_viewers.into(),
+                self.message_count.into(),
+                self.chatter_count.into(),
+                self.coin_issued.into(),
+                self.updated_at.into(),
+            ])?
+            .build_rusqlite(SqliteQueryBuilder);
+
+        transaction.execute(&query, &*values.as_params())?;
+
+        self.id = transaction.last_insert_rowid();
+
+        Ok(())
+    }
+
+    pub fn all(transaction: &Transaction) -> Result<Vec<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(StreamSessionIden::Table)
+            .order_by(StreamSessionIden::Id, Order::Desc)
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let sessions = statement
+            .query_and_then(&*values.as_params(), |row| StreamSession::try_from(row))?
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(sessions)
+    }
+
+    pub fn by_id(id: i64, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(StreamSessionIden::Table)
+            .and_where(Expr::col(StreamSessionIden::Id).eq(id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let session = statement
+            .query_and_then(&*values.as_params(), |row| StreamSession::try_from(row))?
+            .next();
+
+        Ok(session.transpose()?)
+    }
+
+    pub fn by_broadcast(
+        broadcast: &str,
+        transaction: &Transaction,
+    ) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns(Self::COLUMNS)
+            .from(StreamSessionIden::Table)
+            .and_where(Expr::col(StreamSessionIden::Broadcast).eq(broadcast))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let session = statement
+            .query_and_then(&*values.as_params(), |row| StreamSession::try_from(row))?
+            .next();
+
+        Ok(session.transpose()?)
+    }
+
+    pub fn update(&self, transaction: &Transaction) -> Result<usize, ServerError> {
+        let (query, values) = Query::update()
+            .table(StreamSessionIden::Table)
+            .values([
+                (StreamSessionIden::Title, self.title.clone().into()),
+                (StreamSessionIden::StartAt, self.start_at.into()),
+                (StreamSessionIden::EndAt, self.end_at.into()),
+                (StreamSessionIden::PeakViewers, self.peak_viewers.into()),
+                (StreamSessionIden::MessageCount, self.message_count.into()),
+                (StreamSessionIden::ChatterCount, self.chatter_count.into()),
+                (StreamSessionIden::CoinIssued, self.coin_issued.into()),
+                (StreamSessionIden::UpdatedAt, self.updated_at.into()),
+            ])
+            .and_where(Expr::col(StreamSessionIden::Id).eq(self.id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let affected = transaction.execute(&query, &*values.as_params())?;
+        Ok(affected)
+    }
+
+    /**
+     * recount messages, chatters and coins from the processed messages and
+     * their ledger entries.
+     */
+    pub fn refresh_stats(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
+        let (query, values) = Query::select()
+            .expr(Expr::col(ProcessedIden::MessageId).count())
+            .expr(Expr::col(ProcessedIden::Author).count_distinct())
+            .from(ProcessedIden::Table)
+            .and_where(Expr::col(ProcessedIden::ChatId).eq(self.chat_id.clone()))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        (self.message_count, self.chatter_count) =
+            transaction.query_row(&query, &*values.as_params(), |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })?;
+
+        let (query, values) = Query::select()
+            .expr(Expr::expr(Expr::col(LedgerIden::Amount).sum()).if_null(0))
+            .from(LedgerIden::Table)
+            .and_where(Expr::col(LedgerIden::Amount).gt(0))
+            .and_where(
+                Expr::col(LedgerIden::Source).in_subquery(
+                    Query::select()
+                        .column(ProcessedIden::MessageId)
+                        .from(ProcessedIden::Table)
+                        .and_where(Expr::col(ProcessedIden::ChatId).eq(self.chat_id.clone()))
+                        .to_owned(),
+                ),
+            )
+            .build_rusqlite(SqliteQueryBuilder);
+
+        self.coin_issued = transaction.query_row(&query, &*values.as_params(), |row| row.get(0))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{self, chat::Processed, ledger::Reason, user::User};
+    use rusqlite::Connection;
+
+    fn setup_conn() -> Result<Connection, ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+        tran.commit()?;
+        Ok(conn)
+    }
+
+    fn sample(broadcast: &str, chat_id: &str) -> StreamSession {
+        StreamSession {
+            id: 0,
+            broadcast: broadcast.to_string(),
+            chat_id: chat_id.to_string(),
+            channel: String::from("channel"),
+            title: String::from("title"),
+            start_at: Some(Utc::now()),
+            end_at: None,
+            peak_viewers: 0,
+            message_count: 0,
+            chatter_count: 0,
+            coin_issued: 0,
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn insert_update_and_find() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+
+        let mut first = sample("first", "chat-1");
+        first.insert(&tran)?;
+        let mut second = sample("second", "chat-2");
+        second.insert(&tran)?;
+
+        first.peak_viewers = 42;
+        first.end_at = Some(Utc::now());
+        assert_eq!(first.update(&tran)?, 1);
+
+        let fetched = StreamSession::by_broadcast("first", &tran)?.expect("session");
+        assert_eq!(fetched.id, first.id);
+        assert_eq!(fetched.peak_viewers, 42);
+        assert!(fetched.end_at.is_some());
+        assert!(StreamSession::by_id(second.id, &tran)?.is_some());
+        assert!(StreamSession::by_broadcast("third", &tran)?.is_none());
+
+        let all = StreamSession::all(&tran)?;
+        assert_eq!(all[0].broadcast, "second");
+        assert_eq!(all.len(), 2);
+        tran.finish()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn refresh_counts_chat_of_the_stream() -> Result<(), ServerError> {
+        let mut conn = setup_conn()?;
+        let tran = conn.transaction()?;
+
+        let mut user = User {
+            id: 0,
+            youtube: String::from("a"),
+            discord: None,
+            coin: 0,
+            display: String::from("a"),
+            updated_at: Utc::now(),
+        };
+        user.insert(&tran)?;
+
+        for (message, chat, author) in [
+            ("m1", "chat-1", "a"),
+            ("m2", "chat-1", "a"),
+            ("m3", "chat-1", "b"),
+            ("m4", "chat-2", "c"),
+        ] {
+            Processed {
+                message_id: message.to_string(),
+                chat_id: chat.to_string(),
+                processed_at: Utc::now(),
+                author: Some(author.to_string()),
+            }
+            .claim(&tran)?;
+        }
+        user.transact_once(10, Reason::Chat, "system", "m1", &tran)?;
+        user.transact_once(100, Reason::Support, "system", "m2", &tran)?;
+        user.transact_once(10, Reason::Chat, "system", "m4", &tran)?;
+        user.transact(-50, Reason::Purchase, "system", None, &tran)?;
+
+        let mut session = sample("first", "chat-1");
+        session.refresh_stats(&tran)?;
+        assert_eq!(session.message_count, 3);
+        assert_eq!(session.chatter_count, 2);
+        assert_eq!(session.coin_issued, 110);
+
+        let mut empty = sample("other", "chat-3");
+        empty.refresh_stats(&tran)?;
+        assert_eq!(empty.message_count, 0);
+        assert_eq!(empty.coin_issued, 0);
+        tran.finish()?;
+
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use super::ledger::{Ledger, Reason};
 use crate::error::ServerError;
 use chrono::{DateTime, Utc};
 use rusqlite::{Row, Transaction};
@@ -40,7 +41,7 @@ impl TryFrom<&Row<'_>> for User {
 }
 
 impl User {
-    pub fn insert(&self, transaction: &Transaction) -> Result<(), ServerError> {
+    pub fn insert(&mut self, transaction: &Transaction) -> Result<(), ServerError> {
         let (query, values) = Query::insert()
             .into_table(UserIden::Table)
             .columns([
@@ -60,6 +61,8 @@ impl User {
             .build_rusqlite(SqliteQueryBuilder);
         transaction.execute(&query, &*values.as_params())?;
 
+        self.id = transaction.last_insert_rowid();
+
         Ok(())
     }
 
@@ -88,6 +91,28 @@ impl User {
         Ok(value.transpose()?)
     }
 
+    pub fn by_id(id: i64, transaction: &Transaction) -> Result<Option<Self>, ServerError> {
+        let (query, values) = Query::select()
+            .columns([
+                UserIden::Id,
+                UserIden::Youtube,
+                UserIden::Discord,
+                UserIden::Coin,
+                UserIden::Display,
+                UserIden::UpdatedAt,
+            ])
+            .from(UserIden::Table)
+            .and_where(Expr::col(UserIden::Id).eq(id))
+            .build_rusqlite(SqliteQueryBuilder);
+
+        let mut statement = transaction.prepare(&query)?;
+        let value = statement
+            .query_and_then(&*values.as_params(), |row| User::try_from(row))?
+            .next();
+
+        Ok(value.transpose()?)
+    }
+
     pub fn by_discord(
         id: impl Into<String>,
         transaction: &Transaction,
@@ -155,6 +180,76 @@ impl User {
 
         Ok(())
     }
+
+    /**
+     * change the balance by `amount` and record it in the coin ledger.
+     * `updated_at` is used as the ledger timestamp.
+     */
+    pub fn transact(
+        &mut self,
+        amount: i64,
+        reason: Reason,
+        actor: impl Into<String>,
+        order: Option<i64>,
+        transaction: &Transaction,
+    ) -> Result<Ledger, ServerError> {
+        self.record(amount, reason, actor.into(), order, None, transaction)
+    }
+
+    /**
+     * like `transact`, but `None` without touching the balance if `source`
+     * was already credited.
+     */
+    pub fn transact_once(
+        &mut self,
+        amount: i64,
+        reason: Reason,
+        actor: impl Into<String>,
+        source: &str,
+        transaction: &Transaction,
+    ) -> Result<Option<Ledger>, ServerError> {
+        if Ledger::by_source(source, transaction)?.is_some() {
+            return Ok(None);
+        }
+
+        let ledger = self.record(
+            amount,
+            reason,
+            actor.into(),
+            None,
+            Some(source.to_string()),
+            transaction,
+        )?;
+        Ok(Some(ledger))
+    }
+
+    fn record(
+        &mut self,
+        amount: i64,
+        reason: Reason,
+        actor: String,
+        order: Option<i64>,
+        source: Option<String>,
+        transaction: &Transaction,
+    ) -> Result<Ledger, ServerError> {
+        self.coin += amount;
+        self.update(transaction)?;
+
+        let mut ledger = Ledger {
+            id: 0,
+            user: self.id,
+            amount,
+            reason,
+            actor,
+            order,
+            balance: self.coin,
+            created_at: self.updated_at,
+            source,
+        };
+        ledger.insert(transaction)?;
+
+        Ok(ledger)
+    }
 }
 
 #[cfg(test)]
@@ -171,7 +266,7 @@ mod tests {
         tran.commit()?;
 
         let tran = conn.transaction()?;
-        let user = User {
+        let mut user = User {
             id: 1,
             youtube: String::from("test_id"),
             discord: None,
@@ -207,6 +302,8 @@ mod tests {
         let tran = conn.transaction()?;
         let u0 = User::by_youtube(u.youtube.clone(), &tran)?.expect("no value");
         let u1 = User::by_discord("123456".to_string(), &tran)?.expect("no value");
+        let u2 = User::by_id(u.id, &tran)?.expect("no value");
+        assert_eq!(u.youtube, u2.youtube);
         assert_eq!(u.coin, u0.coin);
         assert_eq!(u.display, u0.display);
         assert_eq!(u.updated_at, u0.updated_at);
@@ -231,4 +328,38 @@ mod tests {
 
         Ok(())
     }
+
+    #[test]
+    fn transact_once_credits_a_source_once() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+
+        let mut user = User {
+            id: 0,
+            youtube: String::from("test_id"),
+            discord: None,
+            coin: 0,
+            display: String::from("test_user_1"),
+            updated_at: Utc::now(),
+        };
+        user.insert(&tran)?;
+
+        let ledger = user.transact_once(100, Reason::Support, "system", "event-1", &tran)?;
+        assert_eq!(
+            ledger.map(|l| l.source),
+            Some(Some(String::from("event-1")))
+        );
+        assert!(
+            user.transact_once(100, Reason::Support, "system", "event-1", &tran)?
+                .is_none()
+        );
+        user.transact_once(5, Reason::Chat, "system", "event-2", &tran)?;
+
+        let fetched = User::by_id(user.id, &tran)?.expect("user");
+        assert_eq!(fetched.coin, 105);
+        tran.finish()?;
+
+        Ok(())
+    }
 }
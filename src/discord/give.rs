@@ -1,8 +1,9 @@
 use crate::{
     config::CONFIG,
-    database::{self, user::User as CoinUser},
+    database::{self, ledger::Reason, user::User as CoinUser},
     error::ServerError,
 };
+use chrono::Utc;
 use poise;
 
 #[poise::command(slash_command)]
@@ -18,8 +19,14 @@ pub async fn give(
             let transaction = connection.transaction()?;
             if let Some(mut u) = CoinUser::by_youtube(channel.clone(), &transaction)? {
                 display = u.display.clone();
-                u.coin += amount;
-                u.update(&transaction)?;
+                u.updated_at = Utc::now();
+                u.transact(
+                    amount,
+                    Reason::Give,
+                    format!("discord:{}", ctx.author().id.get()),
+                    None,
+                    &transaction,
+                )?;
             }
             transaction.commit()?;
         }
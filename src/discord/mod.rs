@@ -16,13 +16,14 @@ use poise::serenity_prelude::{ClientBuilder, GatewayIntents};
 use poise::{self};
 use serenity::all::{CreateMessage, Http, Message};
 use std::collections::HashMap;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, Once, RwLock};
 use std::time::Duration;
 
 type Data = ();
 type Context<'a> = poise::Context<'a, Data, ServerError>;
 
 static HTTP: OnceLock<Arc<Http>> = OnceLock::new();
+static VOTE_SCHEDULER: Once = Once::new();
 
 #[derive(Debug, Clone, Copy)]
 pub enum Receiver {
@@ -144,9 +145,11 @@ pub async fn run() -> Result<(), ServerError> {
                         )),
                         description_localizations: HashMap::from([(
                             zh_tw.clone(),
-                            String::from("（管理）將討論串案號結單"),
+                            String::from("（管理）將討論串案號結單並執行退款"),
                         )]),
-                        help_text: Some(String::from("（管理）將討論串案號結單")),
+                        help_text: Some(String::from(
+                            "（管理）依判定（全額退款、半價退款或駁回）將水星幣退還購買者，並將討論串案號結單",
+                        )),
                         ..refund::close()
                     },
                     poise::Command {
@@ -158,7 +161,9 @@ pub async fn run() -> Result<(), ServerError> {
                             zh_tw.clone(),
                             String::from("（管理）將討論串案號重新開啟"),
                         )]),
-                        help_text: Some(String::from("（管理）將討論串案號重新開啟")),
+                        help_text: Some(String::from(
+                            "（管理）將討論串案號重新開啟，並撤回已執行的退款",
+                        )),
                         ..refund::reopen()
                     },
                 ],
@@ -172,49 +177,42 @@ pub async fn run() -> Result<(), ServerError> {
                     zh_tw.clone(),
                     String::from("使用水星幣購買商品"),
                 )]),
-                help_text: Some(String::from("購買相關指令")),
+                help_text: Some(String::from(
+                    "使用水星幣購買商品，如懲罰加倍卡、加班台時數卡。輸入商品名稱時會列出目前開放購買的商品。",
+                )),
+                ..purchase::purchase()
+            },
+            poise::Command {
+                name: String::from("vote"),
+                description: Some(String::from("Vote a game to be played in the next stream")),
+                description_localizations: HashMap::from([(
+                    zh_tw.clone(),
+                    String::from("投票下次直播要玩的遊戲"),
+                )]),
+                help_text: Some(String::from("投票相關指令")),
                 subcommands: vec![
                     poise::Command {
-                        name: String::from("booster"),
-                        description: Some(String::from(
-                            "Purchase a booster to amplify the penalty possibility on the wheel.",
-                        )),
+                        name: String::from("create"),
+                        description: Some(String::from("Start a named ballot.")),
                         description_localizations: HashMap::from([(
                             zh_tw.clone(),
-                            String::from("購買懲罰加倍卡"),
+                            String::from("開始一個新的投票"),
                         )]),
                         help_text: Some(String::from(
-                            "購買懲罰加倍卡，使用後可將懲罰在轉盤上的機率加倍。",
+                            "開始一個具名投票，可指定頻道、計票方式、提名上限與標示選項的表情符號。",
                         )),
-                        ..purchase::booster()
+                        ..vote::create()
                     },
                     poise::Command {
-                        name: String::from("overtime"),
-                        description: Some(String::from(
-                            "Purchase an overtime to force 20 stream overtime.",
-                        )),
+                        name: String::from("list"),
+                        description: Some(String::from("List the open ballots.")),
                         description_localizations: HashMap::from([(
                             zh_tw.clone(),
-                            String::from("購買加班台時數"),
+                            String::from("列出進行中的投票"),
                         )]),
-                        help_text: Some(String::from(
-                            "購買加班台時數卡，可以增加惡靈剩餘的加班台時數。",
-                        )),
-                        ..purchase::overtime()
+                        help_text: Some(String::from("列出進行中的投票。")),
+                        ..vote::list()
                     },
-                ],
-                subcommand_required: true,
-                ..purchase::purchase()
-            },
-            poise::Command {
-                name: String::from("vote"),
-                description: Some(String::from("Vote a game to be played in the next stream")),
-                description_localizations: HashMap::from([(
-                    zh_tw.clone(),
-                    String::from("投票下次直播要玩的遊戲"),
-                )]),
-                help_text: Some(String::from("投票相關指令")),
-                subcommands: vec![
                     poise::Command {
                         name: String::from("nominate"),
                         description: Some(String::from("Nominate a option.")),
@@ -256,14 +254,38 @@ pub async fn run() -> Result<(), ServerError> {
                         ..vote::conclude()
                     },
                     poise::Command {
-                        name: String::from("clear"),
-                        description: Some(String::from("Clear the voting options.")),
+                        name: String::from("pick"),
+                        description: Some(String::from("Pick the winner of a tied vote.")),
+                        description_localizations: HashMap::from([(
+                            zh_tw.clone(),
+                            String::from("選出平票投票的勝出選項"),
+                        )]),
+                        help_text: Some(String::from(
+                            "投票平票且規則為管理員選擇時，指定勝出的選項。",
+                        )),
+                        ..vote::pick()
+                    },
+                    poise::Command {
+                        name: String::from("stake"),
+                        description: Some(String::from("Spend Mercury Coins on an option.")),
+                        description_localizations: HashMap::from([(
+                            zh_tw.clone(),
+                            String::from("在水星幣投票中投入水星幣"),
+                        )]),
+                        help_text: Some(String::from(
+                            "在水星幣投票中，使用水星幣支持一個選項。需先連結 YouTube 頻道。",
+                        )),
+                        ..vote::stake()
+                    },
+                    poise::Command {
+                        name: String::from("close"),
+                        description: Some(String::from("Close a ballot without a result.")),
                         description_localizations: HashMap::from([(
                             zh_tw.clone(),
-                            String::from("清空投票選項"),
+                            String::from("關閉投票，不公布結果"),
                         )]),
-                        help_text: Some(String::from("清空投票選項。")),
-                        ..vote::clear()
+                        help_text: Some(String::from("關閉指定的投票，不公布結果。")),
+                        ..vote::close()
                     },
                 ],
                 subcommand_required: true,
@@ -300,6 +322,10 @@ pub async fn run() -> Result<(), ServerError> {
                 println!("Logged in as {}", _ready.user.name);
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
                 command_mentions::initialize(&ctx.http).await?;
+                // 到期的投票自動結算，重新連線時不重複啟動
+                VOTE_SCHEDULER.call_once(|| {
+                    tokio::spawn(vote::schedule(ctx.http.clone()));
+                });
                 Ok(())
             })
         })
@@ -312,6 +338,8 @@ pub async fn run() -> Result<(), ServerError> {
     )
     .framework(framework)
     .event_handler(anonymous::AnonymousEventHandler)
+    .event_handler(purchase::RefundEventHandler)
+    .event_handler(vote::VoteEventHandler)
     .await?;
 
     HTTP.get_or_init(|| client.http.clone());
@@ -1,126 +1,158 @@
-use crate::coin::command::CoinCommandManager;
-use crate::coin::youtube::User;
-use crate::database::{config::Config, get_connection, user::User as CoinUser};
+use crate::coin::shop::{self, Outcome};
+use crate::database::{
+    config::Config,
+    get_connection,
+    order::{Order, OrderState},
+    product::Product,
+    user::User as CoinUser,
+};
 use crate::error::ServerError;
-use chrono::Days;
+use chrono::{DateTime, Utc};
 use poise::{self, CreateReply};
 use rand::distributions::{Alphanumeric, DistString};
+use rusqlite::{Transaction, TransactionBehavior};
 use serenity::all::{
-    ChannelId, CreateActionRow, CreateButton, CreateInteractionResponse,
-    CreateInteractionResponseMessage, CreateMessage, CreateThread, EditMessage,
+    AutocompleteChoice, ChannelId, ComponentInteraction, Context, CreateActionRow, CreateButton,
+    CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage,
+    CreateMessage, CreateThread, EditMessage, EventHandler, Http, Interaction, MessageId, Ready,
 };
+use std::sync::{Arc, Once};
 use std::time::Duration;
 
+static REFUND_SWEEPER: Once = Once::new();
+
 pub enum CommandReply {
     Success,
-    InvalidInput,
-    InsufficientFunds,
+    Failure(String),
     NoUserFound,
 }
 
-fn find_coin_user(msg_author_id: String) -> Result<Option<String>, ServerError> {
-    let mut connection = get_connection()?; // 自動獲取
-    let transaction = connection.transaction()?;
-    let user_id = match CoinUser::by_discord(msg_author_id, &transaction)? {
-        Some(u) => Some(u.youtube),
-        None => None,
-    };
-    Ok(user_id)
+fn refund_button(order: i64) -> CreateButton {
+    CreateButton::new(format!("refund:{}", order))
 }
 
-#[poise::command(slash_command)]
-pub async fn purchase(ctx: super::Context<'_>) -> Result<(), ServerError> {
-    ctx.say("Purchase Entrypoint.").await?;
+fn attach_message(order: i64, message: u64) -> Result<(), ServerError> {
+    let mut connection = get_connection()?;
+    let transaction = connection.transaction()?;
+    if let Some(mut order) = Order::by_id(order, &transaction)? {
+        order.message = Some(message);
+        order.update(&transaction)?;
+    }
+    transaction.commit()?;
     Ok(())
 }
 
+async fn autocomplete_product(_ctx: super::Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let products = (|| {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        Product::all(&transaction)
+    })();
+
+    match products {
+        Ok(products) => products
+            .into_iter()
+            .filter(|p| p.enabled && (p.name.contains(partial) || p.display.contains(partial)))
+            .map(|p| AutocompleteChoice::new(format!("{} ({})", p.display, p.name), p.name))
+            .collect(),
+        Err(err) => {
+            log::error!("list products failed: {:?}", err);
+            Vec::new()
+        }
+    }
+}
+
 #[poise::command(slash_command)]
-pub async fn booster(
+pub async fn purchase(
     ctx: super::Context<'_>,
-    amp: u32,
-    penalty_content: String,
+    #[description = "The product to purchase"]
+    #[description_localized("zh-TW", "要購買的商品")]
+    #[autocomplete = "autocomplete_product"]
+    product: String,
+    #[description = "Amount, e.g. booster level or overtime hours"]
+    #[description_localized("zh-TW", "數量，如加倍倍率或加班時數")]
+    amount: f64,
+    #[description = "Content of the order"]
+    #[description_localized("zh-TW", "訂單內容")]
+    content: String,
 ) -> Result<(), ServerError> {
-    let author = ctx.author();
-
-    let channel_coin = {
-        let mut connection = crate::database::get_connection()?;
-        let transaction = connection.transaction()?;
-        let channel_outcome = if let Some(text) = Config::ChannelCoin.get(&transaction)?
-            && let Ok(channel) = text.parse::<u64>()
-        {
-            channel
-        } else {
-            return Err(ServerError::Internal(String::from(
-                "Parse ChannelCoin channel id to u64 failed.",
-            )));
-        };
-        transaction.commit()?;
-        channel_outcome
-    };
+    let author_id = ctx.author().id.get();
+    let channel_coin = channel_coin()?;
 
     // 在一个同步块里处理所有 DB 逻辑，生成好要发送的 message
-    let (reply, content) = 'ret: {
-        if amp <= 0 || amp > 9 {
-            log::warn!("{}: incorrect level", ctx.invocation_string());
-            break 'ret (CommandReply::InvalidInput, None);
-        }
-
+    let (reply, notice) = 'ret: {
         let mut connection = get_connection()?;
         let transaction = connection.transaction()?;
 
-        let user_id = match find_coin_user(author.id.get().to_string())? {
-            Some(uid) => uid,
-            None => String::from("User not found"),
-        };
-
-        let mut record = match User::by_youtube(user_id, &transaction)? {
+        let mut record = match CoinUser::by_discord(author_id.to_string(), &transaction)? {
             Some(r) => r,
-            None => {
-                break 'ret (CommandReply::NoUserFound, None);
-            }
+            None => break 'ret (CommandReply::NoUserFound, None),
         };
 
-        if record.discord.is_none() {
-            break 'ret (CommandReply::NoUserFound, None);
-        }
-
-        let coin_config = CoinCommandManager::new().config;
-        let cost = coin_config.booster_cost(amp.into());
-
-        if record.coin < cost {
-            break 'ret (CommandReply::InsufficientFunds, None);
-        }
-        let now = chrono::Utc::now();
-        let due = now.checked_add_days(Days::new(3)).unwrap();
-
-        record.coin -= cost;
-        record.updated_at = now;
-        record.update(&transaction)?;
-
-        println!(
-            "[-] {} buy a level-{} booster for {}",
-            record.display, amp, penalty_content
-        );
-
+        let outcome = shop::place(
+            &product,
+            amount,
+            &content,
+            &mut record,
+            &format!("discord:{}", author_id),
+            Utc::now(),
+            &transaction,
+        )?;
         transaction.commit()?;
 
-        (
-            CommandReply::Success,
-            Some(format!(
-                "\
-# 懲罰加倍 #
-> ## 「{}」 x **{}** 倍 ##
-- 來自： {} (`{}`)，結餘 **{}** 水星幣。
--# 如有疑義，或未抽中想領取半價退款，請在 72 小時內（<t:{}:f> 之前）執行退款流程。
-",
-                penalty_content,
-                amp,
-                record.display,
-                record.youtube,
-                record.coin,
-                due.timestamp()
-            )),
-        )
+        match outcome {
+            Outcome::Placed(order, product) => {
+                println!(
+                    "[-] {} buy {} x {} for {}",
+                    record.display, product.name, amount, content
+                );
+                let notice = shop::notice(&order, &product, &record, false);
+                (CommandReply::Success, Some((order.id, notice)))
+            }
+            Outcome::NotFound => (
+                CommandReply::Failure(format!(
+                    "找不到商品 `{}`，或該商品目前未開放購買。",
+                    product
+                )),
+                None,
+            ),
+            Outcome::InvalidAmount(product) => (
+                CommandReply::Failure(format!(
+                    "您輸入了無效的{}數量。{}。",
+                    product.display,
+                    shop::amount_range(&product)
+                )),
+                None,
+            ),
+            Outcome::SoldOut(product) => (
+                CommandReply::Failure(format!("**購買失敗。**\n{}已售完。", product.display)),
+                None,
+            ),
+            Outcome::DailyLimit(product) => (
+                CommandReply::Failure(format!(
+                    "**購買失敗。**\n您今天已達{}的購買上限（每日 {} 筆）。",
+                    product.display,
+                    product.daily_limit.unwrap_or_default()
+                )),
+                None,
+            ),
+            Outcome::InsufficientFunds(product) => (
+                CommandReply::Failure(format!(
+                    "**購買失敗。**\n您的水星幣不足以購買 {} {} 的{}。您可以使用 {} 指令查詢餘額。",
+                    amount,
+                    product.unit,
+                    product.display,
+                    super::command_mentions::get("coin").unwrap_or("/coin")
+                )),
+                None,
+            ),
+            Outcome::Frozen => (
+                CommandReply::Failure(String::from(
+                    "**購買失敗。**\n您的水星幣已被凍結，請聯絡管理員。",
+                )),
+                None,
+            ),
+        }
     };
 
     match reply {
@@ -128,26 +160,9 @@ pub async fn booster(
             ctx.send(CreateReply::default().content("交易成功！").ephemeral(true))
                 .await?;
         }
-        CommandReply::InvalidInput => {
-            ctx.send(
-                CreateReply::default()
-                    .content("您輸入了無效的倍率。請輸入 1 ~ 9 的整數。")
-                    .ephemeral(true),
-            )
-            .await?;
-        }
-        CommandReply::InsufficientFunds => {
-            ctx.send(
-                CreateReply::default()
-                    .content(
-                        format!(
-                            "**購買失敗。**\n您的水星幣不足以購買 x{} 的加倍倍率。您可以使用 {} 指令查詢餘額。",
-                            amp,
-                            super::command_mentions::get("coin").unwrap_or("/coin")
-                        )
-                    )
-                    .ephemeral(true)
-            ).await?;
+        CommandReply::Failure(content) => {
+            ctx.send(CreateReply::default().content(content).ephemeral(true))
+                .await?;
         }
         CommandReply::NoUserFound => {
             ctx.send(
@@ -164,334 +179,421 @@ pub async fn booster(
     }
 
     // 此处已经不再持有 rusqlite::Transaction，可以安全 .await
-    if let Some(content) = content {
-        let mut message = ChannelId::from(channel_coin)
+    if let Some((order, content)) = notice {
+        let message = ChannelId::from(channel_coin)
             .send_message(
                 &ctx.serenity_context().http,
                 CreateMessage::new()
                     .content(&content)
-                    .button(CreateButton::new("refund").label("退款申请")),
+                    .button(refund_button(order).label("退款申请")),
             )
             .await?;
+        attach_message(order, message.id.get())?;
+    }
 
-        let interaction = message
-            .await_component_interaction(&ctx.serenity_context().shard)
-            .timeout(Duration::from_secs(72 * 60 * 60))
-            .await; // 72 level
-
-        if let Some(interaction) = interaction {
-            // if not the buyer clicking button
-            if interaction.user.id.get() != author.id.get().clone() {
-                ctx.send(
-                    CreateReply::default()
-                        .content(format!(
-                            "**您並非這筆訂單的購買者。**您僅有權限退款自己的訂單。"
-                        ))
-                        .ephemeral(true),
-                )
-                .await?;
-                return Ok(());
-            }
-
-            message
-                .edit(
-                    &ctx.serenity_context().http,
-                    EditMessage::new().components(vec![CreateActionRow::Buttons(vec![
-                        CreateButton::new("refund")
-                            .label("已申請退款")
-                            .disabled(true),
-                    ])]),
-                )
-                .await?;
-
-            let _ = match interaction
-                .create_response(
-                    &ctx,
-                    // This time we dont edit the message but reply to it
-                    CreateInteractionResponse::Message(
-                        CreateInteractionResponseMessage::default()
-                            // Make the message hidden for other users by setting `ephemeral(true)`.
-                            .ephemeral(true)
-                            .content(format!("开始处理退款")),
-                    ),
-                )
-                .await
-            {
-                Ok(_) => (),
-                Err(err) => {
-                    ctx.send(
-                        CreateReply::default()
-                            .content(format!(
-                                "**退款流程啟動失敗。**請稍後再試一次。\n-# Error：{}",
-                                err
-                            ))
-                            .ephemeral(true),
-                    )
-                    .await?;
-                    return Ok(());
-                }
-            };
+    Ok(())
+}
 
-            let author = ctx.author().clone();
-            let case_number = Alphanumeric.sample_string(&mut rand::thread_rng(), 6);
+fn channel_coin() -> Result<u64, ServerError> {
+    let mut connection = get_connection()?;
+    let transaction = connection.transaction()?;
+    if let Some(text) = Config::ChannelCoin.get(&transaction)?
+        && let Ok(channel) = text.parse::<u64>()
+    {
+        Ok(channel)
+    } else {
+        Err(ServerError::Internal(String::from(
+            "Parse ChannelCoin channel id to u64 failed.",
+        )))
+    }
+}
 
-            let refund = ChannelId::new(channel_coin) //channel_coin 1248793225767026758
-                .create_thread_from_message(
-                    ctx.http(),
-                    message.id,
-                    CreateThread::new(format!("退款討論串 {}-{}", author.name, case_number)),
-                )
-                .await?;
+enum RefundCheck {
+    Open(Order, String),
+    NotFound,
+    NotBuyer,
+    Closed,
+}
 
-            let content = format!(
-                "\
-退款商品類型：懲罰加倍卡
-申請人 <@{}>
-案號 {}
-請說明您希望退款的理由。
-=============在這則訊息以下開始處理退款=============
-",
-                author.id.get(),
-                case_number
-            );
+/**
+ * move the order to refund_requested before the thread is opened, so a
+ * double click opens no second thread.
+ */
+fn claim_refund(
+    order: i64,
+    clicker: u64,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<RefundCheck, ServerError> {
+    let mut order = match Order::by_id(order, transaction)? {
+        Some(order) => order,
+        None => return Ok(RefundCheck::NotFound),
+    };
+    let buyer = CoinUser::by_id(order.buyer, transaction)?;
+
+    if buyer.and_then(|b| b.discord) != Some(clicker) {
+        Ok(RefundCheck::NotBuyer)
+    } else if order.state != OrderState::Placed || order.created_at + shop::REFUND_WINDOW < now {
+        Ok(RefundCheck::Closed)
+    } else {
+        order.transition(OrderState::RefundRequested, now, transaction)?;
+        let display = Product::by_name(&order.product, transaction)?
+            .map(|p| p.display)
+            .unwrap_or_else(|| order.product.clone());
+        Ok(RefundCheck::Open(order, display))
+    }
+}
 
-            refund
-                .send_message(
-                    &ctx.serenity_context().http,
-                    CreateMessage::new().content(content),
-                )
-                .await?;
+/**
+ * undo `claim_refund` when the thread could not be opened.
+ */
+fn release_refund(
+    order: i64,
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<bool, ServerError> {
+    match Order::by_id(order, transaction)? {
+        Some(mut order) if order.state == OrderState::RefundRequested && order.thread.is_none() => {
+            // 不是狀態轉移，而是撤銷剛才的領取
+            order.state = OrderState::Placed;
+            order.updated_at = now;
+            order.update(transaction)?;
+            Ok(true)
         }
+        _ => Ok(false),
     }
+}
 
+fn attach_thread(order: i64, thread: u64, case_number: &str) -> Result<(), ServerError> {
+    let mut connection = get_connection()?;
+    let transaction = connection.transaction()?;
+    if let Some(mut order) = Order::by_id(order, &transaction)? {
+        order.thread = Some(thread);
+        order.case_number = Some(case_number.to_string());
+        order.update(&transaction)?;
+    }
+    transaction.commit()?;
     Ok(())
 }
 
-#[poise::command(slash_command)]
-pub async fn overtime(
-    ctx: super::Context<'_>,
-    hours: f32,
-    content: String,
+async fn reply_ephemeral(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: impl Into<String>,
 ) -> Result<(), ServerError> {
-    let author_id = ctx.author().id.get();
-
-    let channel_coin = {
-        let mut connection = crate::database::get_connection()?;
-        let transaction = connection.transaction()?;
-        let channel_outcome = if let Some(text) = Config::ChannelCoin.get(&transaction)?
-            && let Ok(channel) = text.parse::<u64>()
-        {
-            channel
-        } else {
-            return Err(ServerError::Internal(String::from(
-                "Parse ChannelCoin channel id to u64 failed.",
-            )));
-        };
-        transaction.commit()?;
-        channel_outcome
-    };
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .ephemeral(true)
+                    .content(content),
+            ),
+        )
+        .await?;
+    Ok(())
+}
 
-    // 在一个同步块里处理所有 DB 逻辑，生成好要发送的 message
-    let (reply, content) = 'ret: {
-        if hours <= 0.0 {
-            log::warn!("{}: incorrect hours", ctx.invocation_string());
-            break 'ret (CommandReply::InvalidInput, None);
-        }
+async fn report(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: impl Into<String>,
+) -> Result<(), ServerError> {
+    let content = content.into();
+    if reply_ephemeral(ctx, component, content.clone())
+        .await
+        .is_err()
+    {
+        component
+            .create_followup(
+                &ctx.http,
+                CreateInteractionResponseFollowup::new()
+                    .ephemeral(true)
+                    .content(content),
+            )
+            .await?;
+    }
+    Ok(())
+}
 
+pub async fn handle_component(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    order: i64,
+) -> Result<(), ServerError> {
+    let claimed = {
         let mut connection = get_connection()?;
-        let transaction = connection.transaction()?;
-
-        let mut record = match User::by_discord(author_id.to_string(), &transaction)? {
-            Some(r) => r,
-            None => {
-                break 'ret (CommandReply::NoUserFound, None);
-            }
-        };
-
-        if record.discord.is_none() {
-            break 'ret (CommandReply::NoUserFound, None);
-        }
-
-        let cost = (hours * 1000.0).ceil() as i64; // 1000 水星幣每小時
-        if record.coin < cost {
-            break 'ret (CommandReply::InsufficientFunds, None);
-        }
-
-        let now = chrono::Utc::now();
-        let due = now.checked_add_days(Days::new(3)).unwrap();
-
-        record.coin -= cost;
-        record.updated_at = now;
-        record.update(&transaction)?;
-
-        println!(
-            "[-] {} buy a(n) {}-hour overtime for {}",
-            record.display, hours, content
-        );
-
+        // 先取得寫入鎖，同時按下的第二次互動會等到第一次提交後才讀取
+        let transaction = connection.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let claimed = claim_refund(order, component.user.id.get(), Utc::now(), &transaction)?;
         transaction.commit()?;
-
-        (
-            CommandReply::Success,
-            Some(format!(
-                "\
-# 加班台時數卡 #
-## + {} 小時 ##
-- 來自：{} (`{}`)，結餘 **{}** 水星幣。
-- 給惡靈的加班台留言：
-> {}
--# 如有疑義請在 72 小時內（<t:{}:f> 之前）執行退款流程。
-",
-                hours,
-                record.display,
-                record.youtube,
-                record.coin,
-                content,
-                due.timestamp()
-            )),
-        )
+        claimed
     };
-
-    match reply {
-        CommandReply::Success => {
-            ctx.send(CreateReply::default().content("交易成功！").ephemeral(true))
-                .await?;
+    let (order, display) = match claimed {
+        RefundCheck::Open(order, display) => (order, display),
+        RefundCheck::NotFound => {
+            return reply_ephemeral(ctx, component, "**找不到這筆訂單。**").await;
         }
-        CommandReply::InvalidInput => {
-            ctx.send(
-                CreateReply::default()
-                    .content("您輸入了無效的時數。請輸入大於 0 的正數（可以有小數）。")
-                    .ephemeral(true),
+        RefundCheck::NotBuyer => {
+            return reply_ephemeral(
+                ctx,
+                component,
+                "**您並非這筆訂單的購買者。**您僅有權限退款自己的訂單。",
             )
-            .await?;
+            .await;
         }
-        CommandReply::InsufficientFunds => {
-            ctx.send(
-                CreateReply::default()
-                    .content(
-                        format!(
-                            "**購買失敗。**\n您的水星幣不足以購買 {} 小時的加班台時數卡。您可以使用 {} 指令查詢餘額。",
-                            hours,
-                            super::command_mentions::get("coin").unwrap_or("/coin")
-                        )
-                    )
-                    .ephemeral(true)
-            ).await?;
+        RefundCheck::Closed => {
+            return reply_ephemeral(ctx, component, "**這筆訂單已無法申請退款。**").await;
         }
-        CommandReply::NoUserFound => {
-            ctx.send(
-                CreateReply::default()
-                    .content(
-                        format!(
-                            "**找不到您的 Discord 用戶記錄。**\n請先使用 {} 將 Discord 帳號關聯到您的 YouTube 頻道，才能在 Discord 使用水星幣購買。",
-                            super::command_mentions::get("link").unwrap_or("/link")
-                        )
-                    )
-                    .ephemeral(true)
-            ).await?;
-        }
-    }
-
-    // 此处已经不再持有 rusqlite::Transaction，可以安全 .await
-    if let Some(content) = content {
-        let mut message = ChannelId::from(channel_coin)
-            .send_message(
-                &ctx.serenity_context().http,
-                CreateMessage::new()
-                    .content(&content)
-                    .button(CreateButton::new("refund").label("退款申请")),
-            )
-            .await?;
-
-        let interaction = message
-            .await_component_interaction(&ctx.serenity_context().shard)
-            .timeout(Duration::from_secs(72 * 60 * 60))
-            .await; // 72 hours
-
-        if let Some(interaction) = interaction {
-            // if not the buyer clicking button
-            if interaction.user.id.get() != author_id.clone() {
-                ctx.send(
-                    CreateReply::default()
-                        .content(format!(
-                            "**您並非這筆訂單的購買者。**您僅有權限退款自己的訂單。"
-                        ))
-                        .ephemeral(true),
-                )
-                .await?;
-                return Ok(());
-            }
+    };
 
-            message
+    let opened = open_thread(ctx, component, &order, &display).await;
+    if opened.is_err() {
+        let released = {
+            let mut connection = get_connection()?;
+            let transaction = connection.transaction()?;
+            let released = release_refund(order.id, Utc::now(), &transaction)?;
+            transaction.commit()?;
+            released
+        };
+        // 按鈕可能已被停用，恢復讓買家重試
+        if released
+            && let Err(err) = component
+                .message
+                .clone()
                 .edit(
-                    &ctx.serenity_context().http,
+                    &ctx.http,
                     EditMessage::new().components(vec![CreateActionRow::Buttons(vec![
-                        CreateButton::new("refund")
-                            .label("已申請退款")
-                            .disabled(true),
+                        refund_button(order.id).label("退款申请"),
                     ])]),
                 )
-                .await?;
-
-            let _ = match interaction
-                .create_response(
-                    &ctx,
-                    // This time we dont edit the message but reply to it
-                    CreateInteractionResponse::Message(
-                        CreateInteractionResponseMessage::default()
-                            // Make the message hidden for other users by setting `ephemeral(true)`.
-                            .ephemeral(true)
-                            .content(format!("开始处理退款")),
-                    ),
-                )
                 .await
-            {
-                Ok(_) => (),
-                Err(err) => {
-                    ctx.send(
-                        CreateReply::default()
-                            .content(format!(
-                                "**退款流程啟動失敗。**請稍後再試一次。\n-# Error：{}",
-                                err
-                            ))
-                            .ephemeral(true),
-                    )
-                    .await?;
-                    return Ok(());
-                }
-            };
+        {
+            log::warn!(
+                "fail to restore refund button of order #{}: {}",
+                order.id,
+                err
+            );
+        }
+    }
 
-            let author = ctx.author().clone();
-            let case_number = Alphanumeric.sample_string(&mut rand::thread_rng(), 6);
+    opened
+}
 
-            let refund = ChannelId::new(channel_coin) //channel_coin 1248793225767026758
-                .create_thread_from_message(
-                    ctx.http(),
-                    message.id,
-                    CreateThread::new(format!("退款討論串 {}-{}", author.name, case_number)),
-                )
-                .await?;
+async fn open_thread(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    order: &Order,
+    display: &str,
+) -> Result<(), ServerError> {
+    let mut message = *component.message.clone();
+    message
+        .edit(
+            &ctx.http,
+            EditMessage::new().components(vec![CreateActionRow::Buttons(vec![
+                refund_button(order.id).label("已申請退款").disabled(true),
+            ])]),
+        )
+        .await?;
+
+    reply_ephemeral(ctx, component, "开始处理退款").await?;
+
+    let author = &component.user;
+    let case_number = Alphanumeric.sample_string(&mut rand::thread_rng(), 6);
 
-            let content = format!(
-                "\
-退款商品類型：加班台時數卡
+    let refund = message
+        .channel_id
+        .create_thread_from_message(
+            &ctx.http,
+            message.id,
+            CreateThread::new(format!("退款討論串 {}-{}", author.name, case_number)),
+        )
+        .await?;
+    attach_thread(order.id, refund.id.get(), &case_number)?;
+
+    let content = format!(
+        "\
+退款商品類型：{}
 申請人 <@{}>
 案號 {}
 請說明您希望退款的理由。
 =============在這則訊息以下開始處理退款=============
 ",
-                author.id.get(),
-                case_number
-            );
+        display,
+        author.id.get(),
+        case_number
+    );
 
-            refund
-                .send_message(
-                    &ctx.serenity_context().http,
-                    CreateMessage::new().content(content),
-                )
-                .await?;
+    refund
+        .send_message(&ctx.http, CreateMessage::new().content(content))
+        .await?;
+
+    Ok(())
+}
+
+/**
+ * expire the placed orders past their refund window. Orders from YouTube
+ * chat have no button and are only marked expired.
+ */
+async fn expire_refunds(http: &Http) -> Result<(), ServerError> {
+    let due = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let now = Utc::now();
+        Order::by_state(OrderState::Placed, &transaction)?
+            .into_iter()
+            .filter(|o| o.created_at + shop::REFUND_WINDOW <= now)
+            .collect::<Vec<_>>()
+    };
+
+    if due.is_empty() {
+        return Ok(());
+    }
+
+    let channel = ChannelId::from(channel_coin()?);
+    for order in due {
+        if let Some(message) = order.message {
+            match channel.message(http, MessageId::from(message)).await {
+                Ok(mut message) if !message.components.is_empty() => {
+                    message
+                        .edit(
+                            http,
+                            EditMessage::new().components(vec![CreateActionRow::Buttons(vec![
+                                refund_button(order.id).label("退款期限已過").disabled(true),
+                            ])]),
+                        )
+                        .await?;
+                }
+                Ok(_) => (),
+                Err(err) => log::warn!("refund message of order #{} not found: {}", order.id, err),
+            }
         }
+
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        if let Some(mut order) = Order::by_id(order.id, &transaction)?
+            && order.state == OrderState::Placed
+        {
+            order.transition(OrderState::Expired, Utc::now(), &transaction)?;
+        }
+        transaction.commit()?;
     }
 
     Ok(())
 }
+
+pub struct RefundEventHandler;
+
+#[serenity::async_trait]
+impl EventHandler for RefundEventHandler {
+    async fn ready(&self, ctx: Context, _: Ready) {
+        // 重啟後由資料庫的訂單恢復退款期限，只需啟動一次
+        REFUND_SWEEPER.call_once(|| {
+            let http: Arc<Http> = ctx.http.clone();
+            tokio::spawn(async move {
+                loop {
+                    if let Err(err) = expire_refunds(&http).await {
+                        log::error!("expire refunds failed: {:?}", err);
+                    }
+                    tokio::time::sleep(Duration::from_secs(60)).await;
+                }
+            });
+        });
+    }
+
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Component(component) = interaction
+            && let Some(order) = component.data.custom_id.strip_prefix("refund:")
+            && let Ok(order) = order.parse::<i64>()
+            && let Err(err) = handle_component(&ctx, &component, order).await
+        {
+            log::error!("refund button of order #{} failed: {:?}", order, err);
+            let _ = report(
+                &ctx,
+                &component,
+                format!("**退款流程啟動失敗。**請稍後再試一次。\n-# Error：{}", err),
+            )
+            .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{self, ledger::Reason};
+    use rusqlite::Connection;
+
+    #[test]
+    fn refund_is_claimed_once() -> Result<(), ServerError> {
+        let mut conn = Connection::open_in_memory()?;
+        let tran = conn.transaction()?;
+        database::migration::run_migration(&tran)?;
+
+        let now = Utc::now();
+        let mut buyer = CoinUser {
+            id: 0,
+            youtube: String::from("buyer"),
+            discord: Some(1),
+            coin: 0,
+            display: String::from("Buyer"),
+            updated_at: now,
+        };
+        buyer.insert(&tran)?;
+        buyer.transact(500, Reason::Give, "discord:0", None, &tran)?;
+        let mut order = Order {
+            id: 0,
+            product: String::from("booster"),
+            amount: 4.0,
+            cost: 200,
+            buyer: buyer.id,
+            content: String::from("penalty"),
+            message: Some(5),
+            thread: None,
+            case_number: None,
+            state: OrderState::Placed,
+            created_at: now,
+            updated_at: now,
+        };
+        order.insert(&tran)?;
+
+        assert!(matches!(
+            claim_refund(order.id, 2, now, &tran)?,
+            RefundCheck::NotBuyer
+        ));
+        match claim_refund(order.id, 1, now, &tran)? {
+            RefundCheck::Open(order, display) => {
+                assert_eq!(order.state, OrderState::RefundRequested);
+                assert_eq!(display, "懲罰加倍卡");
+            }
+            _ => panic!("order should be open for a refund"),
+        }
+        // 第二次按下時訂單已被領取
+        assert!(matches!(
+            claim_refund(order.id, 1, now, &tran)?,
+            RefundCheck::Closed
+        ));
+
+        // 開討論串失敗時撤銷領取，買家可再按一次
+        assert!(release_refund(order.id, now, &tran)?);
+        assert!(matches!(
+            claim_refund(order.id, 1, now, &tran)?,
+            RefundCheck::Open(..)
+        ));
+        let mut claimed = Order::by_id(order.id, &tran)?.expect("order");
+        claimed.thread = Some(9);
+        claimed.update(&tran)?;
+        assert!(!release_refund(order.id, now, &tran)?);
+        assert_eq!(
+            Order::by_id(order.id, &tran)?.expect("order").state,
+            OrderState::RefundRequested
+        );
+        assert!(matches!(
+            claim_refund(order.id + 1, 1, now, &tran)?,
+            RefundCheck::NotFound
+        ));
+        tran.finish()?;
+
+        Ok(())
+    }
+}
@@ -1,8 +1,63 @@
-use crate::{config::CONFIG, error::ServerError};
+use crate::{
+    coin::refund,
+    config::CONFIG,
+    database::{
+        get_connection,
+        order::{Order, OrderState},
+    },
+    error::ServerError,
+};
 use chrono::Utc;
 use poise::{self};
 use serenity::all::EditThread;
 
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum Verdict {
+    #[name = "full"]
+    #[name_localized("zh-TW", "全額退款")]
+    Full,
+    #[name = "half"]
+    #[name_localized("zh-TW", "半價退款")]
+    Half,
+    #[name = "reject"]
+    #[name_localized("zh-TW", "駁回")]
+    Reject,
+}
+
+impl Verdict {
+    fn amount(&self, cost: i64) -> i64 {
+        match self {
+            Verdict::Full => cost,
+            Verdict::Half => cost / 2,
+            Verdict::Reject => 0,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Verdict::Full => "全額退款",
+            Verdict::Half => "半價退款",
+            Verdict::Reject => "駁回",
+        }
+    }
+}
+
+// 先以討論串 ID 找訂單，舊討論串則以名稱結尾的案號找
+fn find_order(
+    thread: u64,
+    channel_name: &str,
+    transaction: &rusqlite::Transaction,
+) -> Result<Option<Order>, ServerError> {
+    if let Some(order) = Order::by_thread(thread, transaction)? {
+        return Ok(Some(order));
+    }
+
+    match channel_name.rsplit_once('-') {
+        Some((_, case_number)) => Order::by_case_number(case_number, transaction),
+        None => Ok(None),
+    }
+}
+
 #[poise::command(slash_command)]
 pub async fn refund(ctx: super::Context<'_>) -> Result<(), ServerError> {
     ctx.say("Refund Entrypoint.").await?;
@@ -10,18 +65,77 @@ pub async fn refund(ctx: super::Context<'_>) -> Result<(), ServerError> {
 }
 
 #[poise::command(slash_command)]
-pub async fn close(ctx: super::Context<'_>) -> Result<(), ServerError> {
+pub async fn close(
+    ctx: super::Context<'_>,
+    #[description = "Refund verdict"]
+    #[description_localized("zh-TW", "退款判定")]
+    verdict: Verdict,
+) -> Result<(), ServerError> {
     if CONFIG.discord.admin.contains(&ctx.author().id.get()) {
         let channel_name = ctx.channel_id().name(ctx.http()).await?.split_off(16);
 
+        let result = {
+            let mut connection = get_connection()?;
+            let transaction = connection.transaction()?;
+            let result = match find_order(ctx.channel_id().get(), &channel_name, &transaction)? {
+                Some(order)
+                    if matches!(verdict, Verdict::Half) && !refund::half_refundable(&order) =>
+                {
+                    Err(format!(
+                        "訂單 #{} 不是懲罰加倍卡，半價退款僅適用於未抽出的懲罰加倍卡。",
+                        order.id
+                    ))
+                }
+                Some(mut order) if order.state == OrderState::RefundRequested => {
+                    let amount = verdict.amount(order.cost);
+                    let buyer = refund::settle(
+                        &mut order,
+                        amount,
+                        &format!("discord:{}", ctx.author().id.get()),
+                        Utc::now(),
+                        &transaction,
+                    )?;
+                    Ok(Some((order.id, amount, buyer)))
+                }
+                Some(order) => Err(format!(
+                    "訂單 #{} 目前狀態為 {:?}，無法結單。",
+                    order.id, order.state
+                )),
+                None => Ok(None),
+            };
+            transaction.commit()?;
+            result
+        };
+
+        let summary = match result {
+            Ok(Some((order, amount, buyer))) => format!(
+                "判定：{}（訂單 #{}），退還 **{}** 水星幣，{} (`{}`) 結餘 **{}** 水星幣。",
+                verdict.label(),
+                order,
+                amount,
+                buyer.display,
+                buyer.youtube,
+                buyer.coin
+            ),
+            Ok(None) => format!(
+                "判定：{}。找不到對應的訂單，請手動處理水星幣。",
+                verdict.label()
+            ),
+            Err(message) => {
+                ctx.say(message).await?;
+                return Ok(());
+            }
+        };
+
         let now = Utc::now().format("%Y/%m/%d %H:%M");
 
         let content = format!(
             "\
 討論串 {} 於 {} 處理完成。
+{}
 如果需要重啟討論，請私訊管理員。
 =============本案號已結案，將討論串存檔，請勿新增更多訊息=============",
-            channel_name, now
+            channel_name, now, summary
         );
 
         ctx.reply(content).await?;
@@ -45,14 +159,54 @@ pub async fn reopen(ctx: super::Context<'_>) -> Result<(), ServerError> {
 
         let channel_name = ctx.channel_id().name(ctx.http()).await?.split_off(16);
 
+        let reverted = {
+            let mut connection = get_connection()?;
+            let transaction = connection.transaction()?;
+            let reverted = match find_order(ctx.channel_id().get(), &channel_name, &transaction)? {
+                Some(mut order)
+                    if matches!(order.state, OrderState::Refunded | OrderState::Rejected) =>
+                {
+                    let reverted = refund::revert(
+                        &mut order,
+                        &format!("discord:{}", ctx.author().id.get()),
+                        Utc::now(),
+                        &transaction,
+                    )?;
+                    Some((order.id, reverted))
+                }
+                _ => None,
+            };
+            transaction.commit()?;
+            reverted
+        };
+
         let now = Utc::now().format("%Y/%m/%d %H:%M");
 
+        let summary = match reverted {
+            Some((order, reverted)) if reverted.buyer.coin < 0 => format!(
+                "已撤回訂單 #{} 的退款，扣回 **{}** 水星幣。\n⚠️ {} (`{}`) 已花掉退款，結餘為 **{}** 水星幣，請與購買者協調補回。\n",
+                order,
+                reverted.amount,
+                reverted.buyer.display,
+                reverted.buyer.youtube,
+                reverted.buyer.coin
+            ),
+            Some((order, reverted)) if reverted.amount > 0 => {
+                format!(
+                    "已撤回訂單 #{} 的退款，扣回 **{}** 水星幣。\n",
+                    order, reverted.amount
+                )
+            }
+            Some((order, _)) => format!("訂單 #{} 已重新開放處理。\n", order),
+            None => String::new(),
+        };
+
         let content = format!(
             "\
 討論串 {} 於 {} 再開。
-請繼續處理退款事宜。
+{}請繼續處理退款事宜。
 =============在這則訊息以下開始處理退款=============",
-            channel_name, now
+            channel_name, now, summary
         );
 
         ctx.reply(content).await?;
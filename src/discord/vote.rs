@@ -1,20 +1,44 @@
-use crate::database::config::Config;
-use crate::database::get_connection;
-use crate::{config::CONFIG, error::ServerError};
-use chrono::{FixedOffset, TimeZone};
-use core::panic;
+use crate::database::{
+    ballot::{Ballot, BallotMode, BallotOption, BallotStatus, BallotVote, Stake, TieRule},
+    config::Config,
+    get_connection,
+    user::User,
+};
+use crate::{coin::ballot as coin_ballot, config::CONFIG, error::ServerError};
+use chrono::{DateTime, FixedOffset, TimeDelta, TimeZone, Utc};
 use itertools::Itertools;
 use poise;
-use serenity::all::{ChannelId, EditMessage, ReactionType, UserId};
+use rand::{Rng, seq::SliceRandom};
+use rusqlite::Transaction;
+use serenity::all::{
+    AutocompleteChoice, ChannelId, ComponentInteraction, ComponentInteractionDataKind, Context,
+    CreateActionRow, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    CreateSelectMenu, CreateSelectMenuKind, CreateSelectMenuOption, EditMessage, EventHandler,
+    Http, Interaction, Message, MessageId, ReactionType, UserId,
+};
 use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 use std::time::Duration;
-use tokio::sync::{Mutex, OnceCell};
-
-// 將原本的 fetch_vote_channel_and_msg 改寫為更安全的讀取函數
-fn read_vote_config() -> Result<(u64, Option<u64>), ServerError> {
-    let mut connection = get_connection()?; // 這裡現在會自動從全局池拿連接
-
+use tokio::sync::Mutex;
+
+const RUNOFF_DURATION: TimeDelta = TimeDelta::days(1);
+const MAX_NAME_LENGTH: usize = 32;
+/**
+ * Discord shows at most this many different reactions on a message
+ */
+const MAX_REACTIONS: u32 = 20;
+const MENU_SIZE: usize = 25;
+const MAX_MENU_OPTIONS: u32 = 5 * MENU_SIZE as u32;
+const MESSAGE_LIMIT: usize = 2000;
+const SCHEDULE_INTERVAL: Duration = Duration::from_secs(60);
+
+/**
+ * one rendering at a time, so the message is sent only once
+ */
+static RENDER: Mutex<()> = Mutex::const_new(());
+
+fn read_vote_channel() -> Result<u64, ServerError> {
+    let mut connection = get_connection()?;
     let transaction = connection.transaction()?;
 
     let vote_channel_id = if let Some(text) = Config::ChannelVote.get(&transaction)?
@@ -27,95 +51,328 @@ fn read_vote_config() -> Result<(u64, Option<u64>), ServerError> {
         )));
     };
 
-    let vote_message_id = if let Some(text) = Config::MessageVote.get(&transaction)?
-        && let Ok(message_id) = text.parse::<u64>()
-    {
-        Some(message_id)
-    } else {
-        None
-    };
-
     transaction.commit()?;
-    Ok((vote_channel_id, vote_message_id))
+    Ok(vote_channel_id)
 }
 
-// 新增一個專門用來更新 Message ID 的函數
-fn update_vote_message_id(new_id: u64) -> Result<(), ServerError> {
-    let mut connection = get_connection()?;
+#[derive(Debug, Clone, Copy, poise::ChoiceParameter)]
+pub enum Mode {
+    #[name = "reaction"]
+    #[name_localized("zh-TW", "表情符號計票")]
+    Reaction,
+    #[name = "linked"]
+    #[name_localized("zh-TW", "已連結帳號一人一票")]
+    Linked,
+    #[name = "coin"]
+    #[name_localized("zh-TW", "水星幣投票")]
+    Coin,
+    #[name = "menu"]
+    #[name_localized("zh-TW", "選單投票")]
+    Menu,
+}
 
-    // [關鍵修改] 設定 10000ms (10秒) 的等待時間
-    connection.busy_timeout(Duration::from_millis(10000))?;
+impl From<Mode> for BallotMode {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Reaction => BallotMode::Reaction,
+            Mode::Linked => BallotMode::Linked,
+            Mode::Coin => BallotMode::Coin,
+            Mode::Menu => BallotMode::Menu,
+        }
+    }
+}
+
+fn is_admin(ctx: super::Context<'_>) -> bool {
+    CONFIG.discord.admin.contains(&ctx.author().id.get())
+}
 
-    let transaction = connection.transaction()?; // 開啟寫入事務
+async fn autocomplete_ballot(_ctx: super::Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    let ballots = (|| {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        Ballot::open(&transaction)
+    })();
+
+    match ballots {
+        Ok(ballots) => ballots
+            .into_iter()
+            .filter(|ballot| ballot.name.contains(partial))
+            .map(|ballot| AutocompleteChoice::new(ballot.name.clone(), ballot.name))
+            .collect(),
+        Err(err) => {
+            log::error!("list ballots failed: {:?}", err);
+            Vec::new()
+        }
+    }
+}
+
+fn target(open: Vec<Ballot>, name: Option<&str>) -> Result<Ballot, String> {
+    match name {
+        Some(name) => open
+            .into_iter()
+            .find(|ballot| ballot.name == name)
+            .ok_or_else(|| format!("找不到进行中的投票「{}」", name)),
+        None => match <[Ballot; 1]>::try_from(open) {
+            Ok([ballot]) => Ok(ballot),
+            Err(open) if open.is_empty() => Err("当前投票尚未开始".to_string()),
+            Err(_) => Err(format!(
+                "进行中的投票不止一个，请指定投票名称（可用 {} 查看）",
+                super::command_mentions::get("vote_list").unwrap_or("/vote list")
+            )),
+        },
+    }
+}
+
+fn capacity(ballot: &Ballot) -> u32 {
+    match ballot.mode {
+        BallotMode::Reaction | BallotMode::Linked => Emojis::of(ballot).len().min(MAX_REACTIONS),
+        BallotMode::Coin | BallotMode::Menu => MAX_MENU_OPTIONS,
+    }
+}
+
+fn nomination(
+    ballot: &Ballot,
+    options: &[BallotOption],
+    nominee: u64,
+) -> Result<u32, &'static str> {
+    if let Some(max) = ballot.max_nominations {
+        let nominated = options
+            .iter()
+            .filter(|option| option.nominee == nominee)
+            .count();
+        if nominated >= max as usize {
+            return Err("您的提名数已达上限");
+        }
+    }
+    if ballot
+        .max_options
+        .is_some_and(|max| options.len() >= max as usize)
+    {
+        return Err("选项已满");
+    }
 
-    Config::MessageVote.set(new_id.to_string(), &transaction)?;
+    next_slot(options, capacity(ballot)).ok_or("选项已满")
+}
 
-    transaction.commit()?; // 提交寫入
+#[poise::command(slash_command)]
+pub async fn vote(ctx: super::Context<'_>) -> Result<(), ServerError> {
+    ctx.say("Vote Entrypoint.").await?;
     Ok(())
 }
 
-static BALLOT: OnceCell<Arc<Mutex<Ballot>>> = OnceCell::const_new();
+// 開始一個新的投票，預設在 ChannelVote
+#[allow(clippy::too_many_arguments)]
+#[poise::command(slash_command)]
+pub async fn create(
+    ctx: super::Context<'_>,
+    #[description = "投票名稱，其他指令用它指定投票"] name: String,
+    #[description = "投票所在頻道，預設為投票頻道"] channel: Option<ChannelId>,
+    #[description = "計票方式，預設為表情符號計票"] mode: Option<Mode>,
+    #[description = "水星幣投票結束後是否退還落選選項的水星幣"] refund: Option<bool>,
+    #[description = "選項數量上限"]
+    #[min = 1]
+    #[max = 125]
+    max_options: Option<u32>,
+    #[description = "每人提名數量上限"]
+    #[min = 1]
+    max_nominations: Option<u32>,
+    #[description = "標示選項的表情符號，以空格分隔，預設為國旗"] emojis: Option<String>,
+) -> Result<(), ServerError> {
+    if !is_admin(ctx) {
+        ctx.say("权限不足").await?;
+        return Ok(());
+    }
 
-async fn init_ballot(ctx: super::Context<'_>) -> Result<Arc<Mutex<Ballot>>, ServerError> {
-    BALLOT
-        .get_or_try_init(|| async {
-            let mut new_ballot = Ballot {
+    let name = name.trim().to_string();
+    if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+        ctx.say(format!("创建失败: 名称需为 1 至 {} 个字", MAX_NAME_LENGTH))
+            .await?;
+        return Ok(());
+    }
+    if let Some(Err(e)) = emojis.as_deref().map(Emojis::parse) {
+        ctx.say(format!("创建失败: {}", e)).await?;
+        return Ok(());
+    }
+    let channel = match channel {
+        Some(channel) => channel.get(),
+        None => read_vote_channel()?,
+    };
+
+    let created = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let created = if Ballot::open(&transaction)?
+            .iter()
+            .any(|ballot| ballot.name == name)
+        {
+            Err("已有同名的投票正在进行")
+        } else {
+            let mut ballot = Ballot {
+                id: 0,
+                name,
+                channel,
+                message: None,
                 deadline: None,
-                options: HashMap::new(),
+                status: BallotStatus::Open,
+                created_at: Utc::now(),
+                concluded_at: None,
+                winner: None,
+                runoff_of: None,
+                mode: mode.map(BallotMode::from).unwrap_or(BallotMode::Reaction),
+                refund: refund.unwrap_or(false),
+                max_options,
+                max_nominations,
+                emojis,
+                pages: None,
             };
-            new_ballot.fetch(ctx).await?;
-            Ok::<_, ServerError>(Arc::new(Mutex::new(new_ballot)))
-        })
-        .await
-        .map(Arc::clone)
+            ballot.insert(&transaction)?;
+            Ok(ballot)
+        };
+        transaction.commit()?;
+
+        created
+    };
+
+    match created {
+        Ok(ballot) => {
+            ctx.say(format!(
+                "已在 <#{}> 创建投票「{}」，请使用 {} 设置截止时间",
+                ballot.channel,
+                ballot.name,
+                super::command_mentions::get("vote_deadline").unwrap_or("/vote deadline")
+            ))
+            .await?;
+            render(ctx.http(), ballot.id).await?;
+        }
+        Err(e) => {
+            ctx.say(format!("创建失败: {}", e)).await?;
+        }
+    }
+
+    Ok(())
 }
 
 #[poise::command(slash_command)]
-pub async fn vote(ctx: super::Context<'_>) -> Result<(), ServerError> {
-    ctx.say("Vote Entrypoint.").await?;
+pub async fn list(ctx: super::Context<'_>) -> Result<(), ServerError> {
+    let ballots = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let mut ballots = Vec::new();
+        for ballot in Ballot::open(&transaction)? {
+            let options = BallotOption::by_ballot(ballot.id, &transaction)?.len();
+            ballots.push((ballot, options));
+        }
+        transaction.commit()?;
+
+        ballots
+    };
+
+    ctx.say(listing(&ballots)).await?;
+
     Ok(())
 }
 
 #[poise::command(slash_command)]
-pub async fn nominate(ctx: super::Context<'_>, content: String) -> Result<(), ServerError> {
-    let binding = init_ballot(ctx).await?;
-    let mut ballot = binding.lock().await;
-    match ballot.nominate(content, ctx.author().id) {
-        Ok(()) => {
+pub async fn nominate(
+    ctx: super::Context<'_>,
+    content: String,
+    #[description = "投票名稱，只有一個投票時可省略"]
+    #[autocomplete = "autocomplete_ballot"]
+    ballot: Option<String>,
+) -> Result<(), ServerError> {
+    let nominated = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let nominated = match target(Ballot::open(&transaction)?, ballot.as_deref()) {
+            Err(e) => Err(e),
+            Ok(ballot) => {
+                let options = BallotOption::by_ballot(ballot.id, &transaction)?;
+                match nomination(&ballot, &options, ctx.author().id.get()) {
+                    Err(e) => Err(e.to_string()),
+                    Ok(slot) => {
+                        BallotOption {
+                            id: 0,
+                            ballot: ballot.id,
+                            flag: slot,
+                            description: content,
+                            nominee: ctx.author().id.get(),
+                            votes: None,
+                            created_at: Utc::now(),
+                        }
+                        .insert(&transaction)?;
+                        Ok(ballot.id)
+                    }
+                }
+            }
+        };
+        transaction.commit()?;
+
+        nominated
+    };
+
+    match nominated {
+        Ok(ballot) => {
             ctx.say("提名成功").await?;
+            render(ctx.http(), ballot).await?;
         }
         Err(e) => {
             ctx.say(format!("提名失败: {}", e)).await?;
-            return Ok(());
         }
     }
-    ballot.commit(ctx).await?;
 
     Ok(())
 }
 
 #[poise::command(slash_command)]
-pub async fn revoke(ctx: super::Context<'_>, id: String) -> Result<(), ServerError> {
-    let flag = match Flag::try_from(id.as_str()) {
-        Ok(f) => f,
-        Err(e) => {
-            ctx.say(format!("撤回失败: {}", e)).await?;
-            return Ok(());
-        }
+pub async fn revoke(
+    ctx: super::Context<'_>,
+    id: String,
+    #[description = "投票名稱，只有一個投票時可省略"]
+    #[autocomplete = "autocomplete_ballot"]
+    ballot: Option<String>,
+) -> Result<(), ServerError> {
+    let is_admin = is_admin(ctx);
+    let revoked = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let revoked = match target(Ballot::open(&transaction)?, ballot.as_deref()) {
+            Err(e) => Err(e),
+            Ok(ballot) => {
+                let options = BallotOption::by_ballot(ballot.id, &transaction)?;
+                match chosen(&ballot, &options, &id) {
+                    None => Err("未找到该提名".to_string()),
+                    Some(option) if !is_admin && option.nominee != ctx.author().id.get() => {
+                        Err("您没有权限".to_string())
+                    }
+                    Some(option)
+                        if Stake::by_ballot(ballot.id, &transaction)?
+                            .iter()
+                            .any(|stake| stake.option == option.id) =>
+                    {
+                        Err("该选项已有水星币投入".to_string())
+                    }
+                    Some(option) => {
+                        BallotVote::delete_by_option(option.id, &transaction)?;
+                        option.delete(&transaction)?;
+                        Ok(ballot.id)
+                    }
+                }
+            }
+        };
+        transaction.commit()?;
+
+        revoked
     };
 
-    let binding = init_ballot(ctx).await?;
-    let mut ballot = binding.lock().await;
-    match ballot.revoke(flag, ctx.author().id) {
-        Ok(()) => {
+    match revoked {
+        Ok(ballot) => {
             ctx.say("撤回成功").await?;
+            render(ctx.http(), ballot).await?;
         }
         Err(e) => {
             ctx.say(format!("撤回失败: {}", e)).await?;
-            return Ok(());
         }
-    };
-    ballot.commit(ctx).await?;
+    }
 
     Ok(())
 }
@@ -126,39 +383,56 @@ pub async fn deadline(
     #[description = "年份 (例如 2025)"] year: i32,
     #[description = "月份 (1-12)"] month: u32,
     #[description = "日期 (1-31)"] day: u32,
+    #[description = "投票名稱，只有一個投票時可省略"]
+    #[autocomplete = "autocomplete_ballot"]
+    ballot: Option<String>,
 ) -> Result<(), ServerError> {
     // 權限檢查
-    if !CONFIG.discord.admin.contains(&ctx.author().id.get()) {
+    if !is_admin(ctx) {
         ctx.say("权限不足").await?;
         return Ok(());
     }
 
-    // 使用 chrono::Utc 構建日期
     // 時間固定在該日的 23:59:00 UTC+8
-
     let fixed_offset_8 = FixedOffset::east_opt(8 * 3600).expect("Can't offset time.");
-
-    let datetime_opt = fixed_offset_8
+    let Some(deadline) = fixed_offset_8
         .with_ymd_and_hms(year, month, day, 23, 59, 0)
-        .single();
-
-    match datetime_opt {
-        Some(dt) => {
-            let ts = dt.timestamp() as u64;
+        .single()
+    else {
+        ctx.say("❌ 無效的日期（請檢查年份、月份或該月是否有這一天）。")
+            .await?;
+        return Ok(());
+    };
+    let deadline = deadline.with_timezone(&Utc);
+
+    let ballot = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let ballot = match target(Ballot::open(&transaction)?, ballot.as_deref()) {
+            Err(e) => Err(e),
+            Ok(mut ballot) => {
+                ballot.deadline = Some(deadline);
+                ballot.update(&transaction)?;
+                Ok(ballot)
+            }
+        };
+        transaction.commit()?;
 
-            let binding = init_ballot(ctx).await?;
-            let mut ballot = binding.lock().await;
-            ballot.deadline = Some(ts);
+        ballot
+    };
 
+    match ballot {
+        Ok(ballot) => {
             // Discord 會根據用戶所在時區顯示這個 UTC 時間戳
-            ctx.say(format!("✅ 截止日期已設置為: <t:{}:f>", ts))
-                .await?;
-
-            ballot.commit(ctx).await?;
+            ctx.say(format!(
+                "✅ 截止日期已設置為: <t:{}:f>",
+                deadline.timestamp()
+            ))
+            .await?;
+            render(ctx.http(), ballot.id).await?;
         }
-        None => {
-            ctx.say("❌ 無效的日期（請檢查年份、月份或該月是否有這一天）。")
-                .await?;
+        Err(e) => {
+            ctx.say(format!("设置失败: {}", e)).await?;
         }
     }
 
@@ -166,410 +440,1539 @@ pub async fn deadline(
 }
 
 #[poise::command(slash_command)]
-// TODO: Make the vote automatically conclude the result
-pub async fn conclude(ctx: super::Context<'_>) -> Result<(), ServerError> {
-    if !CONFIG.discord.admin.contains(&ctx.author().id.get()) {
+pub async fn conclude(
+    ctx: super::Context<'_>,
+    #[description = "投票名稱，只有一個投票時可省略"]
+    #[autocomplete = "autocomplete_ballot"]
+    ballot: Option<String>,
+) -> Result<(), ServerError> {
+    if !is_admin(ctx) {
         ctx.say("权限不足").await?;
         return Ok(());
     }
-    let binding = init_ballot(ctx).await?;
-    let mut ballot = binding.lock().await;
-    ballot.deadline = None;
-    ctx.say("投票已结束").await?;
-    let outcome = ballot.title(ctx).await?;
-    {
-        ctx.say(format!("投票結果：{}", outcome)).await?;
+
+    let ballot = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let ballot = target(Ballot::open(&transaction)?, ballot.as_deref());
+        transaction.commit()?;
+        ballot
+    };
+    let ballot = match ballot {
+        Ok(ballot) => ballot,
+        Err(e) => {
+            ctx.say(format!("结束失败: {}", e)).await?;
+            return Ok(());
+        }
     };
-    ballot.commit(ctx).await?;
+
+    conclude_ballot(ctx.http(), ballot.id).await?;
+    ctx.say(format!("投票已结束，结果已公布于 <#{}>", ballot.channel))
+        .await?;
+
     Ok(())
 }
 
+// 平手且規則為管理員選擇時，由管理員指定勝出的選項
 #[poise::command(slash_command)]
-pub async fn clear(ctx: super::Context<'_>) -> Result<(), ServerError> {
-    if !CONFIG.discord.admin.contains(&ctx.author().id.get()) {
+pub async fn pick(
+    ctx: super::Context<'_>,
+    id: String,
+    #[description = "投票名稱，省略時為最近結束的投票"] ballot: Option<String>,
+) -> Result<(), ServerError> {
+    if !is_admin(ctx) {
         ctx.say("权限不足").await?;
         return Ok(());
     }
 
-    let binding = init_ballot(ctx).await?;
-    let mut ballot = binding.lock().await;
-    ballot.deadline = None;
-    ballot.options.clear();
-    ctx.say("投票已清空").await?;
-    ballot.commit(ctx).await?;
+    let picked = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let picked = match Ballot::last_concluded(ballot.as_deref(), &transaction)? {
+            None => Err("没有已结束的投票"),
+            Some(ballot) if ballot.winner.is_some() => Err("该投票已有胜出选项"),
+            Some(mut ballot) => {
+                let options = BallotOption::by_ballot(ballot.id, &transaction)?;
+                match chosen(&ballot, &options, &id).cloned() {
+                    None => Err("未找到该提名"),
+                    Some(option) => {
+                        ballot.winner = Some(option.id);
+                        ballot.update(&transaction)?;
+                        coin_ballot::refund_losers(
+                            &ballot,
+                            &[option.id],
+                            Utc::now(),
+                            &transaction,
+                        )?;
+                        Ok((ballot, option))
+                    }
+                }
+            }
+        };
+        transaction.commit()?;
+
+        picked
+    };
+
+    match picked {
+        Ok((ballot, option)) => {
+            render(ctx.http(), ballot.id).await?;
+            ChannelId::from(ballot.channel)
+                .send_message(
+                    ctx.http(),
+                    CreateMessage::new().content(format!(
+                        "「{}」管理员选出：{}",
+                        ballot.name,
+                        label(&Emojis::of(&ballot), &option)
+                    )),
+                )
+                .await?;
+            ctx.say("已选出胜出选项").await?;
+        }
+        Err(e) => {
+            ctx.say(format!("选择失败: {}", e)).await?;
+        }
+    }
+
     Ok(())
 }
 
-#[derive(Debug, Clone)]
-struct Ballot {
-    deadline: Option<u64>,
-    options: HashMap<Flag, VoteOption>,
-}
+#[poise::command(slash_command)]
+pub async fn stake(
+    ctx: super::Context<'_>,
+    id: String,
+    amount: i64,
+    #[description = "投票名稱，只有一個投票時可省略"]
+    #[autocomplete = "autocomplete_ballot"]
+    ballot: Option<String>,
+) -> Result<(), ServerError> {
+    let staked = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let ballot = target(Ballot::open(&transaction)?, ballot.as_deref());
+        let options = match ballot.as_ref() {
+            Ok(ballot) => BallotOption::by_ballot(ballot.id, &transaction)?,
+            Err(_) => Vec::new(),
+        };
+        let voter = User::by_discord(ctx.author().id.get().to_string(), &transaction)?;
+
+        let staked = match (ballot, voter) {
+            (Err(e), _) => Err(e),
+            (_, None) => Err(format!(
+                "请先使用 {} 连结 YouTube 频道",
+                super::command_mentions::get("link").unwrap_or("/link")
+            )),
+            (Ok(ballot), Some(mut voter)) => match chosen(&ballot, &options, &id) {
+                None => Err("未找到该提名".to_string()),
+                Some(option) => match coin_ballot::stake(
+                    &ballot,
+                    option,
+                    &mut voter,
+                    amount,
+                    &format!("discord:{}", ctx.author().id.get()),
+                    Utc::now(),
+                    &transaction,
+                )? {
+                    coin_ballot::Outcome::Staked(stake) => Ok((
+                        label(&Emojis::of(&ballot), option),
+                        stake.amount,
+                        voter.coin,
+                    )),
+                    coin_ballot::Outcome::NotOpen => Err("该投票不是水星币投票".to_string()),
+                    coin_ballot::Outcome::InvalidAmount => Err("数量必须大于 0".to_string()),
+                    coin_ballot::Outcome::InsufficientFunds => Err("水星币不足".to_string()),
+                    coin_ballot::Outcome::Frozen => {
+                        Err("您的水星币已被冻结，请联络管理员".to_string())
+                    }
+                },
+            },
+        };
+        transaction.commit()?;
 
-impl Ballot {
-    async fn fetch(&mut self, ctx: super::Context<'_>) -> Result<(), ServerError> {
-        // Step 1: 讀取 (持有鎖 -> 釋放鎖)
-        let (channel_vote, message_vote_opt) = read_vote_config()?;
-        let channel_id = ChannelId::from(channel_vote);
+        staked
+    };
 
-        let init_process = || async {
-            println!("正在初始化投票訊息..."); // Debug 用
+    match staked {
+        Ok((option, amount, balance)) => {
+            ctx.say(format!(
+                "已在 {} 投入 {} 水星币，剩余 {} 水星币",
+                option, amount, balance
+            ))
+            .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("投票失败: {}", e)).await?;
+        }
+    }
 
-            // Step 2: 網絡請求 (無資料庫鎖，耗時操作)
-            let new_message = channel_id
-                .send_message(
-                    &ctx.http(),
-                    serenity::all::CreateMessage::new().content("# 正在初始化投票..."),
-                )
-                .await?;
+    Ok(())
+}
 
-            println!("訊息發送成功 ID: {}，準備寫入資料庫...", new_message.id); // Debug 用
+// 取消投票，不公布結果
+#[poise::command(slash_command)]
+pub async fn close(
+    ctx: super::Context<'_>,
+    #[description = "投票名稱"]
+    #[autocomplete = "autocomplete_ballot"]
+    ballot: String,
+) -> Result<(), ServerError> {
+    if !is_admin(ctx) {
+        ctx.say("权限不足").await?;
+        return Ok(());
+    }
 
-            // Step 3: 寫入 (持有鎖 -> 釋放鎖)
-            // 這裡現在有了 busy_timeout，如果資料庫忙碌，它會等待而不是報錯
-            match update_vote_message_id(new_message.id.get()) {
-                Ok(_) => println!("資料庫寫入成功"),
-                Err(e) => {
-                    // 如果還是失敗，至少我們知道是在這一步
-                    println!("資料庫寫入失敗: {:?}", e);
-                    return Err(e);
-                }
+    let closed = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let closed = match target(Ballot::open(&transaction)?, Some(&ballot)) {
+            Err(e) => Err(e),
+            Ok(mut ballot) => {
+                coin_ballot::cancel(&mut ballot, Utc::now(), &transaction)?;
+                Ok(ballot)
             }
-
-            Ok::<_, ServerError>(new_message)
         };
+        transaction.commit()?;
+
+        closed
+    };
+
+    match closed {
+        Ok(ballot) => {
+            ctx.say(format!("投票「{}」已关闭", ballot.name)).await?;
+            render(ctx.http(), ballot.id).await?;
+        }
+        Err(e) => {
+            ctx.say(format!("关闭失败: {}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/**
+ * conclude the ballots past their deadline once a minute, after importing a
+ * legacy vote.
+ */
+pub async fn schedule(http: Arc<Http>) {
+    if let Err(err) = import_legacy(&http).await {
+        log::error!("fail to import the legacy vote: {:?}", err);
+    }
+
+    loop {
+        if let Err(err) = conclude_due(&http).await {
+            log::error!("fail to conclude due ballots: {:?}", err);
+        }
+        tokio::time::sleep(SCHEDULE_INTERVAL).await;
+    }
+}
+
+async fn conclude_due(http: &Http) -> Result<(), ServerError> {
+    let due = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let due = Ballot::due(Utc::now(), &transaction)?;
+        transaction.commit()?;
+        due
+    };
+
+    for ballot in due {
+        if let Err(err) = conclude_ballot(http, ballot.id).await {
+            log::error!("fail to conclude ballot {}: {:?}", ballot.id, err);
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+struct LegacyVote {
+    deadline: DateTime<Utc>,
+    options: Vec<(u32, String, u64)>,
+}
 
-        let message = match message_vote_opt {
-            Some(message_id) => {
-                match channel_id.message(&ctx.http(), message_id).await {
-                    Ok(msg) => msg,
-                    // Discord 找不到該訊息 -> 觸發初始化
-                    Err(_) => init_process().await?,
+/**
+ * `None` if the legacy message shows a result instead of a vote
+ */
+fn parse_legacy(content: &str) -> Option<LegacyVote> {
+    let mut lines = content.lines();
+    let (deadline, _) = lines
+        .next()?
+        .strip_prefix("当前投票截止时间: __**<t:")?
+        .split_once(':')?;
+    let deadline = DateTime::from_timestamp(deadline.parse().ok()?, 0)?;
+
+    let options = lines
+        .filter_map(|line| {
+            let (flag, rest) = line.split_once(": ")?;
+            let (description, nominee) = rest.rsplit_once(" (<@")?;
+            let nominee = nominee.strip_suffix(">)")?.parse().ok()?;
+            let flag = FLAGS.iter().position(|known| *known == flag)?;
+            Some((flag as u32, description.trim().to_string(), nominee))
+        })
+        .collect();
+
+    Some(LegacyVote { deadline, options })
+}
+
+/**
+ * turn the legacy vote message into a reaction ballot, so its reactions keep
+ * counting.
+ */
+async fn import_legacy(http: &Http) -> Result<(), ServerError> {
+    let message = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let message = Config::MessageVote
+            .get(&transaction)?
+            .and_then(|text| text.parse::<u64>().ok());
+        transaction.commit()?;
+        message
+    };
+    let Some(message) = message else {
+        return Ok(());
+    };
+
+    let channel = read_vote_channel()?;
+    let legacy = match ChannelId::from(channel)
+        .message(http, MessageId::from(message))
+        .await
+    {
+        Ok(message) => parse_legacy(&message.content),
+        Err(err) => {
+            log::warn!("legacy vote message {} not found: {:?}", message, err);
+            None
+        }
+    };
+
+    let imported = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let imported = match legacy {
+            Some(legacy) => {
+                let now = Utc::now();
+                let mut ballot = Ballot {
+                    id: 0,
+                    name: String::from("vote"),
+                    channel,
+                    message: Some(message),
+                    deadline: Some(legacy.deadline),
+                    status: BallotStatus::Open,
+                    created_at: now,
+                    concluded_at: None,
+                    winner: None,
+                    runoff_of: None,
+                    mode: BallotMode::Reaction,
+                    refund: false,
+                    max_options: None,
+                    max_nominations: None,
+                    emojis: None,
+                    pages: None,
+                };
+                // 同名投票已存在時以訊息 ID 區分
+                let open = Ballot::open(&transaction)?;
+                if open.iter().any(|other| other.name == ballot.name) {
+                    ballot.name = format!("vote-{}", message);
+                }
+                ballot.insert(&transaction)?;
+                for (flag, description, nominee) in legacy.options {
+                    BallotOption {
+                        id: 0,
+                        ballot: ballot.id,
+                        flag,
+                        description,
+                        nominee,
+                        votes: None,
+                        created_at: now,
+                    }
+                    .insert(&transaction)?;
                 }
+                Some(ballot.id)
             }
-            // 資料庫沒紀錄 -> 觸發初始化
-            None => init_process().await?,
+            None => None,
         };
+        Config::MessageVote.set(String::new(), &transaction)?;
+        transaction.commit()?;
 
-        // 解析選項
-        for options in message
-            .content
-            .lines()
-            .skip(1)
-            .filter_map(|l| VoteOption::parse(l))
-        {
-            self.options.insert(options.flag, options);
-        }
-        Ok(())
+        imported
+    };
+
+    if let Some(ballot) = imported {
+        log::info!(
+            "imported legacy vote message {} as ballot {}",
+            message,
+            ballot
+        );
+        render(http, ballot).await?;
     }
 
-    async fn commit(&self, ctx: super::Context<'_>) -> Result<(), ServerError> {
-        // Step 1: Fetch the message and reactions
-        // Step 2: Remove reactions that are no longer in options, and sort options based on existing reactions
-        // Step 3: Add reactions that are in options but not in reactions, and sort options based on adding order
-        // Step 4: Update the message content
+    Ok(())
+}
 
-        // 使用新的讀取函數
-        let (channel_vote, message_vote_opt) = read_vote_config()?;
+fn read_tie_rule() -> Result<TieRule, ServerError> {
+    let mut connection = get_connection()?;
+    let transaction = connection.transaction()?;
+    let rule = Config::VoteTieRule
+        .get(&transaction)?
+        .and_then(|text| TieRule::parse(&text))
+        .unwrap_or(TieRule::Admin);
+    transaction.commit()?;
 
-        // 如果要 Commit 但找不到 ID，這是不正常的 (因為 fetch 應該已經處理過了)
-        let message_vote = message_vote_opt
-            .ok_or_else(|| ServerError::Internal("無法提交投票：找不到投票訊息 ID".to_string()))?;
+    Ok(rule)
+}
 
-        let mut message = ChannelId::from(channel_vote)
-            .message(&ctx.http(), message_vote)
-            .await?;
+/**
+ * a ballot no longer open is left alone, so the scheduler and `/vote
+ * conclude` can race.
+ */
+async fn conclude_ballot(http: &Http, ballot_id: i64) -> Result<(), ServerError> {
+    let ballot = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let ballot = Ballot::by_id(ballot_id, &transaction)?;
+        transaction.commit()?;
+        ballot
+    };
+    let Some(ballot) = ballot.filter(|ballot| ballot.status == BallotStatus::Open) else {
+        return Ok(());
+    };
 
-        let mut content = Vec::new();
-        let mut hashmap = self.options.clone();
-        let reactions = &message.reactions;
-
-        // add title
-        content.push(self.title(ctx).await?);
-
-        // remove reactions that are no longer in options
-        for reaction in reactions {
-            if let Ok(flag) = Flag::try_from(reaction.reaction_type.clone()) {
-                if hashmap.contains_key(&flag) {
-                    // sort options based on existing reactions
-                    content.push(hashmap[&flag].to_string());
-                    // keep the reaction if it's still in options
-                    hashmap.remove(&flag);
-                    continue;
+    let counts = tally(http, &ballot).await?;
+    // 加賽再平手時不再加賽，改由管理員選擇
+    let rule = match read_tie_rule()? {
+        TieRule::Runoff if ballot.runoff_of.is_some() => TieRule::Admin,
+        rule => rule,
+    };
+    let now = Utc::now();
+
+    let concluded = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let concluded = match Ballot::by_id(ballot.id, &transaction)? {
+            Some(mut ballot) if ballot.status == BallotStatus::Open => {
+                let mut options = BallotOption::by_ballot(ballot.id, &transaction)?;
+                for option in options.iter_mut() {
+                    option.votes = Some(counts.get(&option.flag).copied().unwrap_or(0));
+                    option.update(&transaction)?;
                 }
+
+                let decision = decide(&options, rule, &mut rand::thread_rng());
+                let runoff = match &decision {
+                    Decision::Winner(winner) => {
+                        ballot.winner = Some(*winner);
+                        None
+                    }
+                    Decision::Runoff(tied) => {
+                        Some(open_runoff(&ballot, &options, tied, now, &transaction)?)
+                    }
+                    Decision::NoVotes | Decision::AdminPick(_) => None,
+                };
+                // 加賽或待管理員選擇的選項先不退款
+                let alive = match &decision {
+                    Decision::Winner(winner) => vec![*winner],
+                    Decision::Runoff(tied) | Decision::AdminPick(tied) => tied.clone(),
+                    Decision::NoVotes => options.iter().map(|option| option.id).collect(),
+                };
+                coin_ballot::refund_losers(&ballot, &alive, now, &transaction)?;
+                ballot.status = BallotStatus::Concluded;
+                ballot.concluded_at = Some(now);
+                ballot.update(&transaction)?;
+
+                Some((ballot, options, decision, runoff))
             }
+            _ => None,
+        };
+        transaction.commit()?;
 
-            // otherwise, remove the reaction
-            message
-                .delete_reaction_emoji(&ctx.http(), reaction.reaction_type.clone())
-                .await?;
-        }
+        concluded
+    };
+    let Some((ballot, options, decision, runoff)) = concluded else {
+        return Ok(());
+    };
+
+    render(http, ballot.id).await?;
+    ChannelId::from(ballot.channel)
+        .send_message(
+            http,
+            CreateMessage::new().content(announcement(
+                &ballot,
+                &options,
+                &decision,
+                runoff.as_ref(),
+            )),
+        )
+        .await?;
+    if let Some(runoff) = runoff {
+        render(http, runoff.id).await?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Decision {
+    NoVotes,
+    Winner(i64),
+    AdminPick(Vec<i64>),
+    Runoff(Vec<i64>),
+}
+
+fn decide(options: &[BallotOption], rule: TieRule, rng: &mut impl Rng) -> Decision {
+    let max_count = options.iter().filter_map(|option| option.votes).max();
+    let Some(max_count) = max_count.filter(|count| *count > 0) else {
+        return Decision::NoVotes;
+    };
+    let tied: Vec<i64> = options
+        .iter()
+        .filter(|option| option.votes == Some(max_count))
+        .map(|option| option.id)
+        .collect();
+
+    if let [winner] = tied.as_slice() {
+        return Decision::Winner(*winner);
+    }
+    match rule {
+        TieRule::Runoff => Decision::Runoff(tied),
+        TieRule::Admin => Decision::AdminPick(tied),
+        TieRule::Random => match tied.choose(rng) {
+            Some(winner) => Decision::Winner(*winner),
+            None => Decision::NoVotes,
+        },
+    }
+}
 
-        // add reactions that are in options but not in reactions
-        for (flag, option) in hashmap {
-            message.react(&ctx.http(), flag.reaction()).await?;
-            // sort options based on adding order
-            content.push(option.to_string());
+fn open_runoff(
+    ballot: &Ballot,
+    options: &[BallotOption],
+    tied: &[i64],
+    now: DateTime<Utc>,
+    transaction: &Transaction,
+) -> Result<Ballot, ServerError> {
+    let mut runoff = Ballot {
+        id: 0,
+        name: ballot.name.clone(),
+        channel: ballot.channel,
+        message: None,
+        deadline: Some(now + RUNOFF_DURATION),
+        status: BallotStatus::Open,
+        created_at: now,
+        concluded_at: None,
+        winner: None,
+        runoff_of: Some(ballot.id),
+        mode: ballot.mode,
+        refund: ballot.refund,
+        max_options: ballot.max_options,
+        max_nominations: ballot.max_nominations,
+        emojis: ballot.emojis.clone(),
+        pages: None,
+    };
+    runoff.insert(transaction)?;
+
+    for option in options.iter().filter(|option| tied.contains(&option.id)) {
+        BallotOption {
+            id: 0,
+            ballot: runoff.id,
+            votes: None,
+            created_at: now,
+            ..option.clone()
         }
+        .insert(transaction)?;
+    }
 
-        // convert content to a single string
-        let content = content.iter().map(|o| o.to_string()).join("\n");
+    Ok(runoff)
+}
 
-        println!("Committing ballot:\n{}", content);
+fn label(emojis: &Emojis, option: &BallotOption) -> String {
+    format!("{} {}", emojis.label(option.flag), option.description)
+}
 
-        message
-            .edit(&ctx.http(), EditMessage::new().content(content))
-            .await?;
+fn chosen<'a>(
+    ballot: &Ballot,
+    options: &'a [BallotOption],
+    text: &str,
+) -> Option<&'a BallotOption> {
+    let slot = Emojis::of(ballot).find(text).ok()?;
+    options.iter().find(|option| option.flag == slot)
+}
+
+fn announcement(
+    ballot: &Ballot,
+    options: &[BallotOption],
+    decision: &Decision,
+    runoff: Option<&Ballot>,
+) -> String {
+    let emojis = Emojis::of(ballot);
+    let labels = |ids: &[i64]| {
+        options
+            .iter()
+            .filter(|option| ids.contains(&option.id))
+            .map(|option| label(&emojis, option))
+            .join("、")
+    };
 
-        Ok(())
+    let result = format!("「{}」投票結果：{}", ballot.name, outcome(ballot, options));
+    match decision {
+        Decision::NoVotes => format!("「{}」投票已结束，没有人投票", ballot.name),
+        Decision::Winner(winner) => format!("{}\n胜出：{}", result, labels(&[*winner])),
+        Decision::AdminPick(tied) => format!(
+            "{}\n平票：{}\n请管理员使用 {} 选出胜出选项",
+            result,
+            labels(tied),
+            super::command_mentions::get("vote_pick").unwrap_or("/vote pick")
+        ),
+        Decision::Runoff(tied) => format!(
+            "{}\n平票：{}\n加赛投票截止时间: <t:{}:f>",
+            result,
+            labels(tied),
+            runoff
+                .and_then(|runoff| runoff.deadline)
+                .map(|deadline| deadline.timestamp())
+                .unwrap_or_default()
+        ),
     }
+}
 
-    pub fn nominate(&mut self, description: String, nominee: UserId) -> Result<(), String> {
-        if self.deadline.is_none() {
-            return Err("当前投票尚未开始".to_string());
+fn next_slot(options: &[BallotOption], capacity: u32) -> Option<u32> {
+    let used: HashSet<u32> = options.iter().map(|option| option.flag).collect();
+    (0..capacity).find(|slot| !used.contains(slot))
+}
+
+async fn tally(http: &Http, ballot: &Ballot) -> Result<HashMap<u32, i64>, ServerError> {
+    if matches!(ballot.mode, BallotMode::Coin | BallotMode::Menu) {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let options = BallotOption::by_ballot(ballot.id, &transaction)?;
+        let totals = match ballot.mode {
+            BallotMode::Coin => coin_ballot::totals(&Stake::by_ballot(ballot.id, &transaction)?),
+            _ => BallotVote::by_ballot(ballot.id, &transaction)?
+                .iter()
+                .counts_by(|vote| vote.option)
+                .into_iter()
+                .map(|(option, count)| (option, count as i64))
+                .collect(),
+        };
+        transaction.commit()?;
+
+        return Ok(options
+            .iter()
+            .map(|option| (option.flag, totals.get(&option.id).copied().unwrap_or(0)))
+            .collect());
+    }
+
+    let Some(message) = ballot.message else {
+        return Ok(HashMap::new());
+    };
+    let message = ChannelId::from(ballot.channel)
+        .message(http, MessageId::from(message))
+        .await?;
+    let emojis = Emojis::of(ballot);
+
+    match ballot.mode {
+        BallotMode::Linked => {
+            let votes = reaction_users(http, &emojis, &message).await?;
+            let linked = {
+                let mut connection = get_connection()?;
+                let transaction = connection.transaction()?;
+                let mut linked = HashSet::new();
+                for user in votes.iter().map(|(_, user)| *user).unique() {
+                    if User::by_discord(user.to_string(), &transaction)?.is_some() {
+                        linked.insert(user);
+                    }
+                }
+                transaction.commit()?;
+                linked
+            };
+            Ok(one_vote_each(&votes, &linked))
         }
+        // 扣掉機器人預先加上的反應
+        _ => Ok(message
+            .reactions
+            .iter()
+            .filter_map(|r| {
+                emojis
+                    .slot(&r.reaction_type)
+                    .map(|slot| (slot, r.count as i64 - i64::from(r.me)))
+            })
+            .collect()),
+    }
+}
 
-        let mut flags = Flag::all();
-        self.options.iter().for_each(|o| {
-            // remove used flags
-            flags.remove(&o.0);
-        });
+async fn reaction_users(
+    http: &Http,
+    emojis: &Emojis,
+    message: &Message,
+) -> Result<Vec<(u32, u64)>, ServerError> {
+    let mut votes = Vec::new();
+    for reaction in &message.reactions {
+        let Some(slot) = emojis.slot(&reaction.reaction_type) else {
+            continue;
+        };
 
-        if let Some(next_flag) = flags.iter().next() {
-            self.options.insert(
-                *next_flag,
-                VoteOption {
-                    flag: *next_flag,
-                    description,
-                    nominee,
-                },
+        let mut after: Option<UserId> = None;
+        loop {
+            let users = message
+                .reaction_users(http, reaction.reaction_type.clone(), Some(100), after)
+                .await?;
+            let Some(last) = users.last() else {
+                break;
+            };
+            after = Some(last.id);
+            let full = users.len() == 100;
+
+            votes.extend(
+                users
+                    .iter()
+                    .filter(|user| !user.bot)
+                    .map(|user| (slot, user.id.get())),
             );
-            Ok(())
-        } else {
-            Err("选项已满".to_string())
+            if !full {
+                break;
+            }
         }
     }
 
-    pub fn revoke(&mut self, flag: Flag, user: UserId) -> Result<(), String> {
-        if self.deadline.is_none() {
-            return Err("当前投票尚未开始".to_string());
+    Ok(votes)
+}
+
+/**
+ * someone who reacted to several options is not counted
+ */
+fn one_vote_each(votes: &[(u32, u64)], linked: &HashSet<u64>) -> HashMap<u32, i64> {
+    let mut choices: HashMap<u64, HashSet<u32>> = HashMap::new();
+    for (slot, user) in votes {
+        if linked.contains(user) {
+            choices.entry(*user).or_default().insert(*slot);
         }
+    }
 
-        fn is_authorized(nominee: UserId, user: UserId) -> bool {
-            CONFIG.discord.admin.contains(&user.get()) || user == nominee
+    let mut counts = HashMap::new();
+    for slots in choices.values() {
+        if let [slot] = slots.iter().collect::<Vec<_>>().as_slice() {
+            *counts.entry(**slot).or_default() += 1;
         }
+    }
+    counts
+}
+
+fn mode_name(mode: BallotMode) -> &'static str {
+    match mode {
+        BallotMode::Reaction => "表情符号计票",
+        BallotMode::Linked => "一人一票",
+        BallotMode::Coin => "水星币投票",
+        BallotMode::Menu => "选单投票",
+    }
+}
 
-        if let Some(option) = self.options.get(&flag) {
-            if is_authorized(option.nominee, user) {
-                self.options.remove(&flag);
-                Ok(())
+fn listing(ballots: &[(Ballot, usize)]) -> String {
+    if ballots.is_empty() {
+        return "# __**当前没有投票**__".to_string();
+    }
+
+    ballots
+        .iter()
+        .map(|(ballot, options)| {
+            format!(
+                "「{}」<#{}> {}，{} 个选项，{}",
+                ballot.name,
+                ballot.channel,
+                mode_name(ballot.mode),
+                options,
+                match ballot.deadline {
+                    Some(deadline) => format!("截止时间: <t:{}:f>", deadline.timestamp()),
+                    None => "尚未设定截止时间".to_string(),
+                }
+            )
+        })
+        .join("\n")
+}
+
+fn unit(mode: BallotMode) -> &'static str {
+    match mode {
+        BallotMode::Reaction | BallotMode::Linked | BallotMode::Menu => "票",
+        BallotMode::Coin => "水星币",
+    }
+}
+
+fn outcome(ballot: &Ballot, options: &[BallotOption]) -> String {
+    let emojis = Emojis::of(ballot);
+    let max_count = options.iter().filter_map(|option| option.votes).max();
+
+    match max_count {
+        Some(max_count) if max_count > 0 => format!(
+            "__**最高票{}{}, 是{}**__",
+            max_count,
+            unit(ballot.mode),
+            options
+                .iter()
+                .filter(|option| option.votes == Some(max_count))
+                .map(|option| emojis.label(option.flag))
+                .join(", "),
+        ),
+        _ => "# __**当前没有投票**__".to_string(),
+    }
+}
+
+fn view(ballot: &Ballot, options: &[BallotOption]) -> String {
+    let emojis = Emojis::of(ballot);
+    let title = match ballot.status {
+        BallotStatus::Open => match ballot.deadline {
+            Some(deadline) if ballot.runoff_of.is_some() => {
+                format!("加赛投票截止时间: __**<t:{}:f>**__", deadline.timestamp())
+            }
+            Some(deadline) => format!("当前投票截止时间: __**<t:{}:f>**__", deadline.timestamp()),
+            None => "__**当前投票尚未设定截止时间**__".to_string(),
+        },
+        BallotStatus::Concluded => match options
+            .iter()
+            .find(|option| Some(option.id) == ballot.winner)
+        {
+            Some(winner) => format!(
+                "投票已结束，{}，胜出：{}",
+                outcome(ballot, options),
+                label(&emojis, winner)
+            ),
+            None => format!("投票已结束，{}", outcome(ballot, options)),
+        },
+        BallotStatus::Cancelled => return format!("「{}」投票已关闭", ballot.name),
+    };
+    let title = format!("「{}」{}", ballot.name, title);
+    let rule = match (ballot.status, ballot.mode) {
+        (BallotStatus::Open, BallotMode::Linked) => {
+            Some("一人一票，仅计算已连结 YouTube 频道的帐号".to_string())
+        }
+        (BallotStatus::Open, BallotMode::Coin) => Some(format!(
+            "使用 {} 投入水星币{}",
+            super::command_mentions::get("vote_stake").unwrap_or("/vote stake"),
+            if ballot.refund {
+                "，落选选项将退还"
             } else {
-                Err("您没有权限".to_string())
+                ""
             }
+        )),
+        (BallotStatus::Open, BallotMode::Menu) => {
+            Some("在下方选单中选择，一人一票，可随时改投".to_string())
+        }
+        _ => None,
+    };
+
+    let lines = options.iter().map(|option| match option.votes {
+        Some(votes) => format!(
+            "{}: {} (<@{}>) {}{}",
+            emojis.label(option.flag),
+            option.description,
+            option.nominee,
+            votes,
+            unit(ballot.mode)
+        ),
+        None => format!(
+            "{}: {} (<@{}>)",
+            emojis.label(option.flag),
+            option.description,
+            option.nominee
+        ),
+    });
+
+    std::iter::once(title).chain(rule).chain(lines).join("\n")
+}
+
+fn paginate(text: &str) -> Vec<String> {
+    let mut pages = vec![String::new()];
+    for line in text.lines() {
+        // 單行超過上限時截斷
+        let line: String = line.chars().take(MESSAGE_LIMIT).collect();
+        let page = pages.last_mut().expect("pages is never empty");
+        if page.is_empty() {
+            *page = line;
+        } else if page.chars().count() + 1 + line.chars().count() <= MESSAGE_LIMIT {
+            page.push('\n');
+            page.push_str(&line);
         } else {
-            Err("未找到该提名".to_string())
+            pages.push(line);
         }
     }
+    pages
+}
 
-    pub async fn title(&self, ctx: super::Context<'_>) -> Result<String, ServerError> {
-        if let Some(deadline) = self.deadline {
-            Ok(format!("当前投票截止时间: __**<t:{}:f>**__", deadline))
-        } else {
-            // 使用新的讀取函數
-            let (channel_vote, message_vote_opt) = read_vote_config()?;
+fn menus(ballot: &Ballot, options: &[BallotOption]) -> Vec<CreateActionRow> {
+    let emojis = Emojis::of(ballot);
+    let chunks: Vec<&[BallotOption]> = options.chunks(MENU_SIZE).collect();
 
-            // 處理 None 的情況
-            let message_vote = match message_vote_opt {
-                Some(id) => id,
-                None => return Ok("# __**当前没有投票**__".to_string()),
+    chunks
+        .iter()
+        .enumerate()
+        .map(|(page, chunk)| {
+            let choices = chunk
+                .iter()
+                .map(|option| {
+                    let description: String = option.description.chars().take(90).collect();
+                    match emojis.reaction(option.flag) {
+                        Some(emoji) => {
+                            CreateSelectMenuOption::new(description, option.id.to_string())
+                                .emoji(emoji)
+                        }
+                        None => CreateSelectMenuOption::new(
+                            format!("{} {}", emojis.label(option.flag), description),
+                            option.id.to_string(),
+                        ),
+                    }
+                })
+                .collect();
+            let placeholder = match chunks.len() {
+                1 => "选择一个选项".to_string(),
+                _ => format!(
+                    "选择一个选项 ({}-{})",
+                    page * MENU_SIZE + 1,
+                    page * MENU_SIZE + chunk.len()
+                ),
             };
 
-            let reactions = ChannelId::from(channel_vote)
-                .message(&ctx.http(), message_vote)
-                .await?
-                .reactions;
+            CreateActionRow::SelectMenu(
+                CreateSelectMenu::new(
+                    format!("ballot:{}:{}", ballot.id, page),
+                    CreateSelectMenuKind::String { options: choices },
+                )
+                .placeholder(placeholder),
+            )
+        })
+        .collect()
+}
 
-            let reactions = reactions
-                .iter()
-                .filter_map(|r| {
-                    Flag::try_from(r.reaction_type.clone())
-                        .map(|f| (f, r.count))
-                        .ok()
-                })
-                .filter(|(f, _)| self.options.contains_key(f));
-
-            if let Some(max_count) = reactions.clone().map(|(_, c)| c).max() {
-                Ok(format!(
-                    "__**当前最高票{}票, 是{}**__",
-                    max_count,
-                    reactions
-                        .filter(|(_, c)| *c == max_count)
-                        .map(|(f, _)| f.str())
-                        .join(", "),
-                ))
-            } else {
-                Ok("# __**当前没有投票**__".to_string())
+/**
+ * regenerate the ballot messages from the database, sending them again if
+ * they were deleted.
+ */
+pub async fn render(http: &Http, ballot_id: i64) -> Result<(), ServerError> {
+    let _guard = RENDER.lock().await;
+
+    let (mut ballot, options) = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let ballot = Ballot::by_id(ballot_id, &transaction)?
+            .ok_or_else(|| ServerError::Internal(format!("ballot {} not found", ballot_id)))?;
+        let options = BallotOption::by_ballot(ballot.id, &transaction)?;
+        transaction.commit()?;
+        (ballot, options)
+    };
+    let mut pages = paginate(&view(&ballot, &options)).into_iter();
+    let content = pages.next().unwrap_or_default();
+    let components = match (ballot.status, ballot.mode) {
+        (BallotStatus::Open, BallotMode::Menu) => menus(&ballot, &options),
+        _ => Vec::new(),
+    };
+    let channel = ChannelId::from(ballot.channel);
+
+    let existing = match ballot.message {
+        // Discord 找不到該訊息時重新發送
+        Some(message) => channel.message(http, MessageId::from(message)).await.ok(),
+        None => None,
+    };
+    let (message, resent) = match existing {
+        Some(mut message) => {
+            message
+                .edit(
+                    http,
+                    EditMessage::new().content(content).components(components),
+                )
+                .await?;
+            (message, false)
+        }
+        None => {
+            let message = channel
+                .send_message(
+                    http,
+                    CreateMessage::new().content(content).components(components),
+                )
+                .await?;
+            (message, true)
+        }
+    };
+
+    // 主訊息重發時後續頁面也重發，保持順序
+    let old_pages: Vec<u64> = ballot
+        .pages
+        .iter()
+        .flat_map(|pages| pages.split_whitespace())
+        .filter_map(|page| page.parse().ok())
+        .collect();
+    let mut kept = if resent {
+        Vec::new()
+    } else {
+        old_pages.clone()
+    }
+    .into_iter();
+    let mut new_pages = Vec::new();
+    for content in pages {
+        let existing = match kept.next() {
+            Some(page) => channel.message(http, MessageId::from(page)).await.ok(),
+            None => None,
+        };
+        let page = match existing {
+            Some(mut page) => {
+                page.edit(http, EditMessage::new().content(content)).await?;
+                page.id.get()
             }
+            None => channel
+                .send_message(http, CreateMessage::new().content(content))
+                .await?
+                .id
+                .get(),
+        };
+        new_pages.push(page);
+    }
+    for page in old_pages.iter().filter(|page| !new_pages.contains(page)) {
+        if let Err(err) = channel.delete_message(http, MessageId::from(*page)).await {
+            log::warn!(
+                "fail to delete page {} of ballot {}: {}",
+                page,
+                ballot.id,
+                err
+            );
+        }
+    }
+
+    let pages = Some(new_pages.iter().join(" ")).filter(|pages| !pages.is_empty());
+    if resent || pages != ballot.pages {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        ballot.message = Some(message.id.get());
+        ballot.pages = pages;
+        ballot.update(&transaction)?;
+        transaction.commit()?;
+    }
 
-            // match reactions.len() {
-            //     0 => Ok("__**当前没有投票**__".to_string()),
-            //     _ => Ok(format!(
-            //         "__**当前最高票: {}，有 {} 票**__",
-            //         reactions
-            //             .iter()
-            //             .map(|r| Flag::try_from(r.reaction_type.clone()))
-            //             .filter_map(Result::ok)
-            //             .map(|f| f.str())
-            //             .join(", "),
-            //         reactions[0].count
-            //     )),
-            // }
+    // 結束後保留反應，方便查看票數
+    let emojis = Emojis::of(&ballot);
+    let slots: Vec<u32> = match ballot.status {
+        // 水星幣與選單投票不看反應
+        BallotStatus::Open if matches!(ballot.mode, BallotMode::Coin | BallotMode::Menu) => {
+            Vec::new()
+        }
+        BallotStatus::Open => options.iter().map(|option| option.flag).collect(),
+        BallotStatus::Cancelled => Vec::new(),
+        BallotStatus::Concluded => return Ok(()),
+    };
+
+    // remove reactions that are no longer in options
+    for reaction in message.reactions.clone() {
+        let keep = emojis
+            .slot(&reaction.reaction_type)
+            .is_some_and(|slot| slots.contains(&slot));
+        if !keep {
+            message
+                .delete_reaction_emoji(http, reaction.reaction_type.clone())
+                .await?;
         }
     }
-}
 
-#[derive(Debug, Clone)]
-struct VoteOption {
-    flag: Flag,
-    description: String,
-    nominee: UserId,
+    // add reactions that are in options but not in reactions
+    for emoji in slots.iter().filter_map(|slot| emojis.reaction(*slot)) {
+        let reacted = message
+            .reactions
+            .iter()
+            .any(|reaction| same(&reaction.reaction_type, &emoji));
+        if !reacted {
+            message.react(http, emoji).await?;
+        }
+    }
+
+    Ok(())
 }
 
-impl VoteOption {
-    fn to_string(&self) -> String {
-        format!(
-            "{}: {} (<@{}>)",
-            self.flag.str(),
-            self.description,
-            self.nominee
+async fn reply_ephemeral(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    content: impl Into<String>,
+) -> Result<(), ServerError> {
+    component
+        .create_response(
+            &ctx.http,
+            CreateInteractionResponse::Message(
+                CreateInteractionResponseMessage::default()
+                    .ephemeral(true)
+                    .content(content),
+            ),
         )
-    }
+        .await?;
+    Ok(())
+}
 
-    fn parse(text: &str) -> Option<Self> {
-        if let Some((icon, rest)) = text.split_once(": ") {
-            if let Some((desc, nominee)) = rest.rsplit_once(" (<@") {
-                if let Some(nominee) = nominee.strip_suffix(">)") {
-                    if let Some(flag) = Flag::try_from(icon).ok() {
-                        if let Ok(nominee) = nominee.parse::<u64>() {
-                            return Some(VoteOption {
-                                flag,
-                                description: desc.trim().to_string(),
-                                nominee: UserId::from(nominee),
-                            });
+async fn handle_menu(
+    ctx: &Context,
+    component: &ComponentInteraction,
+    ballot: i64,
+) -> Result<(), ServerError> {
+    let ComponentInteractionDataKind::StringSelect { values } = &component.data.kind else {
+        return Ok(());
+    };
+    let option = values.first().and_then(|value| value.parse::<i64>().ok());
+
+    let voted = {
+        let mut connection = get_connection()?;
+        let transaction = connection.transaction()?;
+        let voted = match Ballot::by_id(ballot, &transaction)? {
+            Some(ballot)
+                if ballot.status == BallotStatus::Open && ballot.mode == BallotMode::Menu =>
+            {
+                let options = BallotOption::by_ballot(ballot.id, &transaction)?;
+                match options.iter().find(|o| Some(o.id) == option) {
+                    None => Err("该选项已被撤回"),
+                    Some(option) => {
+                        BallotVote {
+                            id: 0,
+                            ballot: ballot.id,
+                            option: option.id,
+                            voter: component.user.id.get(),
+                            created_at: Utc::now(),
                         }
+                        .cast(&transaction)?;
+                        Ok(label(&Emojis::of(&ballot), option))
                     }
                 }
             }
-        }
+            _ => Err("投票已结束"),
+        };
+        transaction.commit()?;
 
-        return None;
+        voted
+    };
+
+    match voted {
+        Ok(option) => {
+            reply_ephemeral(ctx, component, format!("已投给 {}，可随时改投", option)).await
+        }
+        Err(e) => reply_ephemeral(ctx, component, format!("投票失败: {}", e)).await,
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
-struct Flag(u32);
+pub struct VoteEventHandler;
 
-impl Flag {
-    fn id(&self) -> u32 {
-        self.0
+#[serenity::async_trait]
+impl EventHandler for VoteEventHandler {
+    async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
+        if let Interaction::Component(component) = interaction
+            && let Some(id) = component.data.custom_id.strip_prefix("ballot:")
+            && let Some(Ok(ballot)) = id.split(':').next().map(str::parse::<i64>)
+            && let Err(err) = handle_menu(&ctx, &component, ballot).await
+        {
+            log::error!("select menu of ballot {} failed: {:?}", ballot, err);
+            let _ = reply_ephemeral(
+                &ctx,
+                &component,
+                format!("**投票失败。**请稍后再试一次。\n-# Error：{}", err),
+            )
+            .await;
+        }
     }
+}
 
-    fn str(&self) -> &'static str {
-        <&'static str>::from(*self)
+const FLAGS: [&str; 20] = [
+    "🇦🇷", "🇦🇺", "🇧🇷", "🇨🇦", "🇹🇼", "🇫🇷", "🇩🇪", "🇮🇳", "🇮🇩", "🇮🇹", "🇯🇵", "🇰🇷", "🇲🇽", "🇷🇺", "🇸🇦", "🇿🇦",
+    "🇹🇷", "🇬🇧", "🇺🇸", "🇪🇺",
+];
+
+/**
+ * the emojis labelling the options, flags unless the ballot has its own set.
+ * Options past the last emoji are numbered.
+ */
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Emojis(Vec<ReactionType>);
+
+impl Emojis {
+    fn of(ballot: &Ballot) -> Self {
+        match ballot.emojis.as_deref().map(Emojis::parse) {
+            Some(Ok(emojis)) => emojis,
+            _ => Emojis::flags(),
+        }
     }
 
-    fn reaction(&self) -> ReactionType {
-        ReactionType::from(*self)
+    fn flags() -> Self {
+        Emojis(
+            FLAGS
+                .iter()
+                .map(|flag| ReactionType::Unicode(flag.to_string()))
+                .collect(),
+        )
     }
 
-    fn all() -> HashSet<Flag> {
-        (0..20).map(|i| Flag(i)).collect()
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut emojis: Vec<ReactionType> = Vec::new();
+        for token in text.split_whitespace() {
+            // 純 ASCII 的不是表情符號，也會和編號混淆
+            let emoji = match ReactionType::try_from(token) {
+                Ok(ReactionType::Unicode(text)) if text.is_ascii() => None,
+                Ok(emoji) => Some(emoji),
+                Err(_) => None,
+            }
+            .ok_or_else(|| format!("「{}」不是表情符号", token))?;
+            if emojis.iter().any(|other| same(other, &emoji)) {
+                return Err(format!("「{}」重复了", token));
+            }
+            emojis.push(emoji);
+        }
+
+        if emojis.is_empty() {
+            return Err("至少需要一个表情符号".to_string());
+        }
+        Ok(Emojis(emojis))
     }
-}
 
-impl TryFrom<&str> for Flag {
-    type Error = ServerError;
+    fn len(&self) -> u32 {
+        self.0.len() as u32
+    }
 
-    fn try_from(s: &str) -> Result<Self, Self::Error> {
-        match s {
-            "🇦🇷" => Ok(Flag(0)),
-            "🇦🇺" => Ok(Flag(1)),
-            "🇧🇷" => Ok(Flag(2)),
-            "🇨🇦" => Ok(Flag(3)),
-            "🇹🇼" => Ok(Flag(4)),
-            "🇫🇷" => Ok(Flag(5)),
-            "🇩🇪" => Ok(Flag(6)),
-            "🇮🇳" => Ok(Flag(7)),
-            "🇮🇩" => Ok(Flag(8)),
-            "🇮🇹" => Ok(Flag(9)),
-            "🇯🇵" => Ok(Flag(10)),
-            "🇰🇷" => Ok(Flag(11)),
-            "🇲🇽" => Ok(Flag(12)),
-            "🇷🇺" => Ok(Flag(13)),
-            "🇸🇦" => Ok(Flag(14)),
-            "🇿🇦" => Ok(Flag(15)),
-            "🇹🇷" => Ok(Flag(16)),
-            "🇬🇧" => Ok(Flag(17)),
-            "🇺🇸" => Ok(Flag(18)),
-            "🇪🇺" => Ok(Flag(19)),
-            _ => Err(ServerError::Internal("Invalid flag emoji".to_string())),
+    fn reaction(&self, slot: u32) -> Option<ReactionType> {
+        self.0.get(slot as usize).cloned()
+    }
+
+    fn label(&self, slot: u32) -> String {
+        match self.reaction(slot) {
+            Some(emoji) => emoji.to_string(),
+            None => format!("#{}", slot + 1),
         }
     }
-}
 
-impl TryFrom<ReactionType> for Flag {
-    type Error = ServerError;
+    fn slot(&self, reaction: &ReactionType) -> Option<u32> {
+        self.0
+            .iter()
+            .position(|emoji| same(emoji, reaction))
+            .map(|slot| slot as u32)
+    }
 
-    fn try_from(value: ReactionType) -> Result<Self, Self::Error> {
-        match value {
-            ReactionType::Unicode(s) => Flag::try_from(s.as_str()),
-            _ => Err(ServerError::Internal("Invalid reaction type".to_string())),
+    fn find(&self, text: &str) -> Result<u32, ServerError> {
+        let text = text.trim();
+        if let Ok(number) = text.trim_start_matches('#').parse::<u32>()
+            && number > 0
+        {
+            return Ok(number - 1);
         }
+
+        ReactionType::try_from(text)
+            .ok()
+            .and_then(|emoji| self.slot(&emoji))
+            .ok_or_else(|| ServerError::Internal("Invalid option emoji".to_string()))
     }
 }
 
-impl From<Flag> for &'static str {
-    fn from(flag: Flag) -> Self {
-        match flag.0 {
-            0 => "🇦🇷",
-            1 => "🇦🇺",
-            2 => "🇧🇷",
-            3 => "🇨🇦",
-            4 => "🇹🇼",
-            5 => "🇫🇷",
-            6 => "🇩🇪",
-            7 => "🇮🇳",
-            8 => "🇮🇩",
-            9 => "🇮🇹",
-            10 => "🇯🇵",
-            11 => "🇰🇷",
-            12 => "🇲🇽",
-            13 => "🇷🇺",
-            14 => "🇸🇦",
-            15 => "🇿🇦",
-            16 => "🇹🇷",
-            17 => "🇬🇧",
-            18 => "🇺🇸",
-            19 => "🇪🇺",
-            _ => panic!("Invalid flag id"),
+/**
+ * Discord may drop the variation selector or the name of a server emoji
+ */
+fn same(a: &ReactionType, b: &ReactionType) -> bool {
+    match (a, b) {
+        (ReactionType::Custom { id: a, .. }, ReactionType::Custom { id: b, .. }) => a == b,
+        (ReactionType::Unicode(a), ReactionType::Unicode(b)) => {
+            a.trim_end_matches('\u{fe0f}') == b.trim_end_matches('\u{fe0f}')
         }
+        _ => false,
     }
 }
 
-impl From<Flag> for String {
-    fn from(flag: Flag) -> Self {
-        String::from(<&'static str>::from(flag))
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{SeedableRng, rngs::StdRng};
+
+    fn option(flag: u32, votes: Option<i64>) -> BallotOption {
+        BallotOption {
+            id: flag as i64,
+            ballot: 1,
+            flag,
+            description: format!("option {}", flag),
+            nominee: 42,
+            votes,
+            created_at: Utc::now(),
+        }
     }
-}
 
-impl From<Flag> for ReactionType {
-    fn from(flag: Flag) -> Self {
-        ReactionType::Unicode(String::from(<&'static str>::from(flag)))
+    fn ballot(status: BallotStatus) -> Ballot {
+        Ballot {
+            id: 1,
+            name: String::from("game"),
+            channel: 1,
+            message: None,
+            deadline: DateTime::from_timestamp(1_700_000_000, 0),
+            status,
+            created_at: Utc::now(),
+            concluded_at: None,
+            winner: None,
+            runoff_of: None,
+            mode: BallotMode::Reaction,
+            refund: false,
+            max_options: None,
+            max_nominations: None,
+            emojis: None,
+            pages: None,
+        }
+    }
+
+    #[test]
+    fn next_slot_fills_gaps() {
+        assert_eq!(next_slot(&[], 20), Some(0));
+        assert_eq!(next_slot(&[option(0, None), option(2, None)], 20), Some(1));
+
+        let full: Vec<BallotOption> = (0..20).map(|i| option(i, None)).collect();
+        assert_eq!(next_slot(&full, 20), None);
+        assert_eq!(next_slot(&full, MAX_MENU_OPTIONS), Some(20));
+    }
+
+    #[test]
+    fn capacity_follows_mode_and_emojis() {
+        let mut reaction = ballot(BallotStatus::Open);
+        assert_eq!(capacity(&reaction), 20);
+        reaction.emojis = Some(String::from("🍎 🍌 🍇"));
+        assert_eq!(capacity(&reaction), 3);
+
+        let mut menu = reaction.clone();
+        menu.mode = BallotMode::Menu;
+        assert_eq!(capacity(&menu), 125);
+    }
+
+    #[test]
+    fn emojis_parse_custom_sets() {
+        let emojis = Emojis::parse("🍎 <:mercury:600404340292059257>  ❤️").expect("emojis");
+        assert_eq!(emojis.len(), 3);
+        assert_eq!(emojis.label(1), "<:mercury:600404340292059257>");
+        assert_eq!(emojis.label(3), "#4");
+
+        // 以表情符號或編號指定選項
+        assert_eq!(emojis.find("🍎").ok(), Some(0));
+        assert_eq!(emojis.find("#4").ok(), Some(3));
+        assert_eq!(emojis.find("2").ok(), Some(1));
+        assert!(emojis.find("🍌").is_err());
+        assert_eq!(
+            emojis.slot(&ReactionType::Unicode(String::from("❤"))),
+            Some(2)
+        );
+        assert_eq!(
+            emojis.slot(&ReactionType::Custom {
+                animated: false,
+                id: 600404340292059257.into(),
+                name: None,
+            }),
+            Some(1)
+        );
+
+        assert!(Emojis::parse("🍎 🍎").is_err());
+        assert!(Emojis::parse("🍎 abc").is_err());
+        assert!(Emojis::parse("  ").is_err());
+
+        let mut custom = ballot(BallotStatus::Open);
+        custom.emojis = Some(String::from("🍎 🍌"));
+        assert_eq!(Emojis::of(&custom).label(1), "🍌");
+        assert_eq!(Emojis::of(&ballot(BallotStatus::Open)).label(4), "🇹🇼");
+    }
+
+    #[test]
+    fn paginate_splits_between_lines() {
+        assert_eq!(paginate("a\nb"), vec!["a\nb".to_string()]);
+
+        let line = "字".repeat(1500);
+        let text = [line.as_str(), "short", line.as_str()].join("\n");
+        let pages = paginate(&text);
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0], format!("{}\nshort", line));
+        assert_eq!(pages[1], line);
+
+        let long = "x".repeat(MESSAGE_LIMIT + 10);
+        assert_eq!(paginate(&long)[0].len(), MESSAGE_LIMIT);
+    }
+
+    #[test]
+    fn menus_hold_twenty_five_options_each() {
+        let mut menu = ballot(BallotStatus::Open);
+        menu.mode = BallotMode::Menu;
+        let options: Vec<BallotOption> = (0..60).map(|i| option(i, None)).collect();
+
+        assert_eq!(menus(&menu, &options).len(), 3);
+        assert_eq!(menus(&menu, &options[..25]).len(), 1);
+        assert!(menus(&menu, &[]).is_empty());
+    }
+
+    #[test]
+    fn view_renders_from_database() {
+        let options = vec![option(4, None), option(0, None)];
+        assert_eq!(
+            view(&ballot(BallotStatus::Open), &options),
+            "「game」当前投票截止时间: __**<t:1700000000:f>**__\n🇹🇼: option 4 (<@42>)\n🇦🇷: option 0 (<@42>)"
+        );
+
+        let options = vec![option(4, Some(3)), option(0, Some(3)), option(1, Some(1))];
+        assert_eq!(
+            outcome(&ballot(BallotStatus::Concluded), &options),
+            "__**最高票3票, 是🇹🇼, 🇦🇷**__"
+        );
+        assert!(
+            view(&ballot(BallotStatus::Concluded), &options).ends_with("🇦🇺: option 1 (<@42>) 1票")
+        );
+
+        assert_eq!(
+            view(&ballot(BallotStatus::Cancelled), &options),
+            "「game」投票已关闭"
+        );
+        assert_eq!(
+            outcome(&ballot(BallotStatus::Concluded), &[option(0, Some(0))]),
+            "# __**当前没有投票**__"
+        );
+    }
+
+    #[test]
+    fn decide_applies_tie_rule() {
+        let mut rng = StdRng::seed_from_u64(7);
+        let tied = vec![option(0, Some(3)), option(1, Some(3)), option(2, Some(1))];
+
+        assert_eq!(
+            decide(&tied, TieRule::Admin, &mut rng),
+            Decision::AdminPick(vec![0, 1])
+        );
+        assert_eq!(
+            decide(&tied, TieRule::Runoff, &mut rng),
+            Decision::Runoff(vec![0, 1])
+        );
+        match decide(&tied, TieRule::Random, &mut rng) {
+            Decision::Winner(winner) => assert!(winner == 0 || winner == 1),
+            decision => panic!("unexpected {:?}", decision),
+        }
+
+        let clear = vec![option(0, Some(1)), option(1, Some(4))];
+        assert_eq!(
+            decide(&clear, TieRule::Runoff, &mut rng),
+            Decision::Winner(1)
+        );
+        let empty = vec![option(0, Some(0)), option(1, Some(0))];
+        assert_eq!(decide(&empty, TieRule::Random, &mut rng), Decision::NoVotes);
+    }
+
+    #[test]
+    fn announcement_names_tied_options() {
+        let options = vec![option(0, Some(2)), option(1, Some(2))];
+        let text = announcement(
+            &ballot(BallotStatus::Concluded),
+            &options,
+            &Decision::AdminPick(vec![0, 1]),
+            None,
+        );
+        assert!(text.contains("平票：🇦🇷 option 0、🇦🇺 option 1"));
+        assert!(text.ends_with("/vote pick 选出胜出选项"));
+
+        let mut concluded = ballot(BallotStatus::Concluded);
+        concluded.winner = Some(1);
+        assert!(
+            view(&concluded, &options)
+                .starts_with("「game」投票已结束，__**最高票2票, 是🇦🇷, 🇦🇺**__，胜出：🇦🇺 option 1")
+        );
+    }
+
+    #[test]
+    fn target_needs_name_when_several_open() {
+        let game = ballot(BallotStatus::Open);
+        let penalty = Ballot {
+            id: 2,
+            name: String::from("penalty"),
+            ..ballot(BallotStatus::Open)
+        };
+
+        assert_eq!(target(vec![game.clone()], None).map(|b| b.id), Ok(1));
+        assert_eq!(
+            target(vec![], None).map(|b| b.id),
+            Err("当前投票尚未开始".to_string())
+        );
+        assert!(
+            target(vec![game.clone(), penalty.clone()], None)
+                .is_err_and(|e| e.starts_with("进行中的投票不止一个"))
+        );
+        assert_eq!(
+            target(vec![game.clone(), penalty.clone()], Some("penalty")).map(|b| b.id),
+            Ok(2)
+        );
+        assert_eq!(
+            target(vec![game, penalty], Some("music")).map(|b| b.id),
+            Err("找不到进行中的投票「music」".to_string())
+        );
+    }
+
+    #[test]
+    fn nomination_respects_ballot_limits() {
+        let mut limited = ballot(BallotStatus::Open);
+        limited.max_options = Some(2);
+        limited.max_nominations = Some(1);
+
+        assert_eq!(nomination(&limited, &[], 42), Ok(0));
+        assert_eq!(
+            nomination(&limited, &[option(0, None)], 42),
+            Err("您的提名数已达上限")
+        );
+        assert_eq!(nomination(&limited, &[option(0, None)], 7), Ok(1));
+        let mut other = option(1, None);
+        other.nominee = 8;
+        assert_eq!(
+            nomination(&limited, &[option(0, None), other], 7),
+            Err("选项已满")
+        );
+    }
+
+    #[test]
+    fn listing_shows_open_ballots() {
+        let mut coin = Ballot {
+            id: 2,
+            name: String::from("penalty"),
+            channel: 9,
+            deadline: None,
+            ..ballot(BallotStatus::Open)
+        };
+        coin.mode = BallotMode::Coin;
+
+        assert_eq!(
+            listing(&[(ballot(BallotStatus::Open), 3), (coin, 0)]),
+            "「game」<#1> 表情符号计票，3 个选项，截止时间: <t:1700000000:f>\n「penalty」<#9> 水星币投票，0 个选项，尚未设定截止时间"
+        );
+        assert_eq!(listing(&[]), "# __**当前没有投票**__");
+    }
+
+    #[test]
+    fn view_explains_mode() {
+        let mut open = ballot(BallotStatus::Open);
+        open.mode = BallotMode::Coin;
+        open.refund = true;
+        let text = view(&open, &[option(0, None)]);
+        assert!(text.contains("/vote stake 投入水星币，落选选项将退还"));
+
+        let mut concluded = ballot(BallotStatus::Concluded);
+        concluded.mode = BallotMode::Coin;
+        assert!(view(&concluded, &[option(0, Some(500))]).ends_with("500水星币"));
+    }
+
+    #[test]
+    fn one_vote_each_counts_linked_single_choices() {
+        let linked = HashSet::from([1, 2, 3]);
+        // 1 只投一項；2 投了兩項不計；4 沒有連結帳號
+        let votes = vec![(0, 1), (0, 2), (1, 2), (1, 3), (1, 4), (1, 3)];
+
+        let counts = one_vote_each(&votes, &linked);
+        assert_eq!(counts.get(&0), Some(&1));
+        assert_eq!(counts.get(&1), Some(&1));
+        assert_eq!(counts.len(), 2);
     }
-}
 
-impl From<Flag> for u32 {
-    fn from(flag: Flag) -> Self {
-        flag.id()
+    #[test]
+    fn parses_legacy_vote() {
+        let content = "当前投票截止时间: __**<t:1735747140:f>**__\n\
+            🇹🇼: 唱歌回 (<@42>)\n\
+            🇯🇵: 雜談: 近況 (<@7>)\n\
+            not an option";
+        assert_eq!(
+            parse_legacy(content),
+            Some(LegacyVote {
+                deadline: DateTime::from_timestamp(1735747140, 0).expect("timestamp"),
+                options: vec![
+                    (4, String::from("唱歌回"), 42),
+                    (10, String::from("雜談: 近況"), 7),
+                ],
+            })
+        );
+
+        // a concluded vote has nothing to import
+        assert_eq!(
+            parse_legacy("# __**当前没有投票**__\n🇹🇼: 唱歌回 (<@42>)"),
+            None
+        );
+        assert_eq!(parse_legacy("__**当前最高票3票, 是🇹🇼**__"), None);
     }
 }
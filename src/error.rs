@@ -10,11 +10,36 @@ pub enum ServerError {
     Json(serde_json::Error),
     Rusqlite(rusqlite::Error),
     SeaQuery(sea_query::error::Error),
-    Serenity(serenity::Error),
-    YupOauth2(yup_oauth2::Error),
-    Google(google_youtube3::Error),
+    // 較大的錯誤放進 Box，讓 Result 保持小巧
+    #[from(ignore)]
+    Serenity(Box<serenity::Error>),
+    #[from(ignore)]
+    YupOauth2(Box<yup_oauth2::Error>),
+    #[from(ignore)]
+    Google(Box<google_youtube3::Error>),
     Reqwest(reqwest::Error),
+    #[from(ignore)]
+    #[display("YouTube quota exhausted at {_0}")]
+    Quota(String),
     Internal(String),
 }
 
+impl From<serenity::Error> for ServerError {
+    fn from(err: serenity::Error) -> Self {
+        ServerError::Serenity(Box::new(err))
+    }
+}
+
+impl From<yup_oauth2::Error> for ServerError {
+    fn from(err: yup_oauth2::Error) -> Self {
+        ServerError::YupOauth2(Box::new(err))
+    }
+}
+
+impl From<google_youtube3::Error> for ServerError {
+    fn from(err: google_youtube3::Error) -> Self {
+        ServerError::Google(Box::new(err))
+    }
+}
+
 impl ResponseError for ServerError {}
@@ -42,6 +42,22 @@ pub fn verify(token: &str) -> bool {
         .unwrap_or(false)
 }
 
+#[cfg(test)]
+pub(crate) fn test_token() -> String {
+    use jwt::SignWithKey;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("Can't get time")
+        .as_secs();
+    Claims {
+        iat: now.saturating_sub(10),
+        exp: now + 3600,
+    }
+    .sign_with_key(&*PRIVATE_KEY)
+    .expect("fail to sign token")
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
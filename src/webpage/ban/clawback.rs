@@ -0,0 +1,29 @@
+use crate::{
+    database::{
+        self,
+        ledger::{Ledger, Reason},
+    },
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Query {
+    pub token: String,
+}
+
+#[get("/api/ban/clawback")]
+pub async fn handler(query: web::Query<Query>) -> Result<impl Responder, ServerError> {
+    if !auth::verify(&query.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let entries = Ledger::by_reason(Reason::Clawback, &transaction)?;
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(entries))
+}
@@ -0,0 +1,34 @@
+use crate::{
+    database::{self, ban::Ban},
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, post, web};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub token: String,
+    pub youtube: String,
+}
+
+#[post("/api/ban/delete")]
+pub async fn handler(request: web::Json<Request>) -> Result<impl Responder, ServerError> {
+    let request = request.into_inner();
+
+    if !auth::verify(&request.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+
+    let ban = match Ban::by_youtube(&request.youtube, &transaction)? {
+        Some(ban) => ban,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    ban.delete(&transaction)?;
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().finish())
+}
@@ -0,0 +1,3 @@
+pub mod clawback;
+pub mod delete;
+pub mod list;
@@ -1,10 +1,15 @@
 pub mod anonymous;
 pub mod auth;
+pub mod ban;
 pub mod image;
 pub mod leaderboard;
+pub mod multiplier;
+pub mod order;
 pub mod penalty;
 pub mod ping;
+pub mod product;
 pub mod setting;
+pub mod stream;
 pub mod video;
 pub mod wheel;
 
@@ -43,6 +48,11 @@ pub async fn run() -> Result<(), ServerError> {
             .service(video::update::handler)
             .service(video::metadata::handler)
             .service(leaderboard::get::handler)
+            .service(order::list::handler)
+            .service(order::state::handler)
+            .service(product::list::handler)
+            .service(product::insert::handler)
+            .service(product::update::handler)
             .service(penalty::list::handler)
             .service(penalty::insert::handler)
             .service(penalty::delete::handler)
@@ -53,8 +63,19 @@ pub async fn run() -> Result<(), ServerError> {
             .service(setting::backup::handler)
             .service(setting::get::handler)
             .service(setting::set::handler)
+            .service(setting::history::handler)
+            .service(multiplier::list::handler)
+            .service(multiplier::insert::handler)
+            .service(multiplier::delete::handler)
             .service(image::upload::handler)
             .service(image::get::handler)
+            .service(stream::list::handler)
+            .service(stream::get::handler)
+            .service(stream::chat::handler)
+            .service(stream::export::handler)
+            .service(ban::list::handler)
+            .service(ban::delete::handler)
+            .service(ban::clawback::handler)
     })
     .bind(("0.0.0.0", 8080))?
     .run();
@@ -0,0 +1,48 @@
+use crate::{
+    database::{self, multiplier::Multiplier},
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, post, web};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub token: String,
+    pub name: String,
+    pub factor: f64,
+    pub start_at: DateTime<Utc>,
+    pub end_at: DateTime<Utc>,
+    pub broadcast: Option<String>,
+}
+
+#[post("/api/multiplier/insert")]
+pub async fn handler(request: web::Json<Request>) -> Result<impl Responder, ServerError> {
+    let request = request.into_inner();
+
+    if !auth::verify(&request.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    if !request.factor.is_finite() || request.factor < 0.0 || request.start_at >= request.end_at {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let mut multiplier = Multiplier {
+        id: 0,
+        name: request.name,
+        factor: request.factor,
+        start_at: request.start_at,
+        end_at: request.end_at,
+        broadcast: request.broadcast.filter(|broadcast| !broadcast.is_empty()),
+        created_at: Utc::now(),
+    };
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    multiplier.insert(&transaction)?;
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(multiplier))
+}
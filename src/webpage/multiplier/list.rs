@@ -0,0 +1,26 @@
+use crate::{
+    database::{self, multiplier::Multiplier},
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Query {
+    pub token: String,
+}
+
+#[get("/api/multiplier/list")]
+pub async fn handler(query: web::Query<Query>) -> Result<impl Responder, ServerError> {
+    if !auth::verify(&query.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let multipliers = Multiplier::all(&transaction)?;
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(multipliers))
+}
@@ -0,0 +1,51 @@
+use crate::{
+    database::{self, order::Order, user::User},
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+pub struct Query {
+    pub token: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OrderWithBuyer {
+    #[serde(flatten)]
+    pub order: Order,
+    pub youtube: Option<String>,
+    pub display: Option<String>,
+}
+
+#[get("/api/order/list")]
+pub async fn handler(query: web::Query<Query>) -> Result<impl Responder, ServerError> {
+    if !auth::verify(&query.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let orders = Order::all(&transaction)?;
+    let users = User::all(&transaction)?
+        .into_iter()
+        .map(|u| (u.id, u))
+        .collect::<HashMap<_, _>>();
+    transaction.commit()?;
+
+    let result = orders
+        .into_iter()
+        .map(|order| {
+            let buyer = users.get(&order.buyer);
+            OrderWithBuyer {
+                youtube: buyer.map(|u| u.youtube.clone()),
+                display: buyer.map(|u| u.display.clone()),
+                order,
+            }
+        })
+        .collect::<Vec<_>>();
+
+    Ok(HttpResponse::Ok().json(result))
+}
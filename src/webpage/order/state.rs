@@ -0,0 +1,79 @@
+use crate::{
+    database::{
+        self,
+        order::{Order, OrderState},
+    },
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, post, web};
+use chrono::Utc;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub token: String,
+    pub id: i64,
+    pub state: OrderState,
+}
+
+#[post("/api/order/state")]
+pub async fn handler(request: web::Json<Request>) -> Result<impl Responder, ServerError> {
+    let request = request.into_inner();
+
+    if !auth::verify(&request.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    // 退款結果要經 `/refund close` 退還水星幣並記帳，重開要經 `/refund reopen`
+    // 收回已退的水星幣，不能直接改狀態
+    if matches!(
+        request.state,
+        OrderState::RefundRequested | OrderState::Refunded | OrderState::Rejected
+    ) {
+        return Ok(HttpResponse::BadRequest().body("Handle refunds with /refund"));
+    }
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+
+    let mut order = match Order::by_id(request.id, &transaction)? {
+        Some(order) => order,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    if !order.state.can_transition(request.state) {
+        transaction.rollback()?;
+        return Ok(HttpResponse::Conflict().finish());
+    }
+
+    order.transition(request.state, Utc::now(), &transaction)?;
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(order))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{App, http::StatusCode, test};
+
+    #[actix_web::test]
+    async fn refund_states_are_rejected() {
+        let app = test::init_service(App::new().service(handler)).await;
+
+        // a refunded order is reopened only by `/refund reopen`, which reverts the refund
+        for state in ["refund_requested", "refunded", "rejected"] {
+            let request = test::TestRequest::post()
+                .uri("/api/order/state")
+                .set_json(serde_json::json!({
+                    "token": auth::test_token(),
+                    "id": 1,
+                    "state": state,
+                }))
+                .to_request();
+            let response = test::call_service(&app, request).await;
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", state);
+        }
+    }
+}
@@ -0,0 +1,67 @@
+use crate::{
+    database::{
+        self,
+        product::{Pricing, Product},
+    },
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, post, web};
+use chrono::Utc;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub token: String,
+    pub name: String,
+    pub display: String,
+    pub unit: String,
+    pub pricing: Pricing,
+    pub price: i64,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub stock: Option<i64>,
+    pub daily_limit: Option<i64>,
+    pub enabled: bool,
+}
+
+#[post("/api/product/insert")]
+pub async fn handler(request: web::Json<Request>) -> Result<impl Responder, ServerError> {
+    let request = request.into_inner();
+
+    if !auth::verify(&request.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let mut product = Product {
+        id: 0,
+        name: request.name,
+        display: request.display,
+        unit: request.unit,
+        pricing: request.pricing,
+        price: request.price,
+        min_amount: request.min_amount,
+        max_amount: request.max_amount,
+        stock: request.stock,
+        daily_limit: request.daily_limit,
+        enabled: request.enabled,
+        updated_at: Utc::now(),
+    };
+
+    if !super::is_valid(&product) {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+
+    if Product::by_name(&product.name, &transaction)?.is_some() {
+        transaction.rollback()?;
+        return Ok(HttpResponse::Conflict().finish());
+    }
+
+    product.insert(&transaction)?;
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(product))
+}
@@ -0,0 +1,25 @@
+pub mod insert;
+pub mod list;
+pub mod update;
+
+use crate::database::product::{Pricing, Product};
+
+fn is_valid(product: &Product) -> bool {
+    let range_ok = match (product.min_amount, product.max_amount) {
+        (Some(min), Some(max)) => min > 0.0 && min <= max,
+        (Some(min), None) => min > 0.0,
+        (None, Some(max)) => max > 0.0,
+        (None, None) => true,
+    };
+    let doubling_ok =
+        product.pricing != Pricing::Doubling || product.min_amount.unwrap_or(1.0).fract() == 0.0;
+
+    !product.name.is_empty()
+        && !product.name.contains(char::is_whitespace)
+        && !product.display.is_empty()
+        && product.price >= 0
+        && product.stock.is_none_or(|stock| stock >= 0)
+        && product.daily_limit.is_none_or(|limit| limit > 0)
+        && range_ok
+        && doubling_ok
+}
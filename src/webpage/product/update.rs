@@ -0,0 +1,72 @@
+use crate::{
+    database::{
+        self,
+        product::{Pricing, Product},
+    },
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, post, web};
+use chrono::Utc;
+use serde::Deserialize;
+
+/**
+ * the name is kept as is, since orders refer to products by name
+ */
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    pub token: String,
+    pub id: i64,
+    pub display: String,
+    pub unit: String,
+    pub pricing: Pricing,
+    pub price: i64,
+    pub min_amount: Option<f64>,
+    pub max_amount: Option<f64>,
+    pub stock: Option<i64>,
+    pub daily_limit: Option<i64>,
+    pub enabled: bool,
+}
+
+#[post("/api/product/update")]
+pub async fn handler(request: web::Json<Request>) -> Result<impl Responder, ServerError> {
+    let request = request.into_inner();
+
+    if !auth::verify(&request.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+
+    let mut product = match Product::by_id(request.id, &transaction)? {
+        Some(product) => product,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    product.display = request.display;
+    product.unit = request.unit;
+    product.pricing = request.pricing;
+    product.price = request.price;
+    product.min_amount = request.min_amount;
+    product.max_amount = request.max_amount;
+    product.stock = request.stock;
+    product.daily_limit = request.daily_limit;
+    product.enabled = request.enabled;
+    product.updated_at = Utc::now();
+
+    if !super::is_valid(&product) {
+        transaction.rollback()?;
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
+    let updated = product.update(&transaction)?;
+    if updated == 0 {
+        transaction.rollback()?;
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(product))
+}
@@ -20,13 +20,9 @@ pub async fn handler(query: web::Query<Query>) -> Result<impl Responder, ServerE
     let mut connection = crate::database::get_connection()?;
     let transaction = connection.transaction()?;
 
-    let config = match query.id {
-        0 => Config::ChannelPenalty,
-        1 => Config::ChannelCoin,
-        2 => Config::ChannelVote,
-        3 => Config::MessageVote,
-        4 => Config::YoutubeChannelId,
-        _ => return Ok(HttpResponse::BadRequest().finish()),
+    let config = match Config::try_from(query.id) {
+        Ok(config) => config,
+        Err(_) => return Ok(HttpResponse::BadRequest().finish()),
     };
     let value = config.get(&transaction)?;
     Ok(HttpResponse::Ok().json(serde_json::json!({ "value": value })))
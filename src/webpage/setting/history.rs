@@ -0,0 +1,30 @@
+use crate::database::config::Config;
+use crate::error::ServerError;
+use crate::webpage::auth;
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Query {
+    pub token: String,
+    pub id: i32,
+}
+
+#[get("/api/setting/history")]
+pub async fn handler(query: web::Query<Query>) -> Result<impl Responder, ServerError> {
+    if !auth::verify(&query.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let config = match Config::try_from(query.id) {
+        Ok(config) => config,
+        Err(_) => return Ok(HttpResponse::BadRequest().finish()),
+    };
+
+    let mut connection = crate::database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let history = config.history(&transaction)?;
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(history))
+}
@@ -1,3 +1,4 @@
 pub mod backup;
 pub mod get;
+pub mod history;
 pub mod set;
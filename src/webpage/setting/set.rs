@@ -1,6 +1,10 @@
+use crate::coin::config::CoinConfig;
+use crate::database::ballot::TieRule;
+use crate::database::ban::BanPolicy;
 use crate::database::config::Config;
 use crate::error::ServerError;
 use crate::webpage::auth;
+use crate::youtube::handler::parse_flags;
 use actix_web::{HttpResponse, Responder, post, web};
 use serde::Deserialize;
 
@@ -17,15 +21,22 @@ pub async fn handler(request: web::Json<Request>) -> Result<impl Responder, Serv
         return Ok(HttpResponse::Forbidden().finish());
     }
 
-    let config = match request.id {
-        0 => Config::ChannelPenalty,
-        1 => Config::ChannelCoin,
-        2 => Config::ChannelVote,
-        3 => Config::MessageVote,
-        4 => Config::YoutubeChannelId,
-        _ => return Ok(HttpResponse::BadRequest().finish()),
+    let config = match Config::try_from(request.id) {
+        Ok(config) => config,
+        Err(_) => return Ok(HttpResponse::BadRequest().finish()),
     };
 
+    let valid = match config {
+        Config::CoinRule => CoinConfig::parse(&request.value).is_some(),
+        Config::ChatHandler => parse_flags(&request.value).is_some(),
+        Config::BanPolicy => BanPolicy::parse(&request.value).is_some(),
+        Config::VoteTieRule => TieRule::parse(&request.value).is_some(),
+        _ => true,
+    };
+    if !valid {
+        return Ok(HttpResponse::BadRequest().finish());
+    }
+
     let mut connection = crate::database::get_connection()?;
     let transaction = connection.transaction()?;
     config.set(request.value.clone(), &transaction)?;
@@ -0,0 +1,53 @@
+use crate::{
+    database::{
+        self,
+        chat::{Archived, Search},
+        stream::StreamSession,
+    },
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, get, web};
+use serde::Deserialize;
+
+const DEFAULT_LIMIT: u64 = 200;
+const MAX_LIMIT: u64 = 1000;
+
+#[derive(Debug, Deserialize)]
+pub struct Query {
+    pub token: String,
+    pub text: Option<String>,
+    pub author: Option<String>,
+    pub kind: Option<String>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[get("/api/stream/chat/{id}")]
+pub async fn handler(
+    path: web::Path<i64>,
+    query: web::Query<Query>,
+) -> Result<impl Responder, ServerError> {
+    if !auth::verify(&query.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+    let query = query.into_inner();
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let session = match StreamSession::by_id(path.into_inner(), &transaction)? {
+        Some(session) => session,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let search = Search {
+        text: query.text.filter(|text| !text.is_empty()),
+        author: query.author.filter(|author| !author.is_empty()),
+        kind: query.kind.filter(|kind| !kind.is_empty()),
+        limit: Some(query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT)),
+        offset: query.offset,
+    };
+    let messages = Archived::search(&session.chat_id, &search, &transaction)?;
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(messages))
+}
@@ -0,0 +1,145 @@
+use crate::{
+    database::{
+        self,
+        chat::{Archived, Search},
+        stream::StreamSession,
+    },
+    error::ServerError,
+    webpage::auth,
+};
+use actix_web::{HttpResponse, Responder, get, web};
+use chrono::FixedOffset;
+use serde::Deserialize;
+
+#[derive(Debug, Deserialize)]
+pub struct Query {
+    pub token: String,
+}
+
+/**
+ * the whole chat of a stream as CSV, timestamps in UTC+8.
+ */
+#[get("/api/stream/export/{id}")]
+pub async fn handler(
+    path: web::Path<i64>,
+    query: web::Query<Query>,
+) -> Result<impl Responder, ServerError> {
+    if !auth::verify(&query.token) {
+        return Ok(HttpResponse::Forbidden().finish());
+    }
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let session = match StreamSession::by_id(path.into_inner(), &transaction)? {
+        Some(session) => session,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let messages = Archived::search(&session.chat_id, &Search::default(), &transaction)?;
+    transaction.commit()?;
+
+    let filename = format!("chat-{}.csv", session.broadcast);
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .append_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", filename),
+        ))
+        .body(to_csv(&messages)))
+}
+
+fn to_csv(messages: &[Archived]) -> String {
+    let offset = FixedOffset::east_opt(8 * 3600).expect("Can't offset time.");
+
+    // BOM 讓 Excel 以 UTF-8 開啟
+    let mut csv = String::from("\u{feff}time,author,author_name,kind,text,message_id\r\n");
+    for message in messages {
+        let row = [
+            message
+                .published_at
+                .with_timezone(&offset)
+                .format("%Y-%m-%d %H:%M:%S")
+                .to_string(),
+            message.author.clone().unwrap_or_default(),
+            defuse(&message.author_name),
+            message.kind.clone(),
+            defuse(&message.text),
+            message.message_id.clone(),
+        ];
+        let row: Vec<String> = row.iter().map(|field| escape(field)).collect();
+        csv.push_str(&row.join(","));
+        csv.push_str("\r\n");
+    }
+
+    csv
+}
+
+/**
+ * keep Excel from running viewer text as a formula
+ */
+fn defuse(field: &str) -> String {
+    if field.starts_with(['=', '+', '-', '@']) {
+        format!("'{}", field)
+    } else {
+        field.to_string()
+    }
+}
+
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::DateTime;
+
+    #[test]
+    fn csv_quotes_fields_and_uses_local_time() {
+        let messages = vec![Archived {
+            message_id: String::from("m1"),
+            chat_id: String::from("chat"),
+            author: Some(String::from("UCfan")),
+            author_name: String::from("Fan"),
+            kind: String::from("textMessageEvent"),
+            text: String::from("他說 \"好,笑\""),
+            published_at: DateTime::from_timestamp(0, 0).expect("timestamp"),
+        }];
+
+        let csv = to_csv(&messages);
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(
+            lines[1],
+            "1970-01-01 08:00:00,UCfan,Fan,textMessageEvent,\"他說 \"\"好,笑\"\"\",m1"
+        );
+        assert_eq!(escape("plain"), "plain");
+    }
+
+    #[test]
+    fn csv_defuses_formulas() {
+        let messages = vec![Archived {
+            message_id: String::from("m2"),
+            chat_id: String::from("chat"),
+            author: Some(String::from("UCfan")),
+            author_name: String::from("@Fan"),
+            kind: String::from("textMessageEvent"),
+            text: String::from("=HYPERLINK(\"http://x\",\"y\")"),
+            published_at: DateTime::from_timestamp(0, 0).expect("timestamp"),
+        }];
+
+        let csv = to_csv(&messages);
+        let lines: Vec<&str> = csv.split("\r\n").collect();
+        assert_eq!(
+            lines[1],
+            "1970-01-01 08:00:00,UCfan,'@Fan,textMessageEvent,\"'=HYPERLINK(\"\"http://x\"\",\"\"y\"\")\",m2"
+        );
+        for text in ["+1", "-1", "@a", "=1"] {
+            assert!(defuse(text).starts_with('\''));
+        }
+        assert_eq!(defuse("a=1"), "a=1");
+    }
+}
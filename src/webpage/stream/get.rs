@@ -0,0 +1,20 @@
+use crate::{
+    database::{self, stream::StreamSession},
+    error::ServerError,
+};
+use actix_web::{HttpResponse, Responder, get, web};
+
+#[get("/api/stream/get/{id}")]
+pub async fn handler(path: web::Path<i64>) -> Result<impl Responder, ServerError> {
+    let id = path.into_inner();
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let session = match StreamSession::by_id(id, &transaction)? {
+        Some(session) => session,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(session))
+}
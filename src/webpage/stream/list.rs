@@ -0,0 +1,15 @@
+use crate::{
+    database::{self, stream::StreamSession},
+    error::ServerError,
+};
+use actix_web::{HttpResponse, Responder, get};
+
+#[get("/api/stream/list")]
+pub async fn handler() -> Result<impl Responder, ServerError> {
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let sessions = StreamSession::all(&transaction)?;
+    transaction.commit()?;
+
+    Ok(HttpResponse::Ok().json(sessions))
+}
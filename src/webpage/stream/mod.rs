@@ -0,0 +1,4 @@
+pub mod chat;
+pub mod export;
+pub mod get;
+pub mod list;
@@ -1,257 +1,291 @@
-use crate::error::ServerError;
-use chrono::{DateTime, Utc};
-use google_youtube3::{YouTube, api::LiveChatMessage, common::Connector};
-
-macro_rules! unwrap_or_return {
-    ($name:ident, $source:ident) => {
-        let $name = match $name($source) {
-            Some(x) => x,
-            None => return Ok(()),
-        };
-    };
+use super::{
+    command::LiveChatApi,
+    event::{ChatEvent, ChatMessage},
+    handler::{ChatHandler, ChatPipeline},
+};
+use crate::{
+    coin::{command::CoinCommandManager, youtube::Support},
+    error::ServerError,
+};
+use async_trait::async_trait;
+
+/**
+ * every chat handler, in the order a message goes through them.
+ */
+pub fn pipeline(coin: CoinCommandManager) -> ChatPipeline {
+    ChatPipeline::new()
+        .register(logging::Handler)
+        .register(moderation::Handler)
+        .register(coin::Handler::new())
+        .register(command::Handler::new(coin))
 }
 
-fn event_type(chat: &LiveChatMessage) -> Option<&String> {
-    if let Some(content) = chat.snippet.as_ref() {
-        if let Some(type_) = content.type_.as_ref() {
-            return Some(type_);
-        }
-    }
+pub mod logging {
+    use super::*;
 
-    None
-}
+    pub struct Handler;
 
-fn published_at(chat: &LiveChatMessage) -> Option<DateTime<Utc>> {
-    if let Some(content) = chat.snippet.as_ref() {
-        if let Some(message) = content.published_at.as_ref() {
-            return Some(*message);
+    #[async_trait]
+    impl ChatHandler for Handler {
+        fn name(&self) -> &'static str {
+            "logging"
         }
-    }
 
-    None
-}
-
-fn message(chat: &LiveChatMessage) -> Option<&String> {
-    if let Some(content) = chat.snippet.as_ref() {
-        if let Some(message) = content.display_message.as_ref() {
-            return Some(message);
+        fn enabled_by_default(&self) -> bool {
+            false
         }
-    }
 
-    None
-}
+        async fn run(&self, _: &dyn LiveChatApi, message: &ChatMessage) -> Result<(), ServerError> {
+            println!("{}: {:?}", message.author.id, message.event);
 
-fn author_id(chat: &LiveChatMessage) -> Option<&String> {
-    if let Some(content) = chat.author_details.as_ref() {
-        if let Some(id) = content.channel_id.as_ref() {
-            return Some(id);
+            Ok(())
         }
     }
-
-    None
 }
 
-fn author_name(chat: &LiveChatMessage) -> Option<&String> {
-    if let Some(content) = chat.author_details.as_ref() {
-        if let Some(name) = content.display_name.as_ref() {
-            return Some(name);
-        }
-    }
+pub mod coin {
+    use super::*;
+    use crate::coin::youtube::CoinChatManager;
 
-    None
-}
+    pub struct Handler {
+        manager: CoinChatManager,
+    }
 
-fn is_sponsor(chat: &LiveChatMessage) -> Option<bool> {
-    if let Some(content) = chat.author_details.as_ref() {
-        if let Some(is_sponsor) = content.is_chat_sponsor.as_ref() {
-            return Some(*is_sponsor);
+    impl Handler {
+        pub fn new() -> Self {
+            Self {
+                manager: CoinChatManager::new(),
+            }
         }
     }
 
-    None
-}
-
-pub mod logging {
-    use super::*;
+    #[async_trait]
+    impl ChatHandler for Handler {
+        fn name(&self) -> &'static str {
+            "coin"
+        }
 
-    #[allow(dead_code)]
-    pub async fn run<C>(_: &YouTube<C>, chat: &LiveChatMessage) -> Result<(), ServerError>
-    where
-        C: Connector,
-    {
-        unwrap_or_return!(author_id, chat);
-        unwrap_or_return!(message, chat);
+        async fn run(&self, _: &dyn LiveChatApi, message: &ChatMessage) -> Result<(), ServerError> {
+            let author = &message.author;
+            let manager = &self.manager;
+
+            if let ChatEvent::Text { .. } = message.event {
+                manager.chat(
+                    &message.id,
+                    &message.chat_id,
+                    &author.id,
+                    &author.name,
+                    author.is_sponsor,
+                    message.published_at,
+                )?;
+            } else if let Some(support) = support(&message.event) {
+                manager.support(
+                    &message.id,
+                    &message.chat_id,
+                    &author.id,
+                    &author.name,
+                    &support,
+                    message.published_at,
+                )?;
+            }
 
-        println!("{}: {}", author_id, message);
+            Ok(())
+        }
+    }
 
-        Ok(())
+    pub(super) fn support(event: &ChatEvent) -> Option<Support> {
+        match event {
+            ChatEvent::SuperChat {
+                amount_micros,
+                currency,
+                ..
+            } => Some(Support::SuperChat {
+                amount_micros: *amount_micros,
+                currency: currency.clone(),
+            }),
+            ChatEvent::SuperSticker {
+                amount_micros,
+                currency,
+            } => Some(Support::SuperSticker {
+                amount_micros: *amount_micros,
+                currency: currency.clone(),
+            }),
+            ChatEvent::NewMember { .. } => Some(Support::NewSponsor),
+            ChatEvent::MemberMilestone { .. } => Some(Support::MemberMilestone),
+            ChatEvent::GiftPurchase { count, .. } => {
+                Some(Support::MembershipGifting { count: *count })
+            }
+            // 收到贈送的會員不另外給幣，贈送者已經拿過
+            ChatEvent::Text { .. }
+            | ChatEvent::GiftRedemption { .. }
+            | ChatEvent::MessageDeleted { .. }
+            | ChatEvent::UserBanned { .. }
+            | ChatEvent::ChatEnded
+            | ChatEvent::Other { .. } => None,
+        }
     }
 }
 
-pub mod coin {
+pub mod moderation {
     use super::*;
-    use crate::coin::youtube::CoinChatManager;
-    use serenity::futures::lock::Mutex;
-    use std::sync::LazyLock;
+    use crate::{coin::moderation, database};
 
-    static CONTEXT: LazyLock<Mutex<CoinChatManager>> =
-        LazyLock::new(|| Mutex::new(CoinChatManager::new()));
+    pub struct Handler;
 
-    pub async fn run<C>(_: &YouTube<C>, chat: &LiveChatMessage) -> Result<(), ServerError>
-    where
-        C: Connector,
-    {
-        unwrap_or_return!(event_type, chat);
-        unwrap_or_return!(published_at, chat);
-        unwrap_or_return!(author_id, chat);
-        unwrap_or_return!(author_name, chat);
-        unwrap_or_return!(is_sponsor, chat);
+    #[async_trait]
+    impl ChatHandler for Handler {
+        fn name(&self) -> &'static str {
+            "moderation"
+        }
 
-        let mut manager = CONTEXT.lock().await;
-        manager.chat(author_id, author_name, is_sponsor, event_type, published_at)?;
+        async fn run(&self, _: &dyn LiveChatApi, message: &ChatMessage) -> Result<(), ServerError> {
+            let mut connection = database::get_connection()?;
+            let transaction = connection.transaction()?;
+
+            match &message.event {
+                ChatEvent::MessageDeleted { message_id } => {
+                    if let Some(entry) = moderation::clawback_message(
+                        message_id,
+                        &message.author.id,
+                        message.published_at,
+                        &transaction,
+                    )? {
+                        println!(
+                            "[-] user {} lose ${} for deleted message",
+                            entry.user, -entry.amount
+                        );
+                    }
+                }
+                // 禁言（限時）不影響水星幣，只處理永久封鎖
+                ChatEvent::UserBanned {
+                    user_id,
+                    duration_seconds: None,
+                    ..
+                } => {
+                    let ban = moderation::ban(
+                        &message.id,
+                        user_id,
+                        &message.author.id,
+                        message.published_at,
+                        &transaction,
+                    )?;
+                    println!("[-] user {} banned, coins {:?}", user_id, ban.policy);
+                }
+                ChatEvent::UserBanned { .. }
+                | ChatEvent::Text { .. }
+                | ChatEvent::SuperChat { .. }
+                | ChatEvent::SuperSticker { .. }
+                | ChatEvent::NewMember { .. }
+                | ChatEvent::MemberMilestone { .. }
+                | ChatEvent::GiftPurchase { .. }
+                | ChatEvent::GiftRedemption { .. }
+                | ChatEvent::ChatEnded
+                | ChatEvent::Other { .. } => (),
+            }
+
+            transaction.commit()?;
 
-        Ok(())
+            Ok(())
+        }
     }
 }
 
 pub mod command {
     use super::*;
-    use crate::coin::command::CoinCommandManager;
-    use serenity::futures::lock::Mutex;
-    use std::sync::LazyLock;
-
-    static CONTEXT: LazyLock<Mutex<CoinCommandManager>> =
-        LazyLock::new(|| Mutex::new(CoinCommandManager::new()));
+    use crate::youtube::command::{self, Caller, CommandRegistry};
 
-    pub async fn run<C>(_: &YouTube<C>, chat: &LiveChatMessage) -> Result<(), ServerError>
-    where
-        C: Connector,
-    {
-        unwrap_or_return!(event_type, chat);
-        unwrap_or_return!(published_at, chat);
-        unwrap_or_return!(author_id, chat);
-        unwrap_or_return!(message, chat);
+    pub struct Handler {
+        registry: CommandRegistry,
+    }
 
-        if event_type != "textMessageEvent" {
-            return Ok(());
+    impl Handler {
+        pub fn new(coin: CoinCommandManager) -> Self {
+            Self {
+                registry: command::registry(coin),
+            }
         }
+    }
 
-        if message.starts_with("/purchase booster") {
-            let mut split = message.split_ascii_whitespace();
-
-            // 正確跳過前兩個 token
-            split.next(); // "/purchase"
-            split.next(); // "booster"
-
-            // 解析 Level
-            let level_str = split.next().unwrap_or("");
-            let level = level_str.parse::<i64>().unwrap_or(0);
-
-            if level <= 0 {
-                // 建議改為 <= 0，防止負數或是解析失敗
-                log::warn!("Invalid command format or level: {}", message);
-                return Ok(());
-            }
+    #[async_trait]
+    impl ChatHandler for Handler {
+        fn name(&self) -> &'static str {
+            "command"
+        }
 
-            // 收集剩餘部分
-            let remaining: Vec<&str> = split.collect();
-            let content = if remaining.is_empty() {
-                // 如果沒有剩餘內容，給予預設值
-                "(未指定內容)".to_string()
-            } else {
-                // 如果有內容，則用空格組合回來
-                remaining.join(" ")
+        async fn run(
+            &self,
+            api: &dyn LiveChatApi,
+            message: &ChatMessage,
+        ) -> Result<(), ServerError> {
+            let text = match &message.event {
+                ChatEvent::Text { text } => text,
+                ChatEvent::SuperChat { .. }
+                | ChatEvent::SuperSticker { .. }
+                | ChatEvent::NewMember { .. }
+                | ChatEvent::MemberMilestone { .. }
+                | ChatEvent::GiftPurchase { .. }
+                | ChatEvent::GiftRedemption { .. }
+                | ChatEvent::MessageDeleted { .. }
+                | ChatEvent::UserBanned { .. }
+                | ChatEvent::ChatEnded
+                | ChatEvent::Other { .. } => return Ok(()),
             };
 
-            let manager = CONTEXT.lock().await;
-            manager
-                .buy_booster(author_id, level, &content, published_at)
-                .await?;
+            let caller = Caller {
+                author_id: &message.author.id,
+                author_name: &message.author.name,
+                now: message.published_at,
+            };
+            self.registry
+                .dispatch(api, &message.chat_id, &caller, text)
+                .await
         }
-
-        Ok(())
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::TimeZone;
-    use google_youtube3::api::{
-        LiveChatMessage, LiveChatMessageAuthorDetails, LiveChatMessageSnippet,
-    };
 
-    fn sample_message() -> LiveChatMessage {
-        LiveChatMessage {
-            snippet: Some(LiveChatMessageSnippet {
-                type_: Some("textMessageEvent".to_string()),
-                published_at: Some(
-                    Utc.timestamp_opt(1_704_164_245, 0)
-                        .single()
-                        .expect("valid timestamp"),
-                ),
-                display_message: Some("/purchase booster 3 Hello".to_string()),
-                ..Default::default()
+    #[test]
+    fn support_maps_paid_and_membership_events() {
+        assert_eq!(
+            coin::support(&ChatEvent::Text {
+                text: "hello".to_string()
             }),
-            author_details: Some(LiveChatMessageAuthorDetails {
-                channel_id: Some("channel-id".to_string()),
-                display_name: Some("Display".to_string()),
-                is_chat_sponsor: Some(true),
-                ..Default::default()
+            None
+        );
+        assert_eq!(
+            coin::support(&ChatEvent::SuperChat {
+                amount_micros: 5_000_000,
+                currency: "USD".to_string(),
+                comment: Some("hi".to_string()),
             }),
-            ..Default::default()
-        }
-    }
-
-    #[test]
-    fn extracts_event_type() {
-        let msg = sample_message();
+            Some(Support::SuperChat {
+                amount_micros: 5_000_000,
+                currency: "USD".to_string(),
+            })
+        );
         assert_eq!(
-            event_type(&msg).map(|s| s.as_str()),
-            Some("textMessageEvent")
+            coin::support(&ChatEvent::GiftPurchase {
+                count: 5,
+                level: None
+            }),
+            Some(Support::MembershipGifting { count: 5 })
         );
-    }
-
-    #[test]
-    fn extracts_published_timestamp() {
-        let msg = sample_message();
-        let expected = Utc
-            .timestamp_opt(1_704_164_245, 0)
-            .single()
-            .expect("valid timestamp");
-        assert_eq!(published_at(&msg), Some(expected));
-    }
-
-    #[test]
-    fn extracts_message_text() {
-        let msg = sample_message();
         assert_eq!(
-            message(&msg).map(|s| s.as_str()),
-            Some("/purchase booster 3 Hello")
+            coin::support(&ChatEvent::NewMember {
+                level: None,
+                is_upgrade: false
+            }),
+            Some(Support::NewSponsor)
+        );
+        assert_eq!(
+            coin::support(&ChatEvent::GiftRedemption {
+                gifter: None,
+                level: None
+            }),
+            None
         );
-    }
-
-    #[test]
-    fn extracts_author_fields() {
-        let msg = sample_message();
-        assert_eq!(author_id(&msg).map(|s| s.as_str()), Some("channel-id"));
-        assert_eq!(author_name(&msg).map(|s| s.as_str()), Some("Display"));
-    }
-
-    #[test]
-    fn extracts_membership_flag() {
-        let msg = sample_message();
-        assert_eq!(is_sponsor(&msg), Some(true));
-    }
-
-    #[test]
-    fn missing_fields_return_none() {
-        let msg = LiveChatMessage::default();
-        assert!(event_type(&msg).is_none());
-        assert!(published_at(&msg).is_none());
-        assert!(message(&msg).is_none());
-        assert!(author_id(&msg).is_none());
-        assert!(author_name(&msg).is_none());
-        assert!(is_sponsor(&msg).is_none());
     }
 }
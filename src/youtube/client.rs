@@ -0,0 +1,398 @@
+use crate::{config::CONFIG, discord, error::ServerError};
+use chrono::{DateTime, FixedOffset, NaiveDate, Utc};
+use google_youtube3::{YouTube, common::Response};
+use serenity::all::CreateMessage;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+pub const DAILY_QUOTA: u64 = 10_000;
+
+/**
+ * share of the quota kept for reading chats, so replies and searches cannot
+ * use it up
+ */
+const READ_RESERVE_DIVISOR: u64 = 4;
+
+const MAX_RETRY: u32 = 5;
+const BACKOFF_BASE: Duration = Duration::from_secs(2);
+const BACKOFF_MAX: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Method {
+    ChannelsList,
+    VideosList,
+    LiveBroadcastsList,
+    SearchList,
+    LiveChatMessagesList,
+    LiveChatMessagesInsert,
+}
+
+impl Method {
+    pub fn cost(self) -> u64 {
+        match self {
+            Method::ChannelsList | Method::VideosList | Method::LiveBroadcastsList => 1,
+            Method::LiveChatMessagesList => 5,
+            Method::LiveChatMessagesInsert => 50,
+            Method::SearchList => 100,
+        }
+    }
+
+    fn uses_reserve(self) -> bool {
+        !matches!(self, Method::LiveChatMessagesInsert | Method::SearchList)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Charge {
+    Charged,
+    Reserved,
+    Exhausted,
+}
+
+/**
+ * quota spent today. Quotas reset at midnight Pacific Time; UTC-8 is used.
+ */
+#[derive(Debug)]
+struct Usage {
+    day: NaiveDate,
+    limit: u64,
+    used: HashMap<Method, u64>,
+    exhausted: bool,
+}
+
+impl Usage {
+    fn new(limit: u64, now: DateTime<Utc>) -> Self {
+        Self {
+            day: quota_day(now),
+            limit,
+            used: HashMap::new(),
+            exhausted: false,
+        }
+    }
+
+    fn roll(&mut self, now: DateTime<Utc>) {
+        let day = quota_day(now);
+        if day != self.day {
+            *self = Self::new(self.limit, now);
+        }
+    }
+
+    fn total(&self) -> u64 {
+        self.used.values().sum()
+    }
+
+    fn charge(&mut self, method: Method, now: DateTime<Utc>) -> Charge {
+        self.roll(now);
+        let total = self.total() + method.cost();
+        if self.exhausted || total > self.limit {
+            return Charge::Exhausted;
+        }
+        if !method.uses_reserve() && total > self.limit - self.limit / READ_RESERVE_DIVISOR {
+            return Charge::Reserved;
+        }
+
+        *self.used.entry(method).or_default() += method.cost();
+        Charge::Charged
+    }
+
+    /**
+     * `true` only the first time the day runs out
+     */
+    fn exhaust(&mut self, now: DateTime<Utc>) -> bool {
+        self.roll(now);
+        !std::mem::replace(&mut self.exhausted, true)
+    }
+
+    fn is_exhausted(&mut self, now: DateTime<Utc>) -> bool {
+        self.roll(now);
+        self.exhausted
+    }
+}
+
+fn quota_day(now: DateTime<Utc>) -> NaiveDate {
+    let offset = FixedOffset::west_opt(8 * 3600).expect("Can't offset time.");
+    now.with_timezone(&offset).date_naive()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Failure {
+    Quota,
+    Auth,
+    Transient,
+    Fatal,
+}
+
+fn classify(err: &google_youtube3::Error) -> Failure {
+    use google_youtube3::Error;
+
+    match err {
+        Error::BadRequest(value) => {
+            let error = &value["error"];
+            let reasons: Vec<&str> = error["errors"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|detail| detail["reason"].as_str())
+                .collect();
+
+            if reasons
+                .iter()
+                .any(|reason| matches!(*reason, "quotaExceeded" | "dailyLimitExceeded"))
+            {
+                Failure::Quota
+            } else if reasons.contains(&"rateLimitExceeded") {
+                Failure::Transient
+            } else {
+                classify_status(error["code"].as_u64().unwrap_or(0) as u16)
+            }
+        }
+        Error::Failure(response) => classify_status(response.status().as_u16()),
+        Error::HttpError(_) | Error::Io(_) => Failure::Transient,
+        Error::MissingToken(_) => Failure::Auth,
+        _ => Failure::Fatal,
+    }
+}
+
+fn classify_status(status: u16) -> Failure {
+    match status {
+        401 => Failure::Auth,
+        429 | 500..=599 => Failure::Transient,
+        _ => Failure::Fatal,
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    BACKOFF_BASE
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(BACKOFF_MAX)
+}
+
+/**
+ * the YouTube hub with quota accounting and retries with backoff.
+ */
+#[derive(Clone)]
+pub struct Client<C> {
+    hub: YouTube<C>,
+    usage: Arc<Mutex<Usage>>,
+}
+
+impl<C> Client<C> {
+    pub fn new(hub: YouTube<C>, limit: u64) -> Self {
+        Self {
+            hub,
+            usage: Arc::new(Mutex::new(Usage::new(limit, Utc::now()))),
+        }
+    }
+
+    pub fn hub(&self) -> &YouTube<C> {
+        &self.hub
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.usage
+            .lock()
+            .expect("quota lock poisoned")
+            .is_exhausted(Utc::now())
+    }
+
+    pub fn used(&self) -> u64 {
+        let mut usage = self.usage.lock().expect("quota lock poisoned");
+        usage.roll(Utc::now());
+        usage.total()
+    }
+
+    pub async fn call<T, F, Fut>(&self, method: Method, request: F) -> Result<T, ServerError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<(Response, T), google_youtube3::Error>>,
+    {
+        let mut attempt = 0;
+        loop {
+            let charge = self
+                .usage
+                .lock()
+                .expect("quota lock poisoned")
+                .charge(method, Utc::now());
+            match charge {
+                Charge::Charged => (),
+                // 保留給讀取聊天室的配額，不算用盡
+                Charge::Reserved => {
+                    log::warn!(
+                        "{:?} skipped, the rest of the quota is kept for chat",
+                        method
+                    );
+                    return Err(ServerError::Quota(format!("{:?}", method)));
+                }
+                Charge::Exhausted => return Err(self.spent(method).await),
+            }
+
+            let err = match request().await {
+                Ok((_, value)) => return Ok(value),
+                Err(err) => err,
+            };
+
+            match classify(&err) {
+                Failure::Transient if attempt < MAX_RETRY => {
+                    let delay = backoff(attempt);
+                    log::warn!("{:?} failed, retry in {:?}: {}", method, delay, err);
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
+                }
+                Failure::Quota => return Err(self.spent(method).await),
+                Failure::Auth => {
+                    log::error!("YouTube refused our authorization: {}", err);
+                    return Err(err.into());
+                }
+                Failure::Transient | Failure::Fatal => return Err(err.into()),
+            }
+        }
+    }
+
+    async fn spent(&self, method: Method) -> ServerError {
+        let first = self
+            .usage
+            .lock()
+            .expect("quota lock poisoned")
+            .exhaust(Utc::now());
+        if first {
+            self.notify_exhausted().await;
+        }
+
+        ServerError::Quota(format!("{:?}", method))
+    }
+
+    async fn notify_exhausted(&self) {
+        let used = self.used();
+        log::warn!("YouTube quota exhausted after {} units", used);
+
+        let Some(admin) = CONFIG.discord.admin.first() else {
+            return;
+        };
+        if let Err(err) = discord::Receiver::UserId(*admin)
+            .message(CreateMessage::new().content(format!(
+                "YouTube API 今日配額已用盡（本程式記錄 {} 單位），聊天室讀取暫停，將於太平洋時間午夜後恢復。",
+                used
+            )))
+            .await
+        {
+            log::error!("fail to notify quota exhaustion: {:?}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2025, 1, 1, hour, 0, 0)
+            .single()
+            .expect("valid timestamp")
+    }
+
+    #[test]
+    fn usage_stops_at_limit_and_resets_next_day() {
+        let mut usage = Usage::new(110, at(0));
+        for _ in 0..20 {
+            assert_eq!(
+                usage.charge(Method::LiveChatMessagesList, at(0)),
+                Charge::Charged
+            );
+        }
+        assert_eq!(
+            usage.charge(Method::LiveChatMessagesList, at(0)),
+            Charge::Charged
+        );
+        assert_eq!(usage.charge(Method::VideosList, at(0)), Charge::Charged);
+        assert_eq!(
+            usage.charge(Method::LiveChatMessagesList, at(0)),
+            Charge::Exhausted
+        );
+        assert_eq!(usage.total(), 106);
+
+        // 08:00 UTC is midnight at UTC-8
+        assert_eq!(
+            usage.charge(Method::LiveChatMessagesList, at(8)),
+            Charge::Charged
+        );
+        assert_eq!(usage.total(), 5);
+    }
+
+    #[test]
+    fn replies_leave_quota_for_reading_chat() {
+        let mut usage = Usage::new(400, at(0));
+        assert_eq!(usage.charge(Method::SearchList, at(0)), Charge::Charged);
+        for _ in 0..4 {
+            assert_eq!(
+                usage.charge(Method::LiveChatMessagesInsert, at(0)),
+                Charge::Charged
+            );
+        }
+        // the last 100 units are kept for reading chats
+        assert_eq!(
+            usage.charge(Method::LiveChatMessagesInsert, at(0)),
+            Charge::Reserved
+        );
+        assert_eq!(usage.charge(Method::SearchList, at(0)), Charge::Reserved);
+        for _ in 0..20 {
+            assert_eq!(
+                usage.charge(Method::LiveChatMessagesList, at(0)),
+                Charge::Charged
+            );
+        }
+        assert_eq!(
+            usage.charge(Method::LiveChatMessagesList, at(0)),
+            Charge::Exhausted
+        );
+        assert!(!usage.exhausted);
+    }
+
+    #[test]
+    fn exhaust_blocks_until_reset() {
+        let mut usage = Usage::new(DAILY_QUOTA, at(0));
+        assert!(usage.exhaust(at(0)));
+        assert!(!usage.exhaust(at(1)));
+        assert_eq!(usage.charge(Method::VideosList, at(1)), Charge::Exhausted);
+        assert_eq!(usage.charge(Method::VideosList, at(9)), Charge::Charged);
+    }
+
+    #[test]
+    fn classify_reads_error_reason() {
+        let quota = google_youtube3::Error::BadRequest(serde_json::json!({
+            "error": {"code": 403, "errors": [{"reason": "quotaExceeded"}]}
+        }));
+        assert_eq!(classify(&quota), Failure::Quota);
+
+        let rate = google_youtube3::Error::BadRequest(serde_json::json!({
+            "error": {"code": 403, "errors": [{"reason": "rateLimitExceeded"}]}
+        }));
+        assert_eq!(classify(&rate), Failure::Transient);
+
+        let unavailable = google_youtube3::Error::BadRequest(serde_json::json!({
+            "error": {"code": 503, "errors": [{"reason": "backendError"}]}
+        }));
+        assert_eq!(classify(&unavailable), Failure::Transient);
+
+        let auth = google_youtube3::Error::BadRequest(serde_json::json!({
+            "error": {"code": 401, "errors": [{"reason": "authError"}]}
+        }));
+        assert_eq!(classify(&auth), Failure::Auth);
+
+        let missing = google_youtube3::Error::BadRequest(serde_json::json!({
+            "error": {"code": 404, "errors": [{"reason": "liveChatNotFound"}]}
+        }));
+        assert_eq!(classify(&missing), Failure::Fatal);
+    }
+
+    #[test]
+    fn backoff_doubles_and_caps() {
+        assert_eq!(backoff(0), Duration::from_secs(2));
+        assert_eq!(backoff(3), Duration::from_secs(16));
+        assert_eq!(backoff(20), BACKOFF_MAX);
+    }
+}
@@ -0,0 +1,703 @@
+use super::client::{Client, Method};
+use crate::{
+    coin::command::CoinCommandManager,
+    database::{self, user::User},
+    error::ServerError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, TimeDelta, Utc};
+use google_youtube3::{
+    api::{LiveChatMessage, LiveChatMessageSnippet, LiveChatTextMessageDetails},
+    common::Connector,
+};
+use std::{collections::HashMap, sync::Mutex};
+
+const MAX_MESSAGE_CHARS: usize = 200;
+
+const UNKNOWN_COOLDOWN: TimeDelta = TimeDelta::minutes(5);
+
+/**
+ * the cooldown key of unknown commands, which no command is named
+ */
+const UNKNOWN: &str = "";
+
+/**
+ * answering in a live chat, behind a trait so commands can be tested.
+ */
+#[async_trait]
+pub trait LiveChatApi: Send + Sync {
+    async fn send(&self, chat_id: &str, text: &str) -> Result<(), ServerError>;
+}
+
+#[async_trait]
+impl<C> LiveChatApi for Client<C>
+where
+    C: Connector,
+{
+    async fn send(&self, chat_id: &str, text: &str) -> Result<(), ServerError> {
+        let message = LiveChatMessage {
+            snippet: Some(LiveChatMessageSnippet {
+                live_chat_id: Some(chat_id.to_string()),
+                type_: Some(String::from("textMessageEvent")),
+                text_message_details: Some(LiveChatTextMessageDetails {
+                    message_text: Some(text.to_string()),
+                }),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+        self.call(Method::LiveChatMessagesInsert, || {
+            self.hub()
+                .live_chat_messages()
+                .insert(message.clone())
+                .doit()
+        })
+        .await?;
+
+        Ok(())
+    }
+}
+
+pub struct Caller<'a> {
+    pub author_id: &'a str,
+    pub author_name: &'a str,
+    pub now: DateTime<Utc>,
+}
+
+pub enum Reply {
+    Text(String),
+    Usage,
+}
+
+/**
+ * a `/name arg...` command typed in the live chat.
+ */
+#[async_trait]
+pub trait ChatCommand: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn usage(&self) -> &'static str;
+
+    fn description(&self) -> &'static str;
+
+    fn cooldown(&self) -> TimeDelta {
+        TimeDelta::seconds(10)
+    }
+
+    async fn execute(
+        &self,
+        registry: &CommandRegistry,
+        caller: &Caller<'_>,
+        args: &[&str],
+    ) -> Result<Reply, ServerError>;
+}
+
+/**
+ * `None` if the message is not a command.
+ */
+pub fn parse(message: &str) -> Option<(&str, Vec<&str>)> {
+    let mut split = message.split_whitespace();
+    let name = split.next()?.strip_prefix('/')?;
+    if name.is_empty() {
+        return None;
+    }
+
+    Some((name, split.collect()))
+}
+
+fn truncate(text: String) -> String {
+    if text.chars().count() <= MAX_MESSAGE_CHARS {
+        return text;
+    }
+
+    let mut text: String = text.chars().take(MAX_MESSAGE_CHARS - 1).collect();
+    text.push('…');
+    text
+}
+
+/**
+ * the live chat commands. Unknown commands are answered at most once per
+ * `UNKNOWN_COOLDOWN`.
+ */
+pub struct CommandRegistry {
+    commands: Vec<Box<dyn ChatCommand>>,
+    used_at: Mutex<HashMap<(String, &'static str), DateTime<Utc>>>,
+}
+
+impl CommandRegistry {
+    pub fn new() -> Self {
+        Self {
+            commands: Vec::new(),
+            used_at: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn register(mut self, command: impl ChatCommand + 'static) -> Self {
+        self.commands.push(Box::new(command));
+        self
+    }
+
+    pub fn find(&self, name: &str) -> Option<&dyn ChatCommand> {
+        self.commands
+            .iter()
+            .find(|command| command.name() == name)
+            .map(|command| command.as_ref())
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.commands.iter().map(|command| command.name())
+    }
+
+    pub async fn dispatch(
+        &self,
+        api: &dyn LiveChatApi,
+        chat_id: &str,
+        caller: &Caller<'_>,
+        message: &str,
+    ) -> Result<(), ServerError> {
+        let Some((name, args)) = parse(message) else {
+            return Ok(());
+        };
+
+        let Some(command) = self.find(name) else {
+            if !self.cool_down(caller, UNKNOWN, UNKNOWN_COOLDOWN) {
+                return Ok(());
+            }
+            let text = format!("未知的指令 /{}，輸入 /help 查看可用指令。", name);
+            return self.reply(api, chat_id, caller, text).await;
+        };
+
+        if !self.cool_down(caller, command.name(), command.cooldown()) {
+            log::info!("{} used /{} during cooldown", caller.author_id, name);
+            return Ok(());
+        }
+
+        let text = match command.execute(self, caller, &args).await {
+            Ok(Reply::Text(text)) => text,
+            Ok(Reply::Usage) => format!("用法：{}", command.usage()),
+            Err(err) => {
+                self.reply(
+                    api,
+                    chat_id,
+                    caller,
+                    String::from("指令執行失敗，請稍後再試。"),
+                )
+                .await?;
+                return Err(err);
+            }
+        };
+
+        self.reply(api, chat_id, caller, text).await
+    }
+
+    fn cool_down(&self, caller: &Caller<'_>, name: &'static str, cooldown: TimeDelta) -> bool {
+        let mut used_at = self.used_at.lock().expect("cooldown lock poisoned");
+        let longest = self
+            .commands
+            .iter()
+            .map(|command| command.cooldown())
+            .fold(UNKNOWN_COOLDOWN, TimeDelta::max);
+        used_at.retain(|_, last| caller.now < *last + longest);
+
+        let key = (caller.author_id.to_string(), name);
+        if let Some(last) = used_at.get(&key)
+            && caller.now < *last + cooldown
+        {
+            return false;
+        }
+
+        used_at.insert(key, caller.now);
+        true
+    }
+
+    async fn reply(
+        &self,
+        api: &dyn LiveChatApi,
+        chat_id: &str,
+        caller: &Caller<'_>,
+        text: String,
+    ) -> Result<(), ServerError> {
+        let text = truncate(format!("@{} {}", caller.author_name, text));
+        api.send(chat_id, &text).await
+    }
+}
+
+pub fn registry(coin: CoinCommandManager) -> CommandRegistry {
+    CommandRegistry::new()
+        .register(Coin)
+        .register(Purchase(coin))
+        .register(Vote)
+        .register(Link)
+        .register(Help)
+}
+
+fn user(youtube: &str) -> Result<Option<User>, ServerError> {
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let user = User::by_youtube(youtube, &transaction)?;
+    transaction.commit()?;
+
+    Ok(user)
+}
+
+pub struct Coin;
+
+#[async_trait]
+impl ChatCommand for Coin {
+    fn name(&self) -> &'static str {
+        "coin"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/coin"
+    }
+
+    fn description(&self) -> &'static str {
+        "查詢水星幣餘額"
+    }
+
+    fn cooldown(&self) -> TimeDelta {
+        TimeDelta::seconds(30)
+    }
+
+    async fn execute(
+        &self,
+        _: &CommandRegistry,
+        caller: &Caller<'_>,
+        _: &[&str],
+    ) -> Result<Reply, ServerError> {
+        let text = match user(caller.author_id)? {
+            Some(user) => format!("您目前有 {} 水星幣。", user.coin),
+            None => String::from("找不到您的水星幣記錄，請先在聊天室留言。"),
+        };
+
+        Ok(Reply::Text(text))
+    }
+}
+
+pub struct Purchase(CoinCommandManager);
+
+/**
+ * `<product> <amount> [content]`
+ */
+pub fn parse_purchase<'a>(args: &[&'a str]) -> Option<(&'a str, f64, String)> {
+    let (product, amount, remaining) = match args {
+        [product, amount, remaining @ ..] => (*product, *amount, remaining),
+        _ => return None,
+    };
+
+    let amount = match amount.parse::<f64>() {
+        Ok(amount) if amount > 0.0 => amount,
+        _ => return None,
+    };
+
+    let content = if remaining.is_empty() {
+        // 如果沒有剩餘內容，給予預設值
+        "(未指定內容)".to_string()
+    } else {
+        // 如果有內容，則用空格組合回來
+        remaining.join(" ")
+    };
+
+    Some((product, amount, content))
+}
+
+#[async_trait]
+impl ChatCommand for Purchase {
+    fn name(&self) -> &'static str {
+        "purchase"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/purchase <商品> <數量> [內容]"
+    }
+
+    fn description(&self) -> &'static str {
+        "使用水星幣購買商品"
+    }
+
+    async fn execute(
+        &self,
+        _: &CommandRegistry,
+        caller: &Caller<'_>,
+        args: &[&str],
+    ) -> Result<Reply, ServerError> {
+        let Some((product, amount, content)) = parse_purchase(args) else {
+            return Ok(Reply::Usage);
+        };
+
+        let text = self
+            .0
+            .purchase(
+                &caller.author_id.to_string(),
+                product,
+                amount,
+                &content,
+                caller.now,
+            )
+            .await?;
+
+        Ok(Reply::Text(text))
+    }
+}
+
+pub struct Vote;
+
+#[async_trait]
+impl ChatCommand for Vote {
+    fn name(&self) -> &'static str {
+        "vote"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/vote"
+    }
+
+    fn description(&self) -> &'static str {
+        "查看如何參與投票"
+    }
+
+    fn cooldown(&self) -> TimeDelta {
+        TimeDelta::seconds(60)
+    }
+
+    async fn execute(
+        &self,
+        _: &CommandRegistry,
+        _: &Caller<'_>,
+        _: &[&str],
+    ) -> Result<Reply, ServerError> {
+        Ok(Reply::Text(String::from(
+            "投票在 Discord 進行，請在 Discord 使用 /vote list 查看進行中的投票，以 /vote nominate 提名，並依各投票的方式投票。",
+        )))
+    }
+}
+
+pub struct Link;
+
+#[async_trait]
+impl ChatCommand for Link {
+    fn name(&self) -> &'static str {
+        "link"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/link"
+    }
+
+    fn description(&self) -> &'static str {
+        "連結 Discord 帳號"
+    }
+
+    fn cooldown(&self) -> TimeDelta {
+        TimeDelta::seconds(60)
+    }
+
+    async fn execute(
+        &self,
+        _: &CommandRegistry,
+        caller: &Caller<'_>,
+        _: &[&str],
+    ) -> Result<Reply, ServerError> {
+        let text = match user(caller.author_id)? {
+            Some(user) if user.discord.is_some() => {
+                String::from("您的 YouTube 頻道已連結 Discord 帳號。")
+            }
+            _ => String::from("請在 Discord 使用 /link，依照私訊說明連結此 YouTube 頻道。"),
+        };
+
+        Ok(Reply::Text(text))
+    }
+}
+
+pub struct Help;
+
+#[async_trait]
+impl ChatCommand for Help {
+    fn name(&self) -> &'static str {
+        "help"
+    }
+
+    fn usage(&self) -> &'static str {
+        "/help [指令]"
+    }
+
+    fn description(&self) -> &'static str {
+        "列出指令或查看指令用法"
+    }
+
+    fn cooldown(&self) -> TimeDelta {
+        TimeDelta::seconds(30)
+    }
+
+    async fn execute(
+        &self,
+        registry: &CommandRegistry,
+        _: &Caller<'_>,
+        args: &[&str],
+    ) -> Result<Reply, ServerError> {
+        let text = match args {
+            [] => format!(
+                "可用指令：{}。輸入 /help <指令> 查看用法。",
+                registry
+                    .names()
+                    .map(|name| format!("/{}", name))
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+            [name, ..] => match registry.find(name.trim_start_matches('/')) {
+                Some(command) => format!("{}：{}", command.usage(), command.description()),
+                None => format!("未知的指令 {}。", name),
+            },
+        };
+
+        Ok(Reply::Text(text))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        coin::command::OrderNotifier,
+        database::{config::Config, order::Order},
+    };
+
+    struct FakeChat {
+        sent: Mutex<Vec<(String, String)>>,
+    }
+
+    impl FakeChat {
+        fn new() -> Self {
+            Self {
+                sent: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn take(&self) -> Vec<(String, String)> {
+            std::mem::take(&mut *self.sent.lock().expect("lock"))
+        }
+    }
+
+    #[async_trait]
+    impl LiveChatApi for FakeChat {
+        async fn send(&self, chat_id: &str, text: &str) -> Result<(), ServerError> {
+            self.sent
+                .lock()
+                .expect("lock")
+                .push((chat_id.to_string(), text.to_string()));
+            Ok(())
+        }
+    }
+
+    struct Echo;
+
+    #[async_trait]
+    impl ChatCommand for Echo {
+        fn name(&self) -> &'static str {
+            "echo"
+        }
+
+        fn usage(&self) -> &'static str {
+            "/echo <文字>"
+        }
+
+        fn description(&self) -> &'static str {
+            "重複文字"
+        }
+
+        async fn execute(
+            &self,
+            _: &CommandRegistry,
+            _: &Caller<'_>,
+            args: &[&str],
+        ) -> Result<Reply, ServerError> {
+            if args.is_empty() {
+                return Ok(Reply::Usage);
+            }
+            Ok(Reply::Text(args.join(" ")))
+        }
+    }
+
+    fn caller(now: DateTime<Utc>) -> Caller<'static> {
+        Caller {
+            author_id: "channel",
+            author_name: "viewer",
+            now,
+        }
+    }
+
+    async fn send(registry: &CommandRegistry, chat: &FakeChat, message: &str, now: DateTime<Utc>) {
+        registry
+            .dispatch(chat, "chat", &caller(now), message)
+            .await
+            .expect("dispatch");
+    }
+
+    #[test]
+    fn parses_commands() {
+        assert_eq!(parse("/coin"), Some(("coin", vec![])));
+        assert_eq!(
+            parse("  /purchase booster 3 "),
+            Some(("purchase", vec!["booster", "3"]))
+        );
+        assert_eq!(parse("hello /coin"), None);
+        assert_eq!(parse("/ coin"), None);
+        assert_eq!(parse(""), None);
+    }
+
+    #[test]
+    fn parses_purchase_arguments() {
+        assert_eq!(
+            parse_purchase(&["booster", "3", "Hello", "world"]),
+            Some(("booster", 3.0, String::from("Hello world")))
+        );
+        assert_eq!(
+            parse_purchase(&["overtime", "0.5"]),
+            Some(("overtime", 0.5, String::from("(未指定內容)")))
+        );
+        assert_eq!(parse_purchase(&["booster", "x"]), None);
+        assert_eq!(parse_purchase(&["booster", "-1"]), None);
+        assert_eq!(parse_purchase(&["booster"]), None);
+        assert_eq!(parse_purchase(&[]), None);
+    }
+
+    #[tokio::test]
+    async fn replies_in_the_chat() {
+        let registry = CommandRegistry::new().register(Echo).register(Help);
+        let chat = FakeChat::new();
+        let now = Utc::now();
+
+        send(&registry, &chat, "/echo hi there", now).await;
+        send(&registry, &chat, "/help", now).await;
+        send(&registry, &chat, "/help echo", now + TimeDelta::minutes(1)).await;
+        send(&registry, &chat, "/nope", now).await;
+        send(&registry, &chat, "just chatting", now).await;
+
+        let sent = chat.take();
+        let texts: Vec<&str> = sent.iter().map(|(_, text)| text.as_str()).collect();
+        assert_eq!(
+            texts,
+            vec![
+                "@viewer hi there",
+                "@viewer 可用指令：/echo /help。輸入 /help <指令> 查看用法。",
+                "@viewer /echo <文字>：重複文字",
+                "@viewer 未知的指令 /nope，輸入 /help 查看可用指令。",
+            ]
+        );
+        assert!(sent.iter().all(|(chat_id, _)| chat_id == "chat"));
+    }
+
+    #[tokio::test]
+    async fn misuse_replies_with_usage() {
+        let registry = CommandRegistry::new().register(Echo);
+        let chat = FakeChat::new();
+
+        send(&registry, &chat, "/echo", Utc::now()).await;
+        assert_eq!(
+            chat.take(),
+            vec![(
+                String::from("chat"),
+                String::from("@viewer 用法：/echo <文字>")
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn cooldown_ignores_repeated_commands() {
+        let registry = CommandRegistry::new().register(Echo);
+        let chat = FakeChat::new();
+        let now = Utc::now();
+
+        send(&registry, &chat, "/echo 1", now).await;
+        send(&registry, &chat, "/echo 2", now + TimeDelta::seconds(5)).await;
+        send(&registry, &chat, "/echo 3", now + TimeDelta::seconds(10)).await;
+
+        let texts: Vec<String> = chat.take().into_iter().map(|(_, text)| text).collect();
+        assert_eq!(texts, vec!["@viewer 1", "@viewer 3"]);
+    }
+
+    #[tokio::test]
+    async fn unknown_commands_reply_once_per_cooldown() {
+        let registry = CommandRegistry::new().register(Echo);
+        let chat = FakeChat::new();
+        let now = Utc::now();
+
+        send(&registry, &chat, "/nope", now).await;
+        send(&registry, &chat, "/other", now + TimeDelta::minutes(1)).await;
+        send(&registry, &chat, "/echo hi", now + TimeDelta::minutes(1)).await;
+        send(&registry, &chat, "/nope", now + UNKNOWN_COOLDOWN).await;
+
+        assert_eq!(chat.take().len(), 3);
+        // only the latest uses are still cooling down
+        assert_eq!(registry.used_at.lock().expect("lock").len(), 2);
+        send(&registry, &chat, "/echo hi", now + TimeDelta::hours(1)).await;
+        assert_eq!(registry.used_at.lock().expect("lock").len(), 1);
+    }
+
+    struct DownNotifier;
+
+    #[async_trait]
+    impl OrderNotifier for DownNotifier {
+        async fn notify(&self, _: u64, _: String) -> Result<u64, ServerError> {
+            Err(ServerError::Internal(String::from("discord is down")))
+        }
+    }
+
+    #[tokio::test]
+    async fn purchase_succeeds_when_the_notice_fails() -> Result<(), ServerError> {
+        let _turn = database::scratch().await;
+        let mut buyer = User {
+            id: 0,
+            youtube: String::from("UCbuyer"),
+            discord: None,
+            coin: 100,
+            display: String::from("Buyer"),
+            updated_at: Utc::now(),
+        };
+        {
+            let mut connection = database::get_connection()?;
+            let transaction = connection.transaction()?;
+            Config::ChannelCoin.set(String::from("1"), &transaction)?;
+            buyer.insert(&transaction)?;
+            transaction.commit()?;
+        }
+
+        let registry = registry(CoinCommandManager::with_notifier(DownNotifier));
+        let chat = FakeChat::new();
+        let caller = Caller {
+            author_id: "UCbuyer",
+            author_name: "Buyer",
+            now: Utc::now(),
+        };
+        registry
+            .dispatch(&chat, "chat", &caller, "/purchase booster 2 Hello")
+            .await?;
+
+        let texts: Vec<String> = chat.take().into_iter().map(|(_, text)| text).collect();
+        assert_eq!(texts.len(), 1);
+        assert!(texts[0].contains("購買成功"), "{}", texts[0]);
+
+        // the order stays without a notice to point at
+        let mut connection = database::get_connection()?;
+        let transaction = connection.transaction()?;
+        let buyer = User::by_youtube("UCbuyer", &transaction)?.expect("buyer");
+        let orders: Vec<_> = Order::all(&transaction)?
+            .into_iter()
+            .filter(|order| order.buyer == buyer.id)
+            .collect();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].message, None);
+        assert_eq!(buyer.coin, 100 - orders[0].cost);
+
+        Ok(())
+    }
+
+    #[test]
+    fn long_replies_are_truncated() {
+        let text = truncate("水".repeat(300));
+        assert_eq!(text.chars().count(), MAX_MESSAGE_CHARS);
+        assert!(text.ends_with('…'));
+        assert_eq!(truncate(String::from("short")), "short");
+    }
+}
@@ -0,0 +1,420 @@
+use super::client::{Client, Method};
+use crate::error::ServerError;
+use async_trait::async_trait;
+use google_youtube3::{
+    api::{LiveBroadcastListResponse, SearchListResponse},
+    common::Connector,
+};
+use regex::Regex;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/**
+ * a way to find the broadcast a channel is live on.
+ */
+#[async_trait]
+pub trait BroadcastDetector: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn detect(&self, channel: &str) -> Result<Vec<String>, ServerError>;
+}
+
+/**
+ * 1 quota unit, but only sees broadcasts of the authorized channel.
+ */
+pub struct BroadcastApiDetector<C> {
+    api: Client<C>,
+}
+
+impl<C> BroadcastApiDetector<C> {
+    pub fn new(api: Client<C>) -> Self {
+        Self { api }
+    }
+
+    fn parse(response: &LiveBroadcastListResponse, channel: &str) -> Vec<String> {
+        response
+            .items
+            .iter()
+            .flatten()
+            .filter(|item| {
+                item.snippet
+                    .as_ref()
+                    .and_then(|snippet| snippet.channel_id.as_deref())
+                    == Some(channel)
+            })
+            .filter_map(|item| item.id.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<C> BroadcastDetector for BroadcastApiDetector<C>
+where
+    C: Connector,
+{
+    fn name(&self) -> &'static str {
+        "broadcast"
+    }
+
+    async fn detect(&self, channel: &str) -> Result<Vec<String>, ServerError> {
+        let response = self
+            .api
+            .call(Method::LiveBroadcastsList, || {
+                self.api
+                    .hub()
+                    .live_broadcasts()
+                    .list(&vec!["id".into(), "snippet".into()])
+                    .broadcast_status("active")
+                    .broadcast_type("all")
+                    .doit()
+            })
+            .await?;
+
+        Ok(Self::parse(&response, channel))
+    }
+}
+
+/**
+ * 100 quota units, but works for any channel.
+ */
+pub struct SearchApiDetector<C> {
+    api: Client<C>,
+}
+
+impl<C> SearchApiDetector<C> {
+    pub fn new(api: Client<C>) -> Self {
+        Self { api }
+    }
+
+    fn parse(response: &SearchListResponse) -> Vec<String> {
+        response
+            .items
+            .iter()
+            .flatten()
+            .filter_map(|item| item.id.as_ref()?.video_id.clone())
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<C> BroadcastDetector for SearchApiDetector<C>
+where
+    C: Connector,
+{
+    fn name(&self) -> &'static str {
+        "search"
+    }
+
+    async fn detect(&self, channel: &str) -> Result<Vec<String>, ServerError> {
+        let response = self
+            .api
+            .call(Method::SearchList, || {
+                self.api
+                    .hub()
+                    .search()
+                    .list(&vec!["id".into()])
+                    .channel_id(channel)
+                    .event_type("live")
+                    .add_type("video")
+                    .doit()
+            })
+            .await?;
+
+        Ok(Self::parse(&response))
+    }
+}
+
+/**
+ * scrape `youtube.com/channel/<id>/live`. Unexpected markup is an error
+ * rather than offline.
+ */
+pub struct ScrapeDetector;
+
+impl ScrapeDetector {
+    fn parse(text: &str) -> Result<Option<String>, ServerError> {
+        if !text.contains("<link rel=\"canonical\"") {
+            return Err(ServerError::Internal(String::from(
+                "Unexpected channel page markup",
+            )));
+        }
+
+        if text.matches("\"isLive\":true").count() >= 2 {
+            let re = Regex::new(r#"video_id=([_0-9a-zA-Z]*)"}"#).unwrap();
+            return match re.captures(text) {
+                Some(captures) => Ok(Some(captures[1].into())),
+                None => Err(ServerError::Internal(String::from(
+                    "Broadcast is live but no id found",
+                ))),
+            };
+        }
+
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl BroadcastDetector for ScrapeDetector {
+    fn name(&self) -> &'static str {
+        "scrape"
+    }
+
+    async fn detect(&self, channel: &str) -> Result<Vec<String>, ServerError> {
+        let response =
+            reqwest::get(format!("https://www.youtube.com/channel/{}/live", channel)).await?;
+        let text = response.text().await?;
+
+        Ok(Self::parse(&text)?.into_iter().collect())
+    }
+}
+
+pub struct FallbackDetector {
+    detectors: Vec<Box<dyn BroadcastDetector>>,
+}
+
+impl FallbackDetector {
+    pub fn new() -> Self {
+        Self {
+            detectors: Vec::new(),
+        }
+    }
+
+    pub fn then(mut self, detector: impl BroadcastDetector + 'static) -> Self {
+        self.detectors.push(Box::new(detector));
+        self
+    }
+}
+
+#[async_trait]
+impl BroadcastDetector for FallbackDetector {
+    fn name(&self) -> &'static str {
+        "fallback"
+    }
+
+    async fn detect(&self, channel: &str) -> Result<Vec<String>, ServerError> {
+        let mut last = None;
+        for detector in &self.detectors {
+            match detector.detect(channel).await {
+                Ok(ids) => return Ok(ids),
+                Err(err) => {
+                    log::warn!(
+                        "{} detector failed for {}: {:?}",
+                        detector.name(),
+                        channel,
+                        err
+                    );
+                    last = Some(err);
+                }
+            }
+        }
+
+        Err(last.unwrap_or_else(|| ServerError::Internal(String::from("No detector available"))))
+    }
+}
+
+/**
+ * ask `inner` about a channel at most once per `interval`, failing in
+ * between.
+ */
+pub struct ThrottledDetector {
+    inner: Box<dyn BroadcastDetector>,
+    interval: Duration,
+    asked_at: Mutex<HashMap<String, Instant>>,
+}
+
+impl ThrottledDetector {
+    pub fn new(inner: impl BroadcastDetector + 'static, interval: Duration) -> Self {
+        Self {
+            inner: Box::new(inner),
+            interval,
+            asked_at: Mutex::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl BroadcastDetector for ThrottledDetector {
+    fn name(&self) -> &'static str {
+        self.inner.name()
+    }
+
+    async fn detect(&self, channel: &str) -> Result<Vec<String>, ServerError> {
+        {
+            let mut asked_at = self.asked_at.lock().expect("throttle lock poisoned");
+            let now = Instant::now();
+            if let Some(last) = asked_at.get(channel)
+                && now < *last + self.interval
+            {
+                return Err(ServerError::Internal(format!(
+                    "{} detector throttled",
+                    self.inner.name()
+                )));
+            }
+            asked_at.insert(channel.to_string(), now);
+        }
+
+        self.inner.detect(channel).await
+    }
+}
+
+/**
+ * `own` for the channels of the authorized account, `other` for the rest.
+ */
+pub struct OwnerDetector {
+    owned: Vec<String>,
+    own: Box<dyn BroadcastDetector>,
+    other: Box<dyn BroadcastDetector>,
+}
+
+impl OwnerDetector {
+    pub fn new(
+        owned: Vec<String>,
+        own: impl BroadcastDetector + 'static,
+        other: impl BroadcastDetector + 'static,
+    ) -> Self {
+        Self {
+            owned,
+            own: Box::new(own),
+            other: Box::new(other),
+        }
+    }
+}
+
+#[async_trait]
+impl BroadcastDetector for OwnerDetector {
+    fn name(&self) -> &'static str {
+        "owner"
+    }
+
+    async fn detect(&self, channel: &str) -> Result<Vec<String>, ServerError> {
+        if self.owned.iter().any(|owned| owned == channel) {
+            self.own.detect(channel).await
+        } else {
+            self.other.detect(channel).await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn scrape_finds_live_video() {
+        let live = include_str!("fixtures/live_page.html");
+        assert_eq!(
+            ScrapeDetector::parse(live).ok(),
+            Some(Some(String::from("dQw4w9WgXcQ")))
+        );
+
+        let offline = include_str!("fixtures/offline_page.html");
+        assert_eq!(ScrapeDetector::parse(offline).ok(), Some(None));
+
+        // a consent page or new markup is not mistaken for offline
+        assert!(ScrapeDetector::parse("<html><body>Before you continue</body></html>").is_err());
+    }
+
+    #[test]
+    fn broadcast_api_finds_live_video_of_channel() {
+        let active: LiveBroadcastListResponse =
+            serde_json::from_str(include_str!("fixtures/broadcasts_active.json")).expect("fixture");
+        assert_eq!(
+            BroadcastApiDetector::<()>::parse(&active, "UCxxxxxxxxxxxxxxxxxxxxxx"),
+            vec![String::from("dQw4w9WgXcQ")]
+        );
+        assert!(BroadcastApiDetector::<()>::parse(&active, "UCzzzzzzzzzzzzzzzzzzzzzz").is_empty());
+    }
+
+    #[test]
+    fn search_api_finds_live_video() {
+        let live: SearchListResponse =
+            serde_json::from_str(include_str!("fixtures/search_live.json")).expect("fixture");
+        assert_eq!(
+            SearchApiDetector::<()>::parse(&live),
+            vec![String::from("dQw4w9WgXcQ")]
+        );
+
+        let offline: SearchListResponse =
+            serde_json::from_str(include_str!("fixtures/search_offline.json")).expect("fixture");
+        assert!(SearchApiDetector::<()>::parse(&offline).is_empty());
+    }
+
+    struct Fixed {
+        result: Option<&'static [&'static str]>,
+        calls: AtomicUsize,
+    }
+
+    fn fixed(result: Option<&'static [&'static str]>) -> &'static Fixed {
+        Box::leak(Box::new(Fixed {
+            result,
+            calls: AtomicUsize::new(0),
+        }))
+    }
+
+    #[async_trait]
+    impl BroadcastDetector for &'static Fixed {
+        fn name(&self) -> &'static str {
+            "fixed"
+        }
+
+        async fn detect(&self, _: &str) -> Result<Vec<String>, ServerError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            match self.result {
+                Some(ids) => Ok(ids.iter().map(|id| id.to_string()).collect()),
+                None => Err(ServerError::Internal(String::from("quota exceeded"))),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn fallback_skips_failed_detectors() {
+        let failing = fixed(None);
+        let offline = fixed(Some(&[]));
+        let live = fixed(Some(&["abc"]));
+
+        let detector = FallbackDetector::new().then(failing).then(live);
+        assert_eq!(
+            detector.detect("channel").await.ok(),
+            Some(vec![String::from("abc")])
+        );
+
+        // an offline answer is an answer, the next detector is not asked
+        let detector = FallbackDetector::new().then(offline).then(live);
+        assert_eq!(detector.detect("channel").await.ok(), Some(vec![]));
+        assert_eq!(live.calls.load(Ordering::SeqCst), 1);
+
+        let detector = FallbackDetector::new().then(failing);
+        assert!(detector.detect("channel").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn throttled_asks_once_per_interval() {
+        let live = fixed(Some(&["abc"]));
+        let detector = ThrottledDetector::new(live, Duration::from_secs(3600));
+
+        assert!(detector.detect("channel").await.is_ok());
+        assert!(detector.detect("channel").await.is_err());
+        // each channel has its own interval
+        assert!(detector.detect("other").await.is_ok());
+        assert_eq!(live.calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn owner_picks_detector_by_channel() {
+        let own = fixed(Some(&["own"]));
+        let other = fixed(Some(&["other"]));
+        let detector = OwnerDetector::new(vec![String::from("mine")], own, other);
+
+        assert_eq!(
+            detector.detect("mine").await.ok(),
+            Some(vec![String::from("own")])
+        );
+        assert_eq!(
+            detector.detect("collab").await.ok(),
+            Some(vec![String::from("other")])
+        );
+    }
+}
@@ -0,0 +1,412 @@
+use crate::error::ServerError;
+use chrono::{DateTime, Utc};
+use google_youtube3::api::{LiveChatMessage, LiveChatMessageSnippet};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChatMessage {
+    pub id: String,
+    pub chat_id: String,
+    pub published_at: DateTime<Utc>,
+    pub author: Author,
+    pub event: ChatEvent,
+}
+
+/**
+ * the channel that sent a message; for bans and deletions, the moderator.
+ */
+#[derive(Debug, Clone, PartialEq)]
+pub struct Author {
+    pub id: String,
+    pub name: String,
+    pub is_sponsor: bool,
+    pub is_moderator: bool,
+    pub is_owner: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChatEvent {
+    Text {
+        text: String,
+    },
+    SuperChat {
+        amount_micros: u64,
+        currency: String,
+        comment: Option<String>,
+    },
+    SuperSticker {
+        amount_micros: u64,
+        currency: String,
+    },
+    NewMember {
+        level: Option<String>,
+        is_upgrade: bool,
+    },
+    MemberMilestone {
+        months: u32,
+        level: Option<String>,
+        comment: Option<String>,
+    },
+    GiftPurchase {
+        count: i64,
+        level: Option<String>,
+    },
+    GiftRedemption {
+        gifter: Option<String>,
+        level: Option<String>,
+    },
+    MessageDeleted {
+        message_id: String,
+    },
+    UserBanned {
+        user_id: String,
+        user_name: String,
+        /**
+         * `None` for a permanent ban
+         */
+        duration_seconds: Option<u64>,
+    },
+    ChatEnded,
+    Other {
+        type_: String,
+    },
+}
+
+impl TryFrom<&LiveChatMessage> for ChatMessage {
+    type Error = ServerError;
+
+    fn try_from(chat: &LiveChatMessage) -> Result<Self, Self::Error> {
+        let snippet = chat.snippet.as_ref().ok_or_else(|| missing("snippet"))?;
+        let author = chat.author_details.as_ref();
+
+        Ok(Self {
+            id: chat.id.clone().ok_or_else(|| missing("id"))?,
+            chat_id: snippet
+                .live_chat_id
+                .clone()
+                .ok_or_else(|| missing("liveChatId"))?,
+            published_at: snippet.published_at.ok_or_else(|| missing("publishedAt"))?,
+            author: Author {
+                id: author
+                    .and_then(|author| author.channel_id.clone())
+                    .or_else(|| snippet.author_channel_id.clone())
+                    .ok_or_else(|| missing("authorChannelId"))?,
+                name: author
+                    .and_then(|author| author.display_name.clone())
+                    .unwrap_or_default(),
+                is_sponsor: author
+                    .and_then(|author| author.is_chat_sponsor)
+                    .unwrap_or(false),
+                is_moderator: author
+                    .and_then(|author| author.is_chat_moderator)
+                    .unwrap_or(false),
+                is_owner: author
+                    .and_then(|author| author.is_chat_owner)
+                    .unwrap_or(false),
+            },
+            event: ChatEvent::try_from(snippet)?,
+        })
+    }
+}
+
+impl TryFrom<&LiveChatMessageSnippet> for ChatEvent {
+    type Error = ServerError;
+
+    fn try_from(snippet: &LiveChatMessageSnippet) -> Result<Self, Self::Error> {
+        let type_ = snippet.type_.as_deref().ok_or_else(|| missing("type"))?;
+
+        let event = match type_ {
+            "textMessageEvent" => ChatEvent::Text {
+                text: snippet
+                    .text_message_details
+                    .as_ref()
+                    .and_then(|details| details.message_text.clone())
+                    .or_else(|| snippet.display_message.clone())
+                    .ok_or_else(|| missing("textMessageDetails"))?,
+            },
+            "superChatEvent" => {
+                let details = snippet
+                    .super_chat_details
+                    .as_ref()
+                    .ok_or_else(|| missing("superChatDetails"))?;
+                ChatEvent::SuperChat {
+                    amount_micros: details
+                        .amount_micros
+                        .ok_or_else(|| missing("amountMicros"))?,
+                    currency: details
+                        .currency
+                        .clone()
+                        .ok_or_else(|| missing("currency"))?,
+                    comment: details.user_comment.clone(),
+                }
+            }
+            "superStickerEvent" => {
+                let details = snippet
+                    .super_sticker_details
+                    .as_ref()
+                    .ok_or_else(|| missing("superStickerDetails"))?;
+                ChatEvent::SuperSticker {
+                    amount_micros: details
+                        .amount_micros
+                        .ok_or_else(|| missing("amountMicros"))?,
+                    currency: details
+                        .currency
+                        .clone()
+                        .ok_or_else(|| missing("currency"))?,
+                }
+            }
+            "newSponsorEvent" => {
+                let details = snippet.new_sponsor_details.as_ref();
+                ChatEvent::NewMember {
+                    level: details.and_then(|details| details.member_level_name.clone()),
+                    is_upgrade: details
+                        .and_then(|details| details.is_upgrade)
+                        .unwrap_or(false),
+                }
+            }
+            "memberMilestoneChatEvent" => {
+                let details = snippet
+                    .member_milestone_chat_details
+                    .as_ref()
+                    .ok_or_else(|| missing("memberMilestoneChatDetails"))?;
+                ChatEvent::MemberMilestone {
+                    months: details.member_month.unwrap_or_default(),
+                    level: details.member_level_name.clone(),
+                    comment: details.user_comment.clone(),
+                }
+            }
+            "membershipGiftingEvent" => {
+                let details = snippet
+                    .membership_gifting_details
+                    .as_ref()
+                    .ok_or_else(|| missing("membershipGiftingDetails"))?;
+                ChatEvent::GiftPurchase {
+                    count: details
+                        .gift_memberships_count
+                        .ok_or_else(|| missing("giftMembershipsCount"))?
+                        .into(),
+                    level: details.gift_memberships_level_name.clone(),
+                }
+            }
+            "giftMembershipReceivedEvent" => {
+                let details = snippet.gift_membership_received_details.as_ref();
+                ChatEvent::GiftRedemption {
+                    gifter: details.and_then(|details| details.gifter_channel_id.clone()),
+                    level: details.and_then(|details| details.member_level_name.clone()),
+                }
+            }
+            "messageDeletedEvent" => ChatEvent::MessageDeleted {
+                message_id: snippet
+                    .message_deleted_details
+                    .as_ref()
+                    .and_then(|details| details.deleted_message_id.clone())
+                    .ok_or_else(|| missing("deletedMessageId"))?,
+            },
+            "userBannedEvent" => {
+                let details = snippet
+                    .user_banned_details
+                    .as_ref()
+                    .ok_or_else(|| missing("userBannedDetails"))?;
+                let user = details
+                    .banned_user_details
+                    .as_ref()
+                    .ok_or_else(|| missing("bannedUserDetails"))?;
+                ChatEvent::UserBanned {
+                    user_id: user
+                        .channel_id
+                        .clone()
+                        .ok_or_else(|| missing("channelId"))?,
+                    user_name: user.display_name.clone().unwrap_or_default(),
+                    duration_seconds: match details.ban_type.as_deref() {
+                        Some("temporary") => details.ban_duration_seconds,
+                        _ => None,
+                    },
+                }
+            }
+            "chatEndedEvent" => ChatEvent::ChatEnded,
+            other => ChatEvent::Other {
+                type_: other.to_string(),
+            },
+        };
+
+        Ok(event)
+    }
+}
+
+fn missing(field: &str) -> ServerError {
+    ServerError::Internal(format!("chat message without {}", field))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use google_youtube3::api::{
+        ChannelProfileDetails, LiveChatMembershipGiftingDetails, LiveChatMessageAuthorDetails,
+        LiveChatMessageDeletedDetails, LiveChatSuperChatDetails, LiveChatTextMessageDetails,
+        LiveChatUserBannedMessageDetails,
+    };
+
+    fn sample_message() -> LiveChatMessage {
+        LiveChatMessage {
+            id: Some("message-id".to_string()),
+            snippet: Some(LiveChatMessageSnippet {
+                type_: Some("textMessageEvent".to_string()),
+                published_at: Some(
+                    Utc.timestamp_opt(1_704_164_245, 0)
+                        .single()
+                        .expect("valid timestamp"),
+                ),
+                live_chat_id: Some("chat-id".to_string()),
+                display_message: Some("/purchase booster 3 Hello".to_string()),
+                text_message_details: Some(LiveChatTextMessageDetails {
+                    message_text: Some("/purchase booster 3 Hello".to_string()),
+                }),
+                ..Default::default()
+            }),
+            author_details: Some(LiveChatMessageAuthorDetails {
+                channel_id: Some("channel-id".to_string()),
+                display_name: Some("Display".to_string()),
+                is_chat_sponsor: Some(true),
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    fn with_snippet(edit: impl FnOnce(&mut LiveChatMessageSnippet)) -> LiveChatMessage {
+        let mut msg = sample_message();
+        edit(msg.snippet.as_mut().expect("snippet"));
+        msg
+    }
+
+    #[test]
+    fn converts_text_message() -> Result<(), ServerError> {
+        let message = ChatMessage::try_from(&sample_message())?;
+        assert_eq!(message.id, "message-id");
+        assert_eq!(message.chat_id, "chat-id");
+        assert_eq!(
+            message.published_at,
+            Utc.timestamp_opt(1_704_164_245, 0)
+                .single()
+                .expect("valid timestamp")
+        );
+        assert_eq!(message.author.id, "channel-id");
+        assert_eq!(message.author.name, "Display");
+        assert!(message.author.is_sponsor);
+        assert!(!message.author.is_moderator);
+        assert_eq!(
+            message.event,
+            ChatEvent::Text {
+                text: "/purchase booster 3 Hello".to_string()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn converts_paid_and_membership_events() -> Result<(), ServerError> {
+        let super_chat = with_snippet(|snippet| {
+            snippet.type_ = Some("superChatEvent".to_string());
+            snippet.super_chat_details = Some(LiveChatSuperChatDetails {
+                amount_micros: Some(5_000_000),
+                currency: Some("USD".to_string()),
+                ..Default::default()
+            });
+        });
+        assert_eq!(
+            ChatMessage::try_from(&super_chat)?.event,
+            ChatEvent::SuperChat {
+                amount_micros: 5_000_000,
+                currency: "USD".to_string(),
+                comment: None,
+            }
+        );
+
+        let gift = with_snippet(|snippet| {
+            snippet.type_ = Some("membershipGiftingEvent".to_string());
+            snippet.membership_gifting_details = Some(LiveChatMembershipGiftingDetails {
+                gift_memberships_count: Some(5),
+                ..Default::default()
+            });
+        });
+        assert_eq!(
+            ChatMessage::try_from(&gift)?.event,
+            ChatEvent::GiftPurchase {
+                count: 5,
+                level: None
+            }
+        );
+
+        let sponsor = with_snippet(|snippet| snippet.type_ = Some("newSponsorEvent".to_string()));
+        assert_eq!(
+            ChatMessage::try_from(&sponsor)?.event,
+            ChatEvent::NewMember {
+                level: None,
+                is_upgrade: false
+            }
+        );
+
+        // a paid event without its details is malformed
+        let broken = with_snippet(|snippet| snippet.type_ = Some("superChatEvent".to_string()));
+        assert!(ChatMessage::try_from(&broken).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn converts_moderation_events() -> Result<(), ServerError> {
+        let deleted = with_snippet(|snippet| {
+            snippet.type_ = Some("messageDeletedEvent".to_string());
+            snippet.message_deleted_details = Some(LiveChatMessageDeletedDetails {
+                deleted_message_id: Some("spam".to_string()),
+            });
+        });
+        assert_eq!(
+            ChatMessage::try_from(&deleted)?.event,
+            ChatEvent::MessageDeleted {
+                message_id: "spam".to_string()
+            }
+        );
+
+        let banned = with_snippet(|snippet| {
+            snippet.type_ = Some("userBannedEvent".to_string());
+            snippet.user_banned_details = Some(LiveChatUserBannedMessageDetails {
+                ban_type: Some("temporary".to_string()),
+                ban_duration_seconds: Some(300),
+                banned_user_details: Some(ChannelProfileDetails {
+                    channel_id: Some("troll".to_string()),
+                    display_name: Some("Troll".to_string()),
+                    ..Default::default()
+                }),
+            });
+        });
+        assert_eq!(
+            ChatMessage::try_from(&banned)?.event,
+            ChatEvent::UserBanned {
+                user_id: "troll".to_string(),
+                user_name: "Troll".to_string(),
+                duration_seconds: Some(300),
+            }
+        );
+
+        let poll = with_snippet(|snippet| snippet.type_ = Some("pollEvent".to_string()));
+        assert_eq!(
+            ChatMessage::try_from(&poll)?.event,
+            ChatEvent::Other {
+                type_: "pollEvent".to_string()
+            }
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn missing_fields_are_errors() {
+        assert!(ChatMessage::try_from(&LiveChatMessage::default()).is_err());
+
+        let mut anonymous = sample_message();
+        anonymous.author_details = None;
+        assert!(ChatMessage::try_from(&anonymous).is_err());
+    }
+}
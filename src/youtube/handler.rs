@@ -0,0 +1,119 @@
+use super::{command::LiveChatApi, event::ChatMessage};
+use crate::{
+    database::{self, config::Config},
+    error::ServerError,
+};
+use async_trait::async_trait;
+use google_youtube3::api::LiveChatMessage;
+use std::collections::HashMap;
+
+/**
+ * a behaviour run on every live chat message, switched on and off in
+ * `Config::ChatHandler`.
+ */
+#[async_trait]
+pub trait ChatHandler: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn enabled_by_default(&self) -> bool {
+        true
+    }
+
+    async fn run(&self, api: &dyn LiveChatApi, message: &ChatMessage) -> Result<(), ServerError>;
+}
+
+/**
+ * the handlers a message goes through in order; a failing one does not stop
+ * the rest.
+ */
+pub struct ChatPipeline {
+    handlers: Vec<Box<dyn ChatHandler>>,
+}
+
+impl ChatPipeline {
+    pub fn new() -> Self {
+        Self {
+            handlers: Vec::new(),
+        }
+    }
+
+    pub fn register(mut self, handler: impl ChatHandler + 'static) -> Self {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    pub async fn run(&self, api: &dyn LiveChatApi, chat: &LiveChatMessage) {
+        let message = match ChatMessage::try_from(chat) {
+            Ok(message) => message,
+            Err(err) => {
+                log::warn!("skip malformed chat message: {:?} for {:?}", err, chat);
+                return;
+            }
+        };
+
+        // 設定可隨時從網頁修改，每則訊息重新讀取
+        let flags = match load_flags() {
+            Ok(flags) => flags,
+            Err(err) => {
+                log::error!("fail to load chat handler config: {:?}", err);
+                HashMap::new()
+            }
+        };
+
+        for handler in &self.handlers {
+            if !is_enabled(&flags, handler.name(), handler.enabled_by_default()) {
+                continue;
+            }
+            if let Err(err) = handler.run(api, &message).await {
+                log::error!(
+                    "chat handler {} failed: {:?} for {:?}",
+                    handler.name(),
+                    err,
+                    chat
+                );
+            }
+        }
+    }
+}
+
+pub fn parse_flags(text: &str) -> Option<HashMap<String, bool>> {
+    serde_json::from_str(text).ok()
+}
+
+fn load_flags() -> Result<HashMap<String, bool>, ServerError> {
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let text = Config::ChatHandler.get(&transaction)?;
+    transaction.commit()?;
+
+    let flags = match text {
+        Some(text) => parse_flags(&text).unwrap_or_else(|| {
+            log::warn!("invalid chat handler config, use default: {}", text);
+            HashMap::new()
+        }),
+        None => HashMap::new(),
+    };
+
+    Ok(flags)
+}
+
+fn is_enabled(flags: &HashMap<String, bool>, name: &str, default: bool) -> bool {
+    flags.get(name).copied().unwrap_or(default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flags_override_default() {
+        let flags = parse_flags(r#"{"coin": false, "logging": true}"#).expect("flags");
+        assert!(!is_enabled(&flags, "coin", true));
+        assert!(is_enabled(&flags, "logging", false));
+        assert!(is_enabled(&flags, "command", true));
+        assert!(!is_enabled(&flags, "stats", false));
+
+        assert!(parse_flags(r#"{"coin": 1}"#).is_none());
+        assert!(parse_flags("[]").is_none());
+    }
+}
@@ -1,13 +1,27 @@
 mod chat;
+pub(crate) mod client;
+pub(crate) mod command;
+mod detect;
+mod event;
+pub(crate) mod handler;
+pub mod replay;
+mod session;
+mod supervisor;
 mod video;
 
 use crate::{
+    coin::command::CoinCommandManager,
     config::CONFIG,
     database::{self, config::Config},
     discord,
     error::ServerError,
 };
 use actix_web::cookie::time::{UtcOffset, format_description};
+use client::{Client, DAILY_QUOTA, Method};
+use detect::{
+    BroadcastApiDetector, FallbackDetector, OwnerDetector, ScrapeDetector, SearchApiDetector,
+    ThrottledDetector,
+};
 use google_youtube3::{
     YouTube,
     api::Video,
@@ -18,11 +32,13 @@ use google_youtube3::{
         authenticator_delegate::{DeviceAuthResponse, DeviceFlowDelegate},
     },
 };
-use regex::Regex;
 use serenity::all::CreateMessage;
 use std::{fs::OpenOptions, future::Future, pin::Pin, time::Duration};
+use supervisor::Supervisor;
 use video as h;
 
+const SEARCH_INTERVAL: Duration = Duration::from_secs(20 * 60);
+
 pub struct FlowDelegateForDiscord(pub discord::Receiver);
 impl DeviceFlowDelegate for FlowDelegateForDiscord {
     fn present_user_code<'a>(
@@ -33,15 +49,15 @@ impl DeviceFlowDelegate for FlowDelegateForDiscord {
     }
 }
 
-fn fetch_youtube_channel_id() -> Result<String, ServerError> {
+fn fetch_youtube_channel_ids() -> Result<Vec<String>, ServerError> {
     let mut connection = database::get_connection()?;
     let transaction = connection.transaction()?;
-    let id = Config::YoutubeChannelId
+    let ids = Config::YoutubeChannelId
         .get(&transaction)
         .map_err(|_| ServerError::Internal("Fetch ID failed".into()))?
-        .expect("No ID found");
+        .ok_or_else(|| ServerError::Internal("No ID found".into()))?;
     transaction.commit()?;
-    Ok(id)
+    Ok(supervisor::channel_ids(&ids))
 }
 
 pub async fn present_user_code(device_auth_resp: &DeviceAuthResponse, recv: discord::Receiver) {
@@ -117,29 +133,63 @@ pub async fn run() -> Result<(), ServerError> {
                 .enable_all_versions()
                 .build(),
         );
-    let api = YouTube::new(client, auth);
+    let api = Client::new(YouTube::new(client, auth), DAILY_QUOTA);
     // necessary to trigger auth flow
-    let _ = api
-        .channels()
-        .list(&vec!["snippet".into()])
-        .mine(true)
-        .doit()
+    let owned = api
+        .call(Method::ChannelsList, || {
+            api.hub()
+                .channels()
+                .list(&vec!["snippet".into()])
+                .mine(true)
+                .doit()
+        })
         .await?;
+    let owned: Vec<String> = owned
+        .items
+        .unwrap_or_default()
+        .into_iter()
+        .filter_map(|channel| channel.id)
+        .collect();
 
     println!("YouTube authentication complete");
 
-    // 用一個 block 把資料庫操作包起來
-    let channel_id = fetch_youtube_channel_id()?;
+    // 先用 API 查詢，失敗時（如配額用盡）才改抓網頁；
+    // search 一次 100 單位，每個頻道限 SEARCH_INTERVAL 一次，其間改抓網頁
+    let detector = OwnerDetector::new(
+        owned.clone(),
+        FallbackDetector::new()
+            .then(BroadcastApiDetector::new(api.clone()))
+            .then(ScrapeDetector),
+        FallbackDetector::new()
+            .then(ThrottledDetector::new(
+                SearchApiDetector::new(api.clone()),
+                SEARCH_INTERVAL,
+            ))
+            .then(ScrapeDetector),
+    );
+    let watcher = api.clone();
+    let mut supervisor = Supervisor::new(detector, move |id| {
+        let api = watcher.clone();
+        let owned = owned.clone();
+        tokio::spawn(async move {
+            if let Err(err) = watch(&api, &id, &owned).await {
+                log::error!("chat task of broadcast {} failed: {:?}", id, err);
+            }
+        })
+    });
 
     loop {
         tokio::select! {
             res = async {
-                // 注意：這裡直接使用上面拿到的 channel_id (String)
-                if let Some(id) = get_broadcast_id(&api, channel_id.clone()).await? {
-                    if let Some(video) = video_from_id(&api, &id).await? {
-                        h::chat::handle(&api, &video).await?;
-                    }
+                // 每輪重新讀取，新增頻道不必重啟
+                let channels = fetch_youtube_channel_ids()?;
+                // 配額用盡時不再開新的聊天室，等配額重置
+                if api.is_exhausted() {
+                    log::debug!("youtube quota exhausted, polling paused");
+                } else {
+                    supervisor.poll(&channels).await;
                 }
+                log::debug!("watching broadcasts {:?}", supervisor.active());
                 tokio::time::sleep(Duration::from_secs(60)).await;
                 Ok::<(), ServerError>(())
             } => res?,
@@ -152,41 +202,31 @@ pub async fn run() -> Result<(), ServerError> {
 }
 
 /**
- * return None if no broadcast available
+ * read the chat of a broadcast until it goes offline.
  */
-async fn get_broadcast_id<C>(
-    _: &YouTube<C>,
-    channel: String,
-) -> Result<Option<String>, ServerError> {
-    let response =
-        reqwest::get(format!("https://www.youtube.com/channel/{}/live", channel)).await?;
-    let text = response.text().await?;
-
-    if text.matches("\"isLive\":true").count() >= 2 {
-        let re = Regex::new(r#"video_id=([_0-9a-zA-Z]*)"}"#).unwrap();
-        if let Some(captures) = re.captures(&text) {
-            let id = &captures[1];
-            return Ok(Some(id.into()));
-        }
-        log::error!("broadcast is ready but no id found");
+async fn watch<C>(api: &Client<C>, id: &str, owned: &[String]) -> Result<(), ServerError>
+where
+    C: Connector,
+{
+    if let Some(video) = video_from_id(api, id).await? {
+        let pipeline = chat::pipeline(CoinCommandManager::new());
+        h::chat::handle(api, &pipeline, &video, owned).await?;
     }
 
-    Ok(None)
+    Ok(())
 }
 
-async fn video_from_id<C>(api: &YouTube<C>, id: &String) -> Result<Option<Video>, ServerError>
+async fn video_from_id<C>(api: &Client<C>, id: &str) -> Result<Option<Video>, ServerError>
 where
     C: Connector,
 {
-    let part = vec!["liveStreamingDetails".into()];
-    let (_, res) = api.videos().list(&part).add_id(id).doit().await?;
-
-    if let Some(videos) = res.items {
-        if let Some(video) = videos.into_iter().next() {
-            // select the first broadcast available
-            return Ok(Some(video));
-        }
-    }
+    let part = vec!["snippet".into(), "liveStreamingDetails".into()];
+    let res = api
+        .call(Method::VideosList, || {
+            api.hub().videos().list(&part).add_id(id).doit()
+        })
+        .await?;
 
-    return Ok(None);
+    // select the first broadcast available
+    Ok(res.items.and_then(|videos| videos.into_iter().next()))
 }
@@ -0,0 +1,196 @@
+use super::{chat, command::LiveChatApi, video::chat::process};
+use crate::{
+    coin::command::CoinCommandManager,
+    database::{self, user::User},
+    error::ServerError,
+};
+use async_trait::async_trait;
+use google_youtube3::api::LiveChatMessageListResponse;
+use std::{collections::HashMap, fs, path::Path};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceChange {
+    pub youtube: String,
+    pub display: String,
+    pub before: i64,
+    pub after: i64,
+}
+
+impl BalanceChange {
+    pub fn delta(&self) -> i64 {
+        self.after - self.before
+    }
+}
+
+struct Transcript;
+
+#[async_trait]
+impl LiveChatApi for Transcript {
+    async fn send(&self, chat_id: &str, text: &str) -> Result<(), ServerError> {
+        println!("[reply {}] {}", chat_id, text);
+        Ok(())
+    }
+}
+
+/**
+ * feed a JSONL recording, one `liveChatMessages.list` response per line,
+ * through the chat pipeline and return the balances that changed. Orders are
+ * not announced on Discord.
+ */
+pub async fn run(recording: impl AsRef<Path>) -> Result<Vec<BalanceChange>, ServerError> {
+    let pages = parse_pages(&fs::read_to_string(recording)?)?;
+
+    let before = balances()?;
+    // 重播不在 Discord 公告訂單
+    let pipeline = chat::pipeline(CoinCommandManager::offline());
+    for page in pages {
+        for chat in page.items.unwrap_or_default() {
+            // 錄製檔可能包含多個聊天室，依訊息本身的聊天室記錄
+            let chat_id = chat
+                .snippet
+                .as_ref()
+                .and_then(|snippet| snippet.live_chat_id.clone())
+                .unwrap_or_default();
+            process(&Transcript, &pipeline, &chat_id, vec![chat]).await?;
+        }
+    }
+    let after = balances()?;
+
+    Ok(balance_diff(&before, &after))
+}
+
+pub fn parse_pages(text: &str) -> Result<Vec<LiveChatMessageListResponse>, ServerError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line).map_err(|err| {
+                ServerError::Internal(format!("recording line {}: {}", index + 1, err))
+            })
+        })
+        .collect()
+}
+
+fn balances() -> Result<Vec<User>, ServerError> {
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let users = User::all(&transaction)?;
+    transaction.commit()?;
+
+    Ok(users)
+}
+
+pub fn balance_diff(before: &[User], after: &[User]) -> Vec<BalanceChange> {
+    let before: HashMap<i64, i64> = before.iter().map(|user| (user.id, user.coin)).collect();
+
+    let mut changes: Vec<BalanceChange> = after
+        .iter()
+        .map(|user| BalanceChange {
+            youtube: user.youtube.clone(),
+            display: user.display.clone(),
+            before: before.get(&user.id).copied().unwrap_or(0),
+            after: user.coin,
+        })
+        .filter(|change| change.delta() != 0)
+        .collect();
+    changes.sort_by(|a, b| {
+        b.delta()
+            .cmp(&a.delta())
+            .then_with(|| a.youtube.cmp(&b.youtube))
+    });
+
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::order::Order;
+    use chrono::Utc;
+
+    fn user(id: i64, youtube: &str, coin: i64) -> User {
+        User {
+            id,
+            youtube: youtube.to_string(),
+            discord: None,
+            coin,
+            display: youtube.to_uppercase(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn parse_pages_reads_recording() -> Result<(), ServerError> {
+        let pages = parse_pages(include_str!("fixtures/chat_replay.jsonl"))?;
+        assert_eq!(pages.len(), 2);
+        assert_eq!(pages[0].items.as_ref().map(Vec::len), Some(2));
+        assert!(pages[1].offline_at.is_some());
+
+        let err = parse_pages("{}\n\nnot json").expect_err("malformed");
+        assert!(err.to_string().contains("line 3"));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replays_recording_into_scratch_database() -> Result<(), ServerError> {
+        let _turn = database::scratch().await;
+
+        let recording = "src/youtube/fixtures/chat_replay.jsonl";
+        let changes = run(recording).await?;
+        // msg-1 earned the viewer coins until its deletion took them back,
+        // the fan spent 50 of the Super Chat coins on a booster
+        assert_eq!(
+            changes,
+            vec![BalanceChange {
+                youtube: String::from("UCfan"),
+                display: String::from("Fan"),
+                before: 0,
+                after: 45,
+            }]
+        );
+        let after = balances()?;
+        let viewer = after
+            .iter()
+            .find(|user| user.youtube == "UCviewer")
+            .expect("viewer");
+        assert_eq!(viewer.coin, 0);
+        let fan = after
+            .iter()
+            .find(|user| user.youtube == "UCfan")
+            .expect("fan");
+
+        // the order is placed even though Discord is not configured
+        let orders: Vec<_> = {
+            let mut connection = database::get_connection()?;
+            let transaction = connection.transaction()?;
+            Order::all(&transaction)?
+        }
+        .into_iter()
+        .filter(|order| order.buyer == fan.id)
+        .collect();
+        assert_eq!(orders.len(), 1);
+        assert_eq!(orders[0].product, "booster");
+        assert_eq!((orders[0].amount, orders[0].cost), (2.0, 50));
+        assert_eq!(orders[0].content, "Hello");
+        assert_eq!(orders[0].message, None);
+
+        // every message is already claimed, replaying again changes nothing
+        assert!(run(recording).await?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn balance_diff_lists_changed_users() {
+        let before = vec![user(1, "a", 10), user(2, "b", 5)];
+        let after = vec![user(1, "a", 4), user(2, "b", 5), user(3, "c", 20)];
+
+        let changes = balance_diff(&before, &after);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].youtube, "c");
+        assert_eq!((changes[0].before, changes[0].after), (0, 20));
+        assert_eq!(changes[1].youtube, "a");
+        assert_eq!(changes[1].delta(), -6);
+    }
+}
@@ -0,0 +1,297 @@
+use crate::{
+    database::{self, chat::Processed, config::Config, stream::StreamSession, video},
+    discord,
+    error::ServerError,
+};
+use chrono::{DateTime, FixedOffset, TimeDelta, Utc};
+use google_youtube3::api::Video;
+use serenity::all::CreateMessage;
+
+const PROCESSED_RETENTION: TimeDelta = TimeDelta::days(7);
+
+/**
+ * the session of the broadcast, created the first time its chat is read.
+ */
+pub fn open(video: &Video, chat_id: &str) -> Result<StreamSession, ServerError> {
+    let broadcast = video.id.clone().unwrap_or_default();
+
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let session = match StreamSession::by_broadcast(&broadcast, &transaction)? {
+        Some(mut session) => {
+            observe(&mut session, video);
+            session.update(&transaction)?;
+            session
+        }
+        None => {
+            let mut session = StreamSession {
+                id: 0,
+                broadcast,
+                chat_id: chat_id.to_string(),
+                channel: String::new(),
+                title: String::new(),
+                start_at: None,
+                end_at: None,
+                peak_viewers: 0,
+                message_count: 0,
+                chatter_count: 0,
+                coin_issued: 0,
+                updated_at: Utc::now(),
+            };
+            observe(&mut session, video);
+            session.insert(&transaction)?;
+            session
+        }
+    };
+    transaction.commit()?;
+
+    Ok(session)
+}
+
+pub fn prune(now: DateTime<Utc>) -> Result<usize, ServerError> {
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    let pruned = Processed::prune(now - PROCESSED_RETENTION, &transaction)?;
+    transaction.commit()?;
+
+    Ok(pruned)
+}
+
+pub fn save(session: &mut StreamSession, now: DateTime<Utc>) -> Result<(), ServerError> {
+    let mut connection = database::get_connection()?;
+    let transaction = connection.transaction()?;
+    session.refresh_stats(&transaction)?;
+    session.updated_at = now;
+    session.update(&transaction)?;
+    transaction.commit()?;
+
+    Ok(())
+}
+
+/**
+ * the highest viewer count seen is kept.
+ */
+pub fn observe(session: &mut StreamSession, video: &Video) {
+    if let Some(snippet) = video.snippet.as_ref() {
+        if let Some(title) = snippet.title.as_ref() {
+            session.title = title.clone();
+        }
+        if let Some(channel) = snippet.channel_id.as_ref() {
+            session.channel = channel.clone();
+        }
+    }
+
+    if let Some(details) = video.live_streaming_details.as_ref() {
+        session.start_at = details.actual_start_time.or(session.start_at);
+        session.end_at = details.actual_end_time.or(session.end_at);
+        if let Some(viewers) = details.concurrent_viewers {
+            session.peak_viewers = session.peak_viewers.max(viewers as i64);
+        }
+    }
+}
+
+/**
+ * add a finished stream of an `owned` channel to the video list and ask the
+ * admins to review it.
+ */
+pub async fn archive(session: &StreamSession, owned: &[String]) -> Result<(), ServerError> {
+    if !owned.contains(&session.channel) {
+        log::info!(
+            "broadcast {} of {} is not ours, not archived",
+            session.broadcast,
+            session.channel
+        );
+        return Ok(());
+    }
+
+    let (video, channel) = {
+        let mut connection = database::get_connection()?;
+        let transaction = connection.transaction()?;
+
+        if video::Video::by_link(&session.broadcast, &transaction)?.is_some() {
+            return Ok(());
+        }
+
+        let tags = Config::VideoTag
+            .get(&transaction)?
+            .map(|text| parse_tags(&text))
+            .unwrap_or_default();
+        let Some(mut video) = vod(session, tags) else {
+            log::warn!(
+                "broadcast {} has no start time, not archived",
+                session.broadcast
+            );
+            return Ok(());
+        };
+        video.insert(&transaction)?;
+
+        let channel = Config::ChannelStream
+            .get(&transaction)?
+            .and_then(|text| text.parse::<u64>().ok());
+        transaction.commit()?;
+
+        (video, channel)
+    };
+
+    let Some(channel) = channel else {
+        log::warn!(
+            "no stream channel configured, video {} added silently",
+            video.link
+        );
+        return Ok(());
+    };
+    discord::Receiver::ChannelId(channel)
+        .message(CreateMessage::new().content(format!(
+            "直播已結束，已新增影片「{}」（{}，{}）\n標籤：{}\n請確認標籤是否正確：https://www.youtube.com/watch?v={}",
+            video.title,
+            video.date,
+            video.duration,
+            video.tags.join("、"),
+            video.link
+        )))
+        .await?;
+
+    Ok(())
+}
+
+fn vod(session: &StreamSession, tags: Vec<String>) -> Option<video::Video> {
+    let start = session.start_at?;
+    let end = session.end_at.unwrap_or(session.updated_at);
+    let offset = FixedOffset::east_opt(8 * 3600).expect("Can't offset time.");
+
+    Some(video::Video {
+        id: 0,
+        date: start.with_timezone(&offset).date_naive(),
+        link: session.broadcast.clone(),
+        title: session.title.clone(),
+        tags,
+        duration: format_duration(end - start),
+    })
+}
+
+fn format_duration(duration: TimeDelta) -> String {
+    let total_seconds = duration.num_seconds().max(0);
+    let hours = total_seconds / 3600;
+    let minutes = (total_seconds % 3600) / 60;
+    let seconds = total_seconds % 60;
+
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
+
+fn parse_tags(text: &str) -> Vec<String> {
+    text.split([',', '，'])
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use google_youtube3::api::{VideoLiveStreamingDetails, VideoSnippet};
+
+    fn video(viewers: Option<u64>, end: Option<DateTime<Utc>>) -> Video {
+        Video {
+            id: Some(String::from("abc")),
+            snippet: Some(VideoSnippet {
+                title: Some(String::from("雜談")),
+                channel_id: Some(String::from("UCxxxx")),
+                ..Default::default()
+            }),
+            live_streaming_details: Some(VideoLiveStreamingDetails {
+                actual_start_time: Utc.with_ymd_and_hms(2025, 1, 1, 12, 0, 0).single(),
+                actual_end_time: end,
+                concurrent_viewers: viewers,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn observe_keeps_peak_viewers() {
+        let mut session = StreamSession {
+            id: 0,
+            broadcast: String::from("abc"),
+            chat_id: String::from("chat"),
+            channel: String::new(),
+            title: String::new(),
+            start_at: None,
+            end_at: None,
+            peak_viewers: 0,
+            message_count: 0,
+            chatter_count: 0,
+            coin_issued: 0,
+            updated_at: Utc::now(),
+        };
+
+        observe(&mut session, &video(Some(120), None));
+        assert_eq!(session.title, "雜談");
+        assert_eq!(session.channel, "UCxxxx");
+        assert_eq!(session.peak_viewers, 120);
+        assert!(session.start_at.is_some());
+
+        observe(&mut session, &video(Some(80), None));
+        assert_eq!(session.peak_viewers, 120);
+        assert!(session.end_at.is_none());
+
+        // an ended broadcast no longer reports viewers
+        let end = Utc.with_ymd_and_hms(2025, 1, 1, 14, 0, 0).single();
+        observe(&mut session, &video(None, end));
+        assert_eq!(session.peak_viewers, 120);
+        assert_eq!(session.end_at, end);
+    }
+
+    #[test]
+    fn vod_uses_local_date_and_duration() {
+        let start = Utc.with_ymd_and_hms(2025, 1, 1, 17, 0, 0).single();
+        let mut session = StreamSession {
+            id: 0,
+            broadcast: String::from("abc"),
+            chat_id: String::from("chat"),
+            channel: String::from("UCxxxx"),
+            title: String::from("雜談"),
+            start_at: start,
+            end_at: start.map(|start| start + TimeDelta::seconds(2 * 3600 + 5 * 60 + 7)),
+            peak_viewers: 0,
+            message_count: 0,
+            chatter_count: 0,
+            coin_issued: 0,
+            updated_at: Utc::now(),
+        };
+
+        let video = vod(&session, vec![String::from("雜談")]).expect("video");
+        // 17:00 UTC is already the next day in UTC+8
+        assert_eq!(video.date.to_string(), "2025-01-02");
+        assert_eq!(video.duration, "2:05:07");
+        assert_eq!(video.link, "abc");
+        assert_eq!(video.tags, vec![String::from("雜談")]);
+
+        session.start_at = None;
+        assert!(vod(&session, vec![]).is_none());
+    }
+
+    #[test]
+    fn format_duration_drops_empty_hours() {
+        assert_eq!(format_duration(TimeDelta::seconds(192)), "3:12");
+        assert_eq!(format_duration(TimeDelta::seconds(3600)), "1:00:00");
+    }
+
+    #[test]
+    fn parse_tags_splits_commas() {
+        assert_eq!(
+            parse_tags("雜談, 遊戲，,歌回 "),
+            vec![
+                String::from("雜談"),
+                String::from("遊戲"),
+                String::from("歌回")
+            ]
+        );
+    }
+}
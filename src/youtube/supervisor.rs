@@ -0,0 +1,169 @@
+use super::detect::BroadcastDetector;
+use std::collections::HashMap;
+use tokio::task::JoinHandle;
+
+/**
+ * keep one chat task running per live broadcast. Every channel is asked on
+ * each poll, so a second stream on a live channel is picked up too. Dropping
+ * the supervisor stops every task.
+ */
+pub struct Supervisor {
+    detector: Box<dyn BroadcastDetector>,
+    start: Box<dyn Fn(String) -> JoinHandle<()> + Send + Sync>,
+    tasks: HashMap<String, JoinHandle<()>>,
+}
+
+impl Supervisor {
+    pub fn new(
+        detector: impl BroadcastDetector + 'static,
+        start: impl Fn(String) -> JoinHandle<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            detector: Box::new(detector),
+            start: Box::new(start),
+            tasks: HashMap::new(),
+        }
+    }
+
+    pub async fn poll(&mut self, channels: &[String]) {
+        self.tasks.retain(|id, task| {
+            if task.is_finished() {
+                log::info!("chat task of broadcast {} finished", id);
+            }
+            !task.is_finished()
+        });
+
+        for channel in channels {
+            // 單一頻道查詢失敗不影響其他頻道
+            let ids = match self.detector.detect(channel).await {
+                Ok(ids) => ids,
+                Err(err) => {
+                    log::error!("fail to detect broadcasts of {}: {:?}", channel, err);
+                    continue;
+                }
+            };
+
+            for id in ids {
+                if !self.tasks.contains_key(&id) {
+                    log::info!("start chat task of broadcast {} on {}", id, channel);
+                    let task = (self.start)(id.clone());
+                    self.tasks.insert(id, task);
+                }
+            }
+        }
+    }
+
+    pub fn active(&self) -> Vec<&String> {
+        self.tasks
+            .iter()
+            .filter(|(_, task)| !task.is_finished())
+            .map(|(id, _)| id)
+            .collect()
+    }
+}
+
+impl Drop for Supervisor {
+    fn drop(&mut self) {
+        for task in self.tasks.values() {
+            task.abort();
+        }
+    }
+}
+
+pub fn channel_ids(text: &str) -> Vec<String> {
+    text.split(|c: char| c == ',' || c.is_whitespace())
+        .filter(|id| !id.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::ServerError;
+    use async_trait::async_trait;
+    use std::{
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    struct Fake {
+        live: Arc<Mutex<HashMap<&'static str, Vec<&'static str>>>>,
+    }
+
+    #[async_trait]
+    impl BroadcastDetector for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        async fn detect(&self, channel: &str) -> Result<Vec<String>, ServerError> {
+            match self.live.lock().expect("lock").get(channel) {
+                Some(ids) => Ok(ids.iter().map(|id| id.to_string()).collect()),
+                None => Err(ServerError::Internal(String::from("unknown channel"))),
+            }
+        }
+    }
+
+    #[test]
+    fn parses_channel_ids() {
+        assert_eq!(
+            channel_ids("UCa, UCb\nUCc ,"),
+            vec![
+                String::from("UCa"),
+                String::from("UCb"),
+                String::from("UCc")
+            ]
+        );
+        assert!(channel_ids(" ").is_empty());
+    }
+
+    #[tokio::test]
+    async fn one_task_per_broadcast() {
+        let live = Arc::new(Mutex::new(HashMap::from([
+            ("main", vec!["public"]),
+            ("collab", vec!["collab-stream"]),
+        ])));
+        let started = Arc::new(Mutex::new(Vec::new()));
+
+        let log = started.clone();
+        let mut supervisor = Supervisor::new(Fake { live: live.clone() }, move |id| {
+            log.lock().expect("lock").push(id.clone());
+            tokio::spawn(async move {
+                // the collab stream ends right away
+                if id != "collab-stream" {
+                    tokio::time::sleep(Duration::from_secs(3600)).await;
+                }
+            })
+        });
+        let channels = vec![
+            String::from("main"),
+            String::from("collab"),
+            String::from("broken"),
+        ];
+
+        supervisor.poll(&channels).await;
+        assert_eq!(started.lock().expect("lock").len(), 2);
+
+        // a members-only stream next to the running public one is started,
+        // running broadcasts are not started twice
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        live.lock().expect("lock").insert("collab", vec![]);
+        live.lock()
+            .expect("lock")
+            .insert("main", vec!["public", "members"]);
+        supervisor.poll(&channels).await;
+        assert_eq!(started.lock().expect("lock").len(), 3);
+
+        let mut active = supervisor.active();
+        active.sort();
+        assert_eq!(active, vec!["members", "public"]);
+
+        // a finished broadcast that is still live is started again
+        live.lock()
+            .expect("lock")
+            .insert("collab", vec!["collab-stream"]);
+        supervisor.poll(&channels).await;
+        assert_eq!(started.lock().expect("lock").len(), 4);
+    }
+}
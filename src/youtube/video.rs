@@ -1,8 +1,10 @@
+use super::client::{Client, Method};
 use crate::error::ServerError;
-use google_youtube3::YouTube;
 use google_youtube3::{api::Video, common::Connector};
 use std::time::Duration;
 
+const REFRESH_INTERVAL: Duration = Duration::from_secs(300);
+
 fn chat_id(video: &Video) -> Option<&String> {
     if let Some(content) = video.live_streaming_details.as_ref() {
         if let Some(chat_id) = content.active_live_chat_id.as_ref() {
@@ -16,30 +18,69 @@ fn chat_id(video: &Video) -> Option<&String> {
 pub mod chat {
     use google_youtube3::api::LiveChatMessage;
 
-    use super::super::chat as h;
     use super::*;
+    use crate::database::{
+        self,
+        chat::{Archived, Cursor, Processed},
+    };
+    use crate::youtube::{command::LiveChatApi, handler::ChatPipeline, session, video_from_id};
+    use chrono::{DateTime, Utc};
+    use tokio::time::Instant;
 
-    pub async fn handle<C>(api: &YouTube<C>, video: &Video) -> Result<(), ServerError>
+    /**
+     * read the chat until the broadcast goes offline. The page token is
+     * saved after each page, so a restart neither skips nor repeats
+     * messages.
+     */
+    pub async fn handle<C>(
+        api: &Client<C>,
+        pipeline: &ChatPipeline,
+        video: &Video,
+        owned: &[String],
+    ) -> Result<(), ServerError>
     where
         C: Connector,
     {
         if let Some(chat_id) = chat_id(video) {
             let part = vec!["snippet".into(), "authorDetails".into()];
 
-            let mut next_page: Option<String> = None;
+            let mut session = session::open(video, chat_id)?;
+            let mut refreshed = Instant::now();
+
+            let mut next_page = load_cursor(chat_id)?;
+            let mut resumed = next_page.is_some();
             loop {
-                let (_, res) = if let Some(token) = next_page {
-                    api.live_chat_messages()
-                        .list(&chat_id, &part)
-                        .page_token(&token)
-                        .doit()
-                        .await?
-                } else {
-                    api.live_chat_messages()
-                        .list(&chat_id, &part)
-                        .doit()
-                        .await?
+                let request = || {
+                    let request = api.hub().live_chat_messages().list(chat_id, &part);
+                    match next_page.as_ref() {
+                        Some(token) => request.page_token(token),
+                        None => request,
+                    }
+                    .doit()
+                };
+                let res = match api.call(Method::LiveChatMessagesList, request).await {
+                    Ok(res) => res,
+                    // 配額用盡，保留進度，等配額重置後由 supervisor 重新開始
+                    Err(ServerError::Quota(method)) => {
+                        log::warn!(
+                            "quota exhausted at {}, stop reading chat {}",
+                            method,
+                            chat_id
+                        );
+                        session::save(&mut session, Utc::now())?;
+                        return Ok(());
+                    }
+                    // the saved token may have expired, the claimed ids
+                    // still prevent handling a message twice
+                    Err(err) if resumed => {
+                        log::warn!("fail to resume chat {}, restart: {:?}", chat_id, err);
+                        next_page = None;
+                        resumed = false;
+                        continue;
+                    }
+                    Err(err) => return Err(err),
                 };
+                resumed = false;
 
                 // get arguments for next request
                 next_page = res.next_page_token;
@@ -47,18 +88,37 @@ pub mod chat {
                 let polling_ms = res.polling_interval_millis.unwrap_or(0).max(10000) as u64;
 
                 // process messages
-                if let Some(chats) = res.items {
-                    for chat in chats {
-                        if let Err(err) = run_chat(api, &chat).await {
-                            log::error!("{:?} for {:?}", err, chat);
+                process(api, pipeline, chat_id, res.items.unwrap_or_default()).await?;
+
+                if let Some(token) = next_page.as_ref() {
+                    save_cursor(chat_id, token)?;
+                }
+
+                // 觀看人數只能從影片資訊取得，定期重新查詢
+                if refreshed.elapsed() >= REFRESH_INTERVAL || res.offline_at.is_some() {
+                    refreshed = Instant::now();
+                    if let Some(id) = video.id.as_ref() {
+                        match video_from_id(api, id).await {
+                            Ok(Some(latest)) => session::observe(&mut session, &latest),
+                            Ok(None) => (),
+                            Err(err) => log::warn!("fail to refresh broadcast {}: {:?}", id, err),
                         }
                     }
                 }
 
                 // no more messages, exit
-                if let Some(_) = res.offline_at {
+                if let Some(offline_at) = res.offline_at {
+                    session.end_at = session.end_at.or(Some(offline_at));
+                    session::save(&mut session, Utc::now())?;
+                    if let Err(err) = session::archive(&session, owned).await {
+                        log::error!("fail to archive broadcast {}: {:?}", session.broadcast, err);
+                    }
+                    if let Err(err) = session::prune(Utc::now()) {
+                        log::error!("fail to prune processed messages: {:?}", err);
+                    }
                     break;
                 }
+                session::save(&mut session, Utc::now())?;
 
                 tokio::time::sleep(Duration::from_millis(polling_ms)).await;
             }
@@ -67,16 +127,114 @@ pub mod chat {
         Ok(())
     }
 
-    async fn run_chat<C>(api: &YouTube<C>, chat: &LiveChatMessage) -> Result<(), ServerError>
-    where
-        C: Connector,
-    {
-        // h::log::run(api, &message).await?;
-        h::coin::run(api, &chat).await?;
-        h::command::run(api, &chat).await?;
+    /**
+     * run the unclaimed messages of a page through the pipeline.
+     */
+    pub(crate) async fn process(
+        api: &dyn LiveChatApi,
+        pipeline: &ChatPipeline,
+        chat_id: &str,
+        chats: Vec<LiveChatMessage>,
+    ) -> Result<(), ServerError> {
+        for chat in chats {
+            if !claim(chat_id, &chat)? {
+                continue;
+            }
+            pipeline.run(api, &chat).await;
+        }
+
+        Ok(())
+    }
+
+    fn load_cursor(chat_id: &str) -> Result<Option<String>, ServerError> {
+        let mut connection = database::get_connection()?;
+        let transaction = connection.transaction()?;
+        let cursor = Cursor::by_chat_id(chat_id, &transaction)?;
+        transaction.commit()?;
+
+        Ok(cursor.map(|cursor| cursor.page_token))
+    }
+
+    fn save_cursor(chat_id: &str, token: &str) -> Result<(), ServerError> {
+        let mut connection = database::get_connection()?;
+        let transaction = connection.transaction()?;
+        Cursor {
+            chat_id: chat_id.to_string(),
+            page_token: token.to_string(),
+            updated_at: Utc::now(),
+        }
+        .save(&transaction)?;
+        transaction.commit()?;
 
         Ok(())
     }
+
+    /**
+     * claimed before handling, so a crash loses the message rather than
+     * applying a purchase twice.
+     */
+    fn claim(chat_id: &str, chat: &LiveChatMessage) -> Result<bool, ServerError> {
+        let Some(message_id) = chat.id.as_ref() else {
+            return Ok(true);
+        };
+        let now = Utc::now();
+
+        let mut connection = database::get_connection()?;
+        let transaction = connection.transaction()?;
+        let claimed = Processed {
+            message_id: message_id.clone(),
+            chat_id: chat_id.to_string(),
+            processed_at: now,
+            author: author_id(chat),
+        }
+        .claim(&transaction)?;
+        if claimed {
+            archived(message_id, chat_id, chat, now).save(&transaction)?;
+        }
+        transaction.commit()?;
+
+        Ok(claimed)
+    }
+
+    fn author_id(chat: &LiveChatMessage) -> Option<String> {
+        chat.author_details
+            .as_ref()
+            .and_then(|author| author.channel_id.clone())
+            .or_else(|| {
+                chat.snippet
+                    .as_ref()
+                    .and_then(|snippet| snippet.author_channel_id.clone())
+            })
+    }
+
+    pub(super) fn archived(
+        message_id: &str,
+        chat_id: &str,
+        chat: &LiveChatMessage,
+        now: DateTime<Utc>,
+    ) -> Archived {
+        let snippet = chat.snippet.as_ref();
+
+        Archived {
+            message_id: message_id.to_string(),
+            chat_id: chat_id.to_string(),
+            author: author_id(chat),
+            author_name: chat
+                .author_details
+                .as_ref()
+                .and_then(|author| author.display_name.clone())
+                .unwrap_or_default(),
+            kind: snippet
+                .and_then(|snippet| snippet.type_.clone())
+                .unwrap_or_default(),
+            text: snippet
+                .and_then(|snippet| snippet.display_message.clone())
+                .unwrap_or_default(),
+            published_at: snippet
+                .and_then(|snippet| snippet.published_at)
+                .unwrap_or(now),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -99,4 +257,33 @@ mod tests {
     fn chat_id_returns_none_when_missing() {
         assert!(chat_id(&Video::default()).is_none());
     }
+
+    #[test]
+    fn archived_keeps_displayed_text() {
+        use google_youtube3::api::LiveChatMessageSnippet;
+        use google_youtube3::api::{LiveChatMessage, LiveChatMessageAuthorDetails};
+
+        let now = chrono::Utc::now();
+        let chat = LiveChatMessage {
+            id: Some(String::from("message")),
+            snippet: Some(LiveChatMessageSnippet {
+                type_: Some(String::from("superChatEvent")),
+                author_channel_id: Some(String::from("UCfan")),
+                display_message: Some(String::from("NT$75.00 from Fan: 加油")),
+                ..Default::default()
+            }),
+            author_details: Some(LiveChatMessageAuthorDetails {
+                display_name: Some(String::from("Fan")),
+                ..Default::default()
+            }),
+            ..Default::default()
+        };
+
+        let archived = chat::archived("message", "chat", &chat, now);
+        assert_eq!(archived.author.as_deref(), Some("UCfan"));
+        assert_eq!(archived.author_name, "Fan");
+        assert_eq!(archived.kind, "superChatEvent");
+        assert_eq!(archived.text, "NT$75.00 from Fan: 加油");
+        assert_eq!(archived.published_at, now);
+    }
 }